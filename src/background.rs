@@ -0,0 +1,116 @@
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use tnewt_board::board::{Algorithm, Board};
+use tnewt_board::search::{SearchLimits, SearchResult};
+use tnewt_board::smp::LazySmp;
+
+/// Searches run on a larger stack than the default, as they recurse up to `MAX_PLY` plies.
+pub const SEARCH_STACK_SIZE: usize = 16 * 1024 * 1024;
+
+/// A search running on its own thread, so that the protocols can keep reading
+/// commands while it runs, which hands the search back once it is joined.
+pub struct BackgroundSearch {
+    /// Hands back the search, its result and whether the result was reported.
+    handle: JoinHandle<(LazySmp, SearchResult, bool)>,
+    stop: Arc<AtomicBool>,
+    /// Cleared to discard the result of the search instead of reporting it.
+    report: Arc<AtomicBool>,
+    infinite: bool,
+}
+
+impl BackgroundSearch {
+    /// Starts searching [`board`] with [`smp`] within [`limits`.] The search calls
+    /// [`on_iteration`] with every completed iteration and the time since it started,
+    /// and [`on_finish`] with its result, unless it has been discarded by then.
+    ///
+    /// An infinite search only finishes once it is stopped, even if it has nothing
+    /// left to search.
+    pub fn start(
+        smp: LazySmp,
+        board: Board,
+        limits: SearchLimits,
+        mut on_iteration: impl FnMut(&SearchResult, Duration, &LazySmp) + Send + 'static,
+        on_finish: impl FnOnce(&SearchResult) + Send + 'static,
+    ) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let report = Arc::new(AtomicBool::new(true));
+        let infinite = limits.infinite;
+        let (thread_stop, thread_report) = (Arc::clone(&stop), Arc::clone(&report));
+
+        let handle = thread::Builder::new()
+            .stack_size(SEARCH_STACK_SIZE)
+            .spawn(move || {
+                let start = Instant::now();
+                let result = smp.search(&board, &limits, &thread_stop, |result| {
+                    if thread_report.load(Ordering::Relaxed) {
+                        on_iteration(result, start.elapsed(), &smp);
+                    }
+                });
+                while infinite && !thread_stop.load(Ordering::Relaxed) {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                let reported = thread_report.load(Ordering::Relaxed);
+                if reported {
+                    on_finish(&result);
+                }
+                (smp, result, reported)
+            })
+            .expect("Failed to spawn search thread");
+
+        BackgroundSearch {
+            handle,
+            stop,
+            report,
+            infinite,
+        }
+    }
+
+    /// Whether the search only finishes once it is stopped.
+    #[must_use]
+    pub fn is_infinite(&self) -> bool {
+        self.infinite
+    }
+
+    /// Tells the search to finish as soon as possible.
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    /// Makes the search finish silently, without reporting any more iterations or its
+    /// result, unless it has reported its result already.
+    pub fn discard(&self) {
+        self.report.store(false, Ordering::Relaxed);
+    }
+
+    /// Waits for the search to finish, and returns it along with its result,
+    /// which is `None` if it was discarded before reporting it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the search thread panicked.
+    #[must_use]
+    pub fn join(self) -> (LazySmp, Option<SearchResult>) {
+        let (smp, result, reported) = self.handle.join().expect("Search thread panicked");
+        (smp, reported.then_some(result))
+    }
+}
+
+/// The position at the start of a game, set up for searching.
+#[must_use]
+pub fn new_board() -> Board {
+    let mut board = Board::new();
+    board.set_algorithm(Algorithm::Unmove);
+    board
+}
+
+/// Writes [`line`] to the output shared with the search thread, and flushes it
+/// so that the other side of the protocol sees it immediately.
+pub fn write_line<W: Write>(output: &Mutex<W>, line: &str) {
+    let mut output = output.lock().expect("Output lock poisoned");
+    // The GUI may have already closed its end, in which case there is no one to tell
+    let _ = writeln!(output, "{line}").and_then(|()| output.flush());
+}
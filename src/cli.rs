@@ -0,0 +1,770 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use tnewt_board::board::{self, Algorithm, Board};
+use tnewt_board::color::Color;
+use tnewt_board::datagen::{self, Format, SampleWriter};
+use tnewt_board::divide;
+use tnewt_board::game::{self, PgnReader};
+use tnewt_board::mov::Move;
+use tnewt_board::packed;
+use tnewt_board::polyglot::{self, BookBuilder, BuildOptions, Keys, ResultWeights};
+use tnewt_board::san;
+use tnewt_board::search::{SearchLimits, Searcher};
+use tnewt_board::solver::{self, Line, Solver, Stipulation};
+use tnewt_board::tablebase::{self, Material, Tablebases, Wdl};
+
+use crate::match_runner::{self, EngineSpec, TimeControl};
+use crate::play;
+use crate::stats::Sprt;
+
+pub const USAGE: &str = "\
+usage: tnewt                    run as a UCI engine, or an XBoard engine if the first command is `xboard`
+       tnewt perft <fen> <depth> [--divide] [--threads N] [--algorithm clone|unmove]
+       tnewt moves <fen>
+       tnewt show <fen>
+       tnewt random-game [--seed N]
+       tnewt bench [depth]
+       tnewt divide-diff <fen> <depth> (--file <listing> | --engine <path> [args...])
+       tnewt play [--color white|black] [--movetime MS] [--depth D] [--fen <fen>]
+       tnewt match --engine <engine> --engine <engine> [--games N] [--concurrency N] [--openings <file>]
+                   [--movetime MS] [--depth D] [--nodes N] [--tc <seconds>[+<increment>]] [--max-moves N]
+                   [--sprt <elo0>,<elo1>] [--alpha A] [--beta B] [--pgn <path>]
+       tnewt book <pgn>... --keys <file> --output <path> [--max-plies N] [--min-count N]
+                  [--weights <win>,<draw>,<loss>]
+       tnewt tablebase generate [<ending>...] [--dir <path>]
+       tnewt tablebase probe <fen> [--dir <path>]
+       tnewt solve <fen> <stipulation>
+       tnewt datagen --output <path> [--games N] [--threads N] [--depth D] [--nodes N] [--random-plies N]
+                     [--max-moves N] [--format binary|text] [--seed N]
+
+<fen> may also be `startpos` for the starting position.
+<engine> is `tnewt` or `uci:<path>`, followed by comma separated `key=value` options, e.g.
+`tnewt,name=no-nmp,null_move_pruning=false` or `uci:./stockfish,Hash=64,depth=8`.
+A divide-diff <listing> may go on with the listings of deeper positions, each after the UCI commands
+`position ...` and `go perft <depth>` that divide it.
+--keys lists the numbers of the Polyglot Random64 table in order.
+<ending> is a material of up to four pieces like `KQKR`, by default each of KQK, KRK, KPK, KBNK and KQKR.
+<stipulation> is `#N` to mate, `h#N` to be helped to be mated or `s#N` to force being mated in N moves.
+datagen writes each quiet position of its self-play games with its score and the game's result.";
+
+/// The positions searched by `bench`, whose total node count changes whenever the
+/// search does.
+const BENCH_FENS: [&str; 6] = [
+    board::STARTING_FEN,
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+];
+const DEFAULT_BENCH_DEPTH: i32 = 9;
+/// Random games are cut off after this many plies.
+const RANDOM_GAME_MOVE_LIMIT: u32 = 1000;
+/// The time each move of a match gets if no other limit is given.
+const DEFAULT_MATCH_MOVE_TIME: Duration = Duration::from_millis(100);
+
+#[derive(Debug)]
+pub enum Error {
+    /// The arguments don't match the usage of any command.
+    Usage(String),
+    Board(board::Error),
+    Divide(divide::Error),
+    Polyglot(polyglot::Error),
+    Tablebase(tablebase::Error),
+    Solver(solver::Error),
+    Packed(packed::Error),
+    Io(std::io::Error),
+    /// An engine playing a match failed.
+    Engine(String),
+}
+
+impl Error {
+    /// The exit code of the process, which is 2 for invalid usage and 1 for any other failure.
+    #[must_use]
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Usage(_) => 2,
+            _ => 1,
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Usage(message) => write!(f, "{message}\n\n{USAGE}"),
+            Error::Board(e) => write!(f, "{e}"),
+            Error::Divide(e) => write!(f, "{e}"),
+            Error::Polyglot(e) => write!(f, "{e}"),
+            Error::Tablebase(e) => write!(f, "{e}"),
+            Error::Solver(e) => write!(f, "{e}"),
+            Error::Packed(e) => write!(f, "{e}"),
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Engine(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl From<board::Error> for Error {
+    fn from(error: board::Error) -> Self {
+        Error::Board(error)
+    }
+}
+
+impl From<divide::Error> for Error {
+    fn from(error: divide::Error) -> Self {
+        Error::Divide(error)
+    }
+}
+
+impl From<polyglot::Error> for Error {
+    fn from(error: polyglot::Error) -> Self {
+        Error::Polyglot(error)
+    }
+}
+
+impl From<tablebase::Error> for Error {
+    fn from(error: tablebase::Error) -> Self {
+        Error::Tablebase(error)
+    }
+}
+
+impl From<solver::Error> for Error {
+    fn from(error: solver::Error) -> Self {
+        Error::Solver(error)
+    }
+}
+
+impl From<packed::Error> for Error {
+    fn from(error: packed::Error) -> Self {
+        Error::Packed(error)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+/// Runs the command given by [`args`,] writing its output to [`out`.]
+/// `show` and `random-game` display their board on stdout regardless.
+///
+/// # Errors
+///
+/// This function will return [`Error::Usage`] if [`args`] are invalid, or the
+/// error the command failed with.
+pub fn run(args: &[String], out: &mut impl Write) -> Result<(), Error> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["perft", fen, depth, options @ ..] => perft(fen, depth, options, out),
+        ["moves", fen] => moves(fen, out),
+        ["show", fen] => show(fen, out),
+        ["random-game", options @ ..] => random_game(options, out),
+        ["bench"] => bench(DEFAULT_BENCH_DEPTH, out),
+        ["bench", depth] => bench(parse(depth, "depth")?, out),
+        ["divide-diff", args @ ..] => divide_diff(args, out),
+        ["play", options @ ..] => play(options, out),
+        ["match", options @ ..] => run_match(options, out),
+        ["book", options @ ..] => build_book(options, out),
+        ["tablebase", "generate", options @ ..] => generate_tablebases(options, out),
+        ["tablebase", "probe", fen, options @ ..] => probe_tablebases(fen, options, out),
+        ["solve", fen, stipulation] => solve(fen, stipulation, out),
+        ["datagen", options @ ..] => generate_data(options, out),
+        ["help" | "--help" | "-h"] => Ok(writeln!(out, "{USAGE}")?),
+        [command, ..] => Err(Error::Usage(format!("invalid arguments to `{command}`"))),
+        [] => Err(Error::Usage("no command given".to_string())),
+    }
+}
+
+pub fn parse<T: FromStr>(value: &str, name: &str) -> Result<T, Error> {
+    value
+        .parse()
+        .map_err(|_| Error::Usage(format!("invalid {name} `{value}`")))
+}
+
+pub fn parse_board(fen: &str) -> Result<Board, Error> {
+    match fen {
+        "startpos" => Ok(Board::new()),
+        fen => Ok(Board::from_fen(fen)?),
+    }
+}
+
+/// `perft <fen> <depth> [--divide] [--threads N] [--algorithm clone|unmove]`
+fn perft(fen: &str, depth: &str, options: &[&str], out: &mut impl Write) -> Result<(), Error> {
+    let mut board = parse_board(fen)?;
+    let depth: i32 = parse(depth, "depth")?;
+    if depth < 1 {
+        return Err(Error::Usage(format!("invalid depth `{depth}`")));
+    }
+    let mut show_divide = false;
+    let mut threads = 1;
+    let mut options = options.iter().copied();
+    while let Some(option) = options.next() {
+        let mut value = || {
+            options
+                .next()
+                .ok_or_else(|| Error::Usage(format!("missing value of `{option}`")))
+        };
+        match option {
+            "--divide" => show_divide = true,
+            "--threads" => threads = parse(value()?, "number of threads")?,
+            "--algorithm" => match value()? {
+                "clone" => board.set_algorithm(Algorithm::Clone),
+                "unmove" => board.set_algorithm(Algorithm::Unmove),
+                algorithm => return Err(Error::Usage(format!("invalid algorithm `{algorithm}`"))),
+            },
+            option => return Err(Error::Usage(format!("invalid option `{option}`"))),
+        }
+    }
+
+    let start = Instant::now();
+    let divide = match threads {
+        1 => divide::divide(&mut board, depth),
+        threads => divide::divide_parallel(&board, depth, threads),
+    };
+    let elapsed = start.elapsed();
+    if show_divide {
+        for (mov, num_positions) in &divide {
+            writeln!(out, "{mov}: {num_positions}")?;
+        }
+        writeln!(out)?;
+    }
+    let total: u64 = divide.values().sum();
+    writeln!(out, "Total Positions: {total}")?;
+    writeln!(
+        out,
+        "Time: {} ms ({} nodes/second)",
+        elapsed.as_millis(),
+        nps(total, elapsed)
+    )?;
+    Ok(())
+}
+
+/// `moves <fen>`
+fn moves(fen: &str, out: &mut impl Write) -> Result<(), Error> {
+    let mut moves: Vec<String> = parse_board(fen)?
+        .gen_legal_moves()
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    moves.sort();
+    for mov in &moves {
+        writeln!(out, "{mov}")?;
+    }
+    writeln!(out, "Total Moves: {}", moves.len())?;
+    Ok(())
+}
+
+/// `show <fen>`
+fn show(fen: &str, out: &mut impl Write) -> Result<(), Error> {
+    let board = parse_board(fen)?;
+    board.display();
+    writeln!(out, "FEN: {}", board.to_fen())?;
+    let turn = match board.state().turn() {
+        Color::White => "White",
+        Color::Black => "Black",
+    };
+    let check = if board.in_check() { ", in check" } else { "" };
+    writeln!(out, "{turn} to move{check}")?;
+    Ok(())
+}
+
+/// `random-game [--seed N]`
+fn random_game(options: &[&str], out: &mut impl Write) -> Result<(), Error> {
+    let seed = match options {
+        [] => rand::thread_rng().gen(),
+        ["--seed", seed] => parse(seed, "seed")?,
+        [option, ..] => return Err(Error::Usage(format!("invalid option `{option}`"))),
+    };
+    let mut board = Board::new();
+    let game_state = board
+        .play_random_game_with_rng(RANDOM_GAME_MOVE_LIMIT, &mut StdRng::seed_from_u64(seed))?;
+    board.display();
+    writeln!(out, "Seed: {seed}")?;
+    writeln!(out, "FEN: {}", board.to_fen())?;
+    writeln!(out, "Result: {}", game::result_str(game_state))?;
+    Ok(())
+}
+
+/// `bench [depth]`
+fn bench(depth: i32, out: &mut impl Write) -> Result<(), Error> {
+    let mut nodes = 0;
+    let start = Instant::now();
+    for fen in BENCH_FENS {
+        let result = Searcher::new(&Board::from_fen(fen)?).search(depth);
+        nodes += result.nodes;
+        writeln!(out, "{fen}: {} nodes", result.nodes)?;
+    }
+    let elapsed = start.elapsed();
+    writeln!(out)?;
+    writeln!(out, "Nodes searched: {nodes}")?;
+    writeln!(out, "Time: {} ms", elapsed.as_millis())?;
+    writeln!(out, "Nodes/second: {}", nps(nodes, elapsed))?;
+    Ok(())
+}
+
+/// `divide-diff <fen> <depth> (--file <listing> | --engine <path> [args...])`
+fn divide_diff(args: &[&str], out: &mut impl Write) -> Result<(), Error> {
+    let [fen, depth, source, rest @ ..] = args else {
+        return Err(Error::Usage(
+            "invalid arguments to `divide-diff`".to_string(),
+        ));
+    };
+    let depth: i32 = parse(depth, "depth")?;
+    let board = parse_board(fen)?;
+    let fen = board.to_fen();
+
+    let divergence = match (*source, rest) {
+        ("--file", [path]) => divide::find_divergence(
+            &board,
+            depth,
+            &mut divide::Listing::from_file(&fen, depth, path)?,
+        )?,
+        ("--engine", [path, engine_args @ ..]) => divide::find_divergence(
+            &board,
+            depth,
+            &mut divide::UciEngine::spawn(path, engine_args)?,
+        )?,
+        _ => {
+            return Err(Error::Usage(
+                "invalid arguments to `divide-diff`".to_string(),
+            ))
+        }
+    };
+    match divergence {
+        Some(divergence) => writeln!(out, "{divergence}")?,
+        None => writeln!(out, "No divergence found")?,
+    }
+    Ok(())
+}
+
+/// `play [--color white|black] [--movetime MS] [--depth D] [--fen <fen>]`, reading moves from stdin.
+fn play(options: &[&str], out: &mut impl Write) -> Result<(), Error> {
+    let mut human = Color::White;
+    let mut movetime = None;
+    let mut depth = None;
+    let mut start = Board::new();
+    let mut options = options.iter().copied();
+    while let Some(option) = options.next() {
+        let mut value = || {
+            options
+                .next()
+                .ok_or_else(|| Error::Usage(format!("missing value of `{option}`")))
+        };
+        match option {
+            "--color" => match value()? {
+                "white" => human = Color::White,
+                "black" => human = Color::Black,
+                color => return Err(Error::Usage(format!("invalid color `{color}`"))),
+            },
+            "--movetime" => movetime = Some(Duration::from_millis(parse(value()?, "move time")?)),
+            "--depth" => depth = Some(parse(value()?, "depth")?),
+            "--fen" => start = parse_board(value()?)?,
+            option => return Err(Error::Usage(format!("invalid option `{option}`"))),
+        }
+    }
+    // A depth alone limits the search, without the default move time
+    let movetime = match (movetime, depth) {
+        (None, None) => Some(play::DEFAULT_MOVE_TIME),
+        (movetime, _) => movetime,
+    };
+    let options = play::Options {
+        human,
+        limits: SearchLimits {
+            depth,
+            movetime,
+            ..SearchLimits::default()
+        },
+        start,
+    };
+    play::run(std::io::stdin().lock(), out, options)?;
+    Ok(())
+}
+
+/// `match --engine <engine> --engine <engine> [options]`
+fn run_match(options: &[&str], out: &mut impl Write) -> Result<(), Error> {
+    let mut engines = vec![];
+    let mut games = 100;
+    let mut concurrency = 1;
+    let mut openings = None;
+    let mut limits = SearchLimits::default();
+    let mut time_control = None;
+    let mut max_moves = 200;
+    let mut sprt_elo = None;
+    let (mut alpha, mut beta) = (0.05, 0.05);
+    let mut pgn = None;
+    let mut options = options.iter().copied();
+    while let Some(option) = options.next() {
+        let mut value = || {
+            options
+                .next()
+                .ok_or_else(|| Error::Usage(format!("missing value of `{option}`")))
+        };
+        match option {
+            "--engine" => engines.push(value()?.parse::<EngineSpec>()?),
+            "--games" => games = parse(value()?, "number of games")?,
+            "--concurrency" => concurrency = parse(value()?, "concurrency")?,
+            "--openings" => openings = Some(match_runner::read_openings(value()?)?),
+            "--movetime" => {
+                limits.movetime = Some(Duration::from_millis(parse(value()?, "move time")?));
+            }
+            "--depth" => limits.depth = Some(parse(value()?, "depth")?),
+            "--nodes" => limits.nodes = Some(parse(value()?, "number of nodes")?),
+            "--tc" => time_control = Some(value()?.parse::<TimeControl>()?),
+            "--max-moves" => max_moves = parse(value()?, "number of moves")?,
+            "--sprt" => {
+                let bounds = value()?;
+                sprt_elo = bounds
+                    .split_once(',')
+                    .and_then(|(elo0, elo1)| Some((elo0.parse().ok()?, elo1.parse().ok()?)));
+                if sprt_elo.is_none() {
+                    return Err(Error::Usage(format!("invalid SPRT bounds `{bounds}`")));
+                }
+            }
+            "--alpha" => alpha = parse(value()?, "alpha")?,
+            "--beta" => beta = parse(value()?, "beta")?,
+            "--pgn" => pgn = Some(value()?.into()),
+            option => return Err(Error::Usage(format!("invalid option `{option}`"))),
+        }
+    }
+    let Ok(engines) = <[EngineSpec; 2]>::try_from(engines) else {
+        return Err(Error::Usage(
+            "a match needs exactly two engines".to_string(),
+        ));
+    };
+    if limits == SearchLimits::default() && time_control.is_none() {
+        limits.movetime = Some(DEFAULT_MATCH_MOVE_TIME);
+    }
+    let options = match_runner::Options {
+        engines,
+        games,
+        concurrency,
+        openings: openings.unwrap_or_else(match_runner::default_openings),
+        limits,
+        time_control,
+        max_plies: max_moves * 2,
+        sprt: sprt_elo.map(|(elo0, elo1)| Sprt {
+            elo0,
+            elo1,
+            alpha,
+            beta,
+        }),
+        pgn,
+    };
+    match_runner::run(options, out)?;
+    Ok(())
+}
+
+/// `book <pgn>... --keys <file> --output <path> [options]`
+fn build_book(options: &[&str], out: &mut impl Write) -> Result<(), Error> {
+    let mut pgns = vec![];
+    let mut keys = None;
+    let mut output = None;
+    let mut build_options = BuildOptions::default();
+    let mut options = options.iter().copied();
+    while let Some(option) = options.next() {
+        let mut value = || {
+            options
+                .next()
+                .ok_or_else(|| Error::Usage(format!("missing value of `{option}`")))
+        };
+        match option {
+            "--keys" => keys = Some(value()?),
+            "--output" => output = Some(value()?),
+            "--max-plies" => build_options.max_plies = parse(value()?, "number of plies")?,
+            "--min-count" => build_options.min_count = parse(value()?, "number of games")?,
+            "--weights" => {
+                let weights = value()?;
+                let parsed: Vec<u32> = weights
+                    .split(',')
+                    .map(str::parse)
+                    .collect::<Result<_, _>>()
+                    .unwrap_or_default();
+                let [win, draw, loss] = parsed[..] else {
+                    return Err(Error::Usage(format!("invalid weights `{weights}`")));
+                };
+                build_options.weights = ResultWeights { win, draw, loss };
+            }
+            option if option.starts_with("--") => {
+                return Err(Error::Usage(format!("invalid option `{option}`")))
+            }
+            pgn => pgns.push(pgn),
+        }
+    }
+    let (Some(keys), Some(output)) = (keys, output) else {
+        return Err(Error::Usage(
+            "a book needs `--keys` and `--output`".to_string(),
+        ));
+    };
+    if pgns.is_empty() {
+        return Err(Error::Usage("no PGN files given".to_string()));
+    }
+
+    let mut builder = BookBuilder::new(Keys::read(keys)?, build_options);
+    let (mut games, mut skipped) = (0, 0);
+    for pgn in pgns {
+        for (number, game) in PgnReader::new(BufReader::new(File::open(pgn)?)).enumerate() {
+            games += 1;
+            if let Err(e) = builder.add_game(&game?) {
+                skipped += 1;
+                writeln!(out, "Skipped game {} of {pgn}: {e}", number + 1)?;
+            }
+        }
+    }
+    let book = builder.build();
+    book.write(output)?;
+    let positions = book
+        .entries()
+        .windows(2)
+        .filter(|pair| pair[0].key != pair[1].key)
+        .count()
+        + usize::from(!book.entries().is_empty());
+    writeln!(out, "Games: {games} ({skipped} skipped)")?;
+    writeln!(
+        out,
+        "Wrote {} moves of {positions} positions to {output}",
+        book.entries().len()
+    )?;
+    Ok(())
+}
+
+/// Reads the `--dir <path>` option of the `tablebase` commands, and the other arguments.
+fn tablebase_dir<'a>(options: &[&'a str]) -> Result<(&'a str, Vec<&'a str>), Error> {
+    let mut dir = ".";
+    let mut rest = vec![];
+    let mut options = options.iter().copied();
+    while let Some(option) = options.next() {
+        match option {
+            "--dir" => {
+                dir = options
+                    .next()
+                    .ok_or_else(|| Error::Usage(format!("missing value of `{option}`")))?;
+            }
+            option if option.starts_with("--") => {
+                return Err(Error::Usage(format!("invalid option `{option}`")))
+            }
+            arg => rest.push(arg),
+        }
+    }
+    Ok((dir, rest))
+}
+
+/// `tablebase generate [<ending>...] [--dir <path>]`, which only generates the
+/// tables that aren't in the directory yet.
+fn generate_tablebases(options: &[&str], out: &mut impl Write) -> Result<(), Error> {
+    let (dir, endings) = tablebase_dir(options)?;
+    let endings = match endings.as_slice() {
+        [] => tablebase::ENDINGS.to_vec(),
+        endings => endings.to_vec(),
+    };
+    let materials = endings
+        .iter()
+        .map(|ending| {
+            ending
+                .parse::<Material>()
+                .map_err(|_| Error::Usage(format!("invalid ending `{ending}`")))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    std::fs::create_dir_all(dir)?;
+    let mut tablebases = Tablebases::read_dir(dir)?;
+    for material in materials {
+        for generated in tablebases.generate(&material)? {
+            let table = tablebases.get(&generated).expect("The table was generated");
+            let path = std::path::Path::new(dir).join(table.file_name());
+            table.write(&path)?;
+            writeln!(
+                out,
+                "{generated}: longest mate in {} moves, written to {}",
+                table.longest_mate().div_ceil(2),
+                path.display()
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// `tablebase probe <fen> [--dir <path>]`
+fn probe_tablebases(fen: &str, options: &[&str], out: &mut impl Write) -> Result<(), Error> {
+    let (dir, rest) = tablebase_dir(options)?;
+    if let [arg, ..] = rest.as_slice() {
+        return Err(Error::Usage(format!("invalid argument `{arg}`")));
+    }
+    let board = parse_board(fen)?;
+    let tablebases = Tablebases::read_dir(dir)?;
+    let Some(probe) = tablebases.probe(&board) else {
+        writeln!(out, "Not in the tablebases")?;
+        return Ok(());
+    };
+    match (probe.wdl, probe.dtm) {
+        (Wdl::Win, dtm) => writeln!(out, "Win: mate in {} moves", dtm.div_ceil(2))?,
+        (Wdl::Draw, _) => writeln!(out, "Draw")?,
+        (Wdl::Loss, 0) => writeln!(out, "Loss: checkmated")?,
+        (Wdl::Loss, dtm) => writeln!(out, "Loss: mated in {} moves", dtm / 2)?,
+    }
+    if let Some((mov, _)) = tablebases.best_move(&board) {
+        writeln!(out, "Best move: {}", san::to_san(&board, &mov))?;
+    }
+    Ok(())
+}
+
+/// `solve <fen> <stipulation>`, which shows every key with the lines that follow
+/// it, and the cooks and duals of the problem.
+fn solve(fen: &str, stipulation: &str, out: &mut impl Write) -> Result<(), Error> {
+    let board = parse_board(fen)?;
+    let stipulation: Stipulation = stipulation
+        .parse()
+        .map_err(|e: solver::Error| Error::Usage(e.to_string()))?;
+    let start = Instant::now();
+    let mut solver = Solver::new(stipulation);
+    let solution = solver.solve(&board);
+    if solution.keys.is_empty() {
+        writeln!(out, "{stipulation}: no solution")?;
+    } else {
+        writeln!(out, "{stipulation}:")?;
+        write_solution_lines(&board, &solution.keys, 1, out)?;
+    }
+    if !solution.cooks().is_empty() {
+        let keys: Vec<String> = solution
+            .keys
+            .iter()
+            .map(|key| numbered_san(&board, &[key.mov]))
+            .collect();
+        writeln!(out, "Cooked, {} keys: {}", keys.len(), keys.join(", "))?;
+    }
+    for dual in solution.duals() {
+        let mut after = board.clone();
+        for mov in &dual.line {
+            after.make_move(mov);
+        }
+        let moves: Vec<String> = dual
+            .moves
+            .iter()
+            .map(|mov| numbered_san(&after, &[*mov]))
+            .collect();
+        writeln!(
+            out,
+            "Dual after {}: {}",
+            numbered_san(&board, &dual.line),
+            moves.join(", ")
+        )?;
+    }
+    let elapsed = start.elapsed();
+    writeln!(
+        out,
+        "Nodes: {} ({} nps)",
+        solver.nodes(),
+        nps(solver.nodes(), elapsed)
+    )?;
+    Ok(())
+}
+
+/// Writes each of [`lines`] and the lines that follow it on their own lines,
+/// indented by how deep they are.
+fn write_solution_lines(
+    board: &Board,
+    lines: &[Line],
+    depth: usize,
+    out: &mut impl Write,
+) -> Result<(), Error> {
+    for line in lines {
+        let key = if depth == 1 { "!" } else { "" };
+        writeln!(
+            out,
+            "{:indent$}{}{key}",
+            "",
+            numbered_san(board, &[line.mov]),
+            indent = 2 * depth
+        )?;
+        let mut after = board.clone();
+        after.make_move(&line.mov);
+        write_solution_lines(&after, &line.continuations, depth + 1, out)?;
+    }
+    Ok(())
+}
+
+/// Writes [`moves`] played from [`board`'s] position in SAN with move numbers,
+/// e.g. `1. e4 e5 2. Nf3` or `1... e5`.
+fn numbered_san(board: &Board, moves: &[Move]) -> String {
+    let mut board = board.clone();
+    let mut tokens = vec![];
+    for (index, mov) in moves.iter().enumerate() {
+        let fullmove = board.state().fullmove_count();
+        match board.state().turn() {
+            Color::White => tokens.push(format!("{fullmove}.")),
+            Color::Black if index == 0 => tokens.push(format!("{fullmove}...")),
+            Color::Black => {}
+        }
+        tokens.push(san::to_san(&board, mov));
+        board.make_move(mov);
+    }
+    tokens.join(" ")
+}
+
+fn nps(nodes: u64, elapsed: Duration) -> u128 {
+    u128::from(nodes) * 1000 / elapsed.as_millis().max(1)
+}
+
+/// `datagen --output <path> [options]`
+fn generate_data(options: &[&str], out: &mut impl Write) -> Result<(), Error> {
+    let mut output = None;
+    let mut format = Format::Binary;
+    let mut datagen_options = datagen::Options {
+        seed: rand::thread_rng().gen(),
+        ..datagen::Options::default()
+    };
+    let mut limits = SearchLimits::default();
+    let mut options = options.iter().copied();
+    while let Some(option) = options.next() {
+        let mut value = || {
+            options
+                .next()
+                .ok_or_else(|| Error::Usage(format!("missing value of `{option}`")))
+        };
+        match option {
+            "--output" => output = Some(value()?),
+            "--games" => datagen_options.games = parse(value()?, "number of games")?,
+            "--threads" => datagen_options.threads = parse(value()?, "number of threads")?,
+            "--depth" => limits.depth = Some(parse(value()?, "depth")?),
+            "--nodes" => limits.nodes = Some(parse(value()?, "number of nodes")?),
+            "--random-plies" => {
+                datagen_options.random_plies = parse(value()?, "number of plies")?;
+            }
+            "--max-moves" => {
+                datagen_options.max_plies = parse::<u32>(value()?, "number of moves")? * 2
+            }
+            "--format" => match value()? {
+                "binary" => format = Format::Binary,
+                "text" => format = Format::Text,
+                format => return Err(Error::Usage(format!("invalid format `{format}`"))),
+            },
+            "--seed" => datagen_options.seed = parse(value()?, "seed")?,
+            option => return Err(Error::Usage(format!("invalid option `{option}`"))),
+        }
+    }
+    let Some(output) = output else {
+        return Err(Error::Usage("datagen needs `--output`".to_string()));
+    };
+    if limits != SearchLimits::default() {
+        datagen_options.limits = limits;
+    }
+
+    let start = Instant::now();
+    let mut writer = SampleWriter::new(BufWriter::new(File::create(output)?), format);
+    let games = datagen::generate(&datagen_options, &mut writer)?;
+    let positions = writer.written();
+    writer.finish()?;
+    writeln!(out, "Games: {games}")?;
+    writeln!(
+        out,
+        "Wrote {positions} positions to {output} in {:.1}s",
+        start.elapsed().as_secs_f64()
+    )?;
+    Ok(())
+}
@@ -1,25 +1,800 @@
-#![allow(unused_mut, unused_variables, unused_imports)]
-use color_eyre::eyre::Result;
-
-use tnewt_board::board;
-use tnewt_board::board::Board;
-use tnewt_board::implementations;
-use tnewt_board::mov::Move;
-
-fn main() -> Result<(), board::Error> {
-    // let mut board = initialize::from_chars!(&[
-    //     ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', //  0  1  2  3  4  5  6  7
-    //     ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', //  8  9 10 11 12 13 14 15
-    //     ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', // 16 17 18 19 20 21 22 23
-    //     ' ', ' ', ' ', 'k', ' ', ' ', ' ', ' ', // 24 25 26 27 28 29 30 31
-    //     ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', // 32 33 34 35 36 37 38 39
-    //     ' ', ' ', ' ', 'K', ' ', ' ', ' ', ' ', // 40 41 42 43 44 45 46 47
-    //     ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', // 48 49 50 51 52 53 54 55
-    //     ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', // 56 57 58 59 60 61 62 63
-    // ])?;
-
-    let mut board = Board::new();
-    board.perft(5)?;
-
-    Ok(())
+use std::io::{BufRead, Cursor, Read, Write};
+
+mod background;
+mod cli;
+mod match_runner;
+mod play;
+mod stats;
+mod uci;
+mod xboard;
+
+/// Runs the protocol the GUI speaks, which is the Chess Engine Communication Protocol
+/// if its first command is `xboard`, and UCI otherwise.
+fn run_engine<R: BufRead, W: Write + Send + 'static>(mut input: R, output: W) -> W {
+    let mut first_line = String::new();
+    input
+        .read_line(&mut first_line)
+        .expect("Failed to read input");
+    let is_xboard = first_line.trim() == "xboard";
+    let input = Cursor::new(first_line).chain(input);
+    match is_xboard {
+        true => xboard::run(input, output),
+        false => uci::run(input, output),
+    }
+}
+
+/// Runs as an engine without arguments, and as a command line tool otherwise.
+/// See [`cli::USAGE`.]
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.is_empty() {
+        run_engine(std::io::stdin().lock(), std::io::stdout());
+        return;
+    }
+    if let Err(e) = cli::run(&args, &mut std::io::stdout()) {
+        eprintln!("error: {e}");
+        std::process::exit(e.exit_code());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use tnewt_board::board::{self, Board, GameState};
+    use tnewt_board::color::Color;
+    use tnewt_board::game::{Game, Termination};
+    use tnewt_board::search::SearchLimits;
+
+    use crate::match_runner::{self, EngineKind, EngineSpec};
+    use crate::stats::{Score, Sprt, Verdict};
+
+    /// Runs the engine on [`script`,] returning every line it writes.
+    fn run_engine(script: &str) -> Vec<String> {
+        let output = super::run_engine(Cursor::new(script.to_string()), vec![]);
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    fn count(lines: &[String], prefix: &str) -> usize {
+        lines.iter().filter(|line| line.starts_with(prefix)).count()
+    }
+
+    #[test]
+    fn uci_handshake() {
+        let lines = run_engine("uci\nisready\nquit\n");
+        assert!(lines[0].starts_with("id name tnewt"));
+        assert!(lines
+            .iter()
+            .any(|line| line.starts_with("option name Hash type spin")));
+        assert!(lines
+            .iter()
+            .any(|line| line.starts_with("option name Move Overhead")));
+        assert_eq!(lines[lines.len() - 2..], ["uciok", "readyok"]);
+    }
+
+    #[test]
+    fn uci_go() {
+        let lines = run_engine("position startpos moves e2e4 e7e5\ngo depth 3\n");
+        assert_eq!(count(&lines, "info depth "), 3);
+        let info = lines
+            .iter()
+            .find(|line| line.starts_with("info depth 3 "))
+            .unwrap();
+        for field in [
+            " multipv 1 ",
+            " score cp ",
+            " nodes ",
+            " nps ",
+            " time ",
+            " pv ",
+        ] {
+            assert!(info.contains(field), "{info} is missing{field}");
+        }
+        assert_eq!(count(&lines, "bestmove "), 1);
+        assert!(lines.last().unwrap().starts_with("bestmove "));
+
+        // The limits may follow the moves to choose from
+        let lines = run_engine("go searchmoves a2a3 h2h3 depth 2\n");
+        assert_eq!(count(&lines, "info depth "), 2);
+        let best_move = lines.last().unwrap();
+        assert!(["bestmove a2a3 ", "bestmove h2h3 "]
+            .iter()
+            .any(|prefix| best_move.starts_with(prefix)));
+
+        // Mate in two, with the counters left out of the FEN
+        let lines = run_engine(
+            "position fen r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq -\n\
+             go mate 2\n",
+        );
+        assert!(lines.iter().any(|line| line.contains(" score mate 2 ")));
+        assert!(lines.last().unwrap().starts_with("bestmove d5f6 ponder "));
+
+        // Checkmated, with no move to play
+        let lines = run_engine("position startpos moves f2f3 e7e5 g2g4 d8h4\ngo nodes 1000\n");
+        assert_eq!(lines.last().unwrap(), "bestmove 0000");
+    }
+
+    #[test]
+    fn uci_options() {
+        let lines = run_engine(
+            "setoption name MultiPV value 3\n\
+             setoption name Threads value 2\n\
+             setoption name Hash value 4\n\
+             setoption name Move Overhead value 0\n\
+             go depth 2\n",
+        );
+        assert_eq!(count(&lines, "info depth 2 "), 3);
+        assert_eq!(count(&lines, "info depth 2 multipv 3 "), 1);
+        assert_eq!(count(&lines, "info string"), 0);
+
+        let lines = run_engine("setoption name Contempt value 10\nposition fen 8/8 w\n");
+        assert_eq!(count(&lines, "info string"), 2);
+    }
+
+    #[test]
+    fn uci_stop() {
+        // Infinite and ponder searches only report a best move once stopped
+        for go in ["go infinite", "go ponder wtime 1000 btime 1000"] {
+            let lines = run_engine(&format!("{go}\nisready\nstop\nquit\n"));
+            assert_eq!(count(&lines, "readyok"), 1);
+            assert_eq!(count(&lines, "bestmove "), 1);
+            assert!(lines.last().unwrap().starts_with("bestmove "));
+        }
+
+        // Ponderhit continues with the clock, and reports a best move only once
+        let lines = run_engine("go ponder movetime 100\nponderhit\n");
+        assert_eq!(count(&lines, "bestmove "), 1);
+
+        let lines = run_engine("go movetime 50\nposition startpos moves e2e4 e9e5\nquit\n");
+        assert_eq!(count(&lines, "bestmove "), 1);
+        assert_eq!(count(&lines, "info string illegal move `e9e5`"), 1);
+    }
+
+    #[test]
+    fn uci_own_book() {
+        use tnewt_board::polyglot::{self, Entry};
+
+        let dir = std::env::temp_dir();
+        let (keys_file, book_file) = (
+            dir.join(format!("tnewt_keys_{}.txt", std::process::id())),
+            dir.join(format!("tnewt_book_{}.bin", std::process::id())),
+        );
+        let randoms: Vec<u64> = (0..polyglot::NUM_RANDOMS as u64)
+            .map(|index| index.wrapping_mul(0x9E37_79B9_7F4A_7C15))
+            .collect();
+        let listing: Vec<String> = randoms
+            .iter()
+            .map(|random| format!("0x{random:016X}"))
+            .collect();
+        std::fs::write(&keys_file, listing.join(",\n")).unwrap();
+        let mut board = Board::new();
+        let mov = board.move_from_uci("a2a3").unwrap();
+        let entry = Entry {
+            key: polyglot::Keys::new(randoms).unwrap().key(&board),
+            mov: polyglot::encode_move(&board, &mov),
+            weight: 1,
+            learn: 0,
+        };
+        std::fs::write(&book_file, entry.to_bytes()).unwrap();
+
+        let lines = run_engine(&format!(
+            "setoption name OwnBook value true\n\
+             setoption name Book Keys File value {}\n\
+             setoption name Book File value {}\n\
+             position startpos\ngo depth 4\n\
+             setoption name Book File value /nonexistent.bin\n\
+             position startpos\ngo depth 1\n",
+            keys_file.display(),
+            book_file.display()
+        ));
+        assert_eq!(count(&lines, "bestmove a2a3"), 1);
+        assert_eq!(count(&lines, "info depth 4 "), 0);
+        assert_eq!(count(&lines, "info string Failed to load the book: "), 1);
+        assert_eq!(count(&lines, "info depth 1 "), 1);
+        assert_eq!(count(&lines, "bestmove "), 2);
+    }
+
+    #[test]
+    fn xboard_game() {
+        let lines = run_engine("xboard\nprotover 2\nping 1\nnew\nsd 2\nusermove e2e4\nping 2\n");
+        assert!(lines[0].starts_with("feature ") && lines[0].ends_with(" done=1"));
+        assert_eq!(lines[1], "pong 1");
+        // The engine plays black, and answers the ping only once it has moved
+        assert!(lines[2].starts_with("move "));
+        assert_eq!(lines[3], "pong 2");
+
+        // Thinking output is only sent once asked for
+        let lines = run_engine("xboard\npost\nsd 2\ngo\n");
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("2 "));
+        assert!(lines[2].starts_with("move "));
+
+        let lines = run_engine("xboard\nforce\nusermove e2e5\nusermove e2e4\nfoo\n");
+        assert_eq!(
+            lines,
+            ["Illegal move: e2e5", "Error (unknown command): foo"]
+        );
+    }
+
+    #[test]
+    fn xboard_setboard_and_undo() {
+        // Mate in two, which the engine finds with the clock
+        let lines = run_engine(
+            "xboard\nforce\n\
+             setboard r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1\n\
+             post\nlevel 40 5 0\ntime 30000\notim 30000\ngo\n",
+        );
+        assert!(lines
+            .iter()
+            .any(|line| line.split(' ').nth(1) == Some("100002")));
+        assert_eq!(lines.last().unwrap(), "move d5f6");
+
+        // Taking back the engine's move and the opponent's gives the engine the move again
+        let lines = run_engine(
+            "xboard\nnew\nforce\nusermove e2e4\nusermove e7e5\nusermove g1f3\n\
+             remove\nundo\nundo\nundo\nsd 1\ngo\n",
+        );
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].starts_with("move "));
+
+        let lines = run_engine("xboard\nsetboard 8/8/8 w - - 0 1\n");
+        assert_eq!(
+            lines,
+            ["tellusererror Illegal position: the board of FEN `8/8/8 w - - 0 1` does not have 64 squares"]
+        );
+    }
+
+    #[test]
+    fn xboard_analyze() {
+        let lines = run_engine("xboard\npost\nanalyze\nusermove e2e4\nping 1\nundo\nexit\nquit\n");
+        assert!(lines.contains(&"pong 1".to_string()));
+        assert!(!lines.iter().any(|line| line.starts_with("move ")));
+    }
+
+    /// Output shared with the test, to read the engine's replies while it runs.
+    #[derive(Clone, Default)]
+    struct SharedOutput(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedOutput {
+        fn lines(&self) -> Vec<String> {
+            let output = self.0.lock().unwrap();
+            String::from_utf8_lossy(&output)
+                .lines()
+                .map(str::to_string)
+                .collect()
+        }
+    }
+
+    #[test]
+    fn xboard_force_after_engine_move() {
+        let output = SharedOutput::default();
+        let mut engine = crate::xboard::Engine::new(output.clone());
+        for line in ["xboard", "protover 2", "new", "sd 2", "usermove e2e4"] {
+            assert!(engine.handle(line));
+        }
+        // The GUI sees the engine's move before the engine is told anything else
+        while !output.lines().iter().any(|line| line.starts_with("move ")) {
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        for line in [
+            "force",
+            "usermove d2d4",
+            "result 1/2-1/2 {Agreed}",
+            "ping 1",
+        ] {
+            assert!(engine.handle(line));
+        }
+        engine.finish();
+        let lines = output.lines();
+        assert_eq!(count(&lines, "move "), 1);
+        assert_eq!(count(&lines, "Illegal move"), 0, "{lines:?}");
+        assert_eq!(lines.last().unwrap(), "pong 1");
+    }
+
+    /// Runs the command line tool with [`args`,] returning every line it writes.
+    fn run_cli(args: &[&str]) -> Result<Vec<String>, crate::cli::Error> {
+        let args: Vec<String> = args.iter().map(ToString::to_string).collect();
+        let mut output = vec![];
+        crate::cli::run(&args, &mut output)?;
+        Ok(String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect())
+    }
+
+    #[test]
+    fn cli_commands() -> Result<(), crate::cli::Error> {
+        for options in [
+            &[][..],
+            &["--threads", "3"],
+            &["--algorithm", "clone", "--divide"],
+            &["--divide", "--threads", "2"],
+        ] {
+            let lines = run_cli(&[&["perft", "startpos", "3"], options].concat())?;
+            let total = lines
+                .iter()
+                .position(|line| line == "Total Positions: 8902")
+                .unwrap();
+            assert_eq!(total, if options.contains(&"--divide") { 21 } else { 0 });
+        }
+
+        let lines = run_cli(&["moves", "8/8/8/8/8/8/8/k1K5 w - - 0 1"])?;
+        assert_eq!(lines, ["c1c2", "c1d1", "c1d2", "Total Moves: 3"]);
+
+        let lines = run_cli(&["show", "startpos"])?;
+        assert_eq!(
+            lines,
+            [
+                format!("FEN: {}", board::STARTING_FEN),
+                "White to move".to_string()
+            ]
+        );
+
+        let game = run_cli(&["random-game", "--seed", "7"])?;
+        assert_eq!(game[0], "Seed: 7");
+        assert_eq!(run_cli(&["random-game", "--seed", "7"])?, game);
+
+        let lines = run_cli(&["bench", "1"])?;
+        assert!(lines
+            .iter()
+            .any(|line| line.starts_with("Nodes searched: ")));
+        Ok(())
+    }
+
+    #[test]
+    fn cli_errors() {
+        for (args, exit_code, message) in [
+            (
+                &["perft", "startpos"][..],
+                2,
+                "invalid arguments to `perft`",
+            ),
+            (&["perft", "startpos", "two"], 2, "invalid depth `two`"),
+            (
+                &["perft", "startpos", "2", "--threads"],
+                2,
+                "missing value of `--threads`",
+            ),
+            (
+                &["perft", "startpos", "2", "--algorithm", "magic"],
+                2,
+                "invalid algorithm `magic`",
+            ),
+            (&["frobnicate"], 2, "invalid arguments to `frobnicate`"),
+            (
+                &["match", "--engine", "tnewt"],
+                2,
+                "a match needs exactly two engines",
+            ),
+            (
+                &["match", "--engine", "stockfish"],
+                2,
+                "invalid engine `stockfish`",
+            ),
+            (
+                &["match", "--engine", "tnewt,futility_pruning=maybe"],
+                2,
+                "invalid futility_pruning `maybe`",
+            ),
+            (&["match", "--tc", "1+x"], 2, "invalid time control `1+x`"),
+            (
+                &["book", "games.pgn", "--output", "book.bin"],
+                2,
+                "a book needs `--keys` and `--output`",
+            ),
+            (
+                &["book", "--keys", "k", "--output", "o", "--weights", "2,1"],
+                2,
+                "invalid weights `2,1`",
+            ),
+            (
+                &["tablebase", "generate", "KQXK"],
+                2,
+                "invalid ending `KQXK`",
+            ),
+            (
+                &["tablebase", "generate", "KQRKR"],
+                1,
+                "tables can't be generated for `KQRKR`",
+            ),
+            (
+                &["tablebase", "probe", "startpos", "--dir"],
+                2,
+                "missing value of `--dir`",
+            ),
+            (
+                &["solve", "startpos", "m#2"],
+                2,
+                "invalid stipulation `m#2`",
+            ),
+            (&["datagen", "--games", "1"], 2, "datagen needs `--output`"),
+            (
+                &["datagen", "--output", "data.bin", "--format", "csv"],
+                2,
+                "invalid format `csv`",
+            ),
+            (&["moves", "8/8/8"], 1, "FEN `8/8/8` does not have 6 fields"),
+            (
+                &["show", "8/8/8/8/8/8/8/7X w - - 0 1"],
+                1,
+                "invalid piece `X`",
+            ),
+        ] {
+            let error = run_cli(args).unwrap_err();
+            assert_eq!(error.exit_code(), exit_code, "{args:?}");
+            assert!(error.to_string().starts_with(message), "{args:?}: {error}");
+        }
+    }
+
+    #[test]
+    fn build_opening_book() -> Result<(), crate::cli::Error> {
+        use tnewt_board::polyglot::{self, Book, Keys};
+
+        let dir = std::env::temp_dir();
+        let path = |name: &str| dir.join(format!("tnewt_{}_{name}", std::process::id()));
+        let (keys_file, pgn_file, book_file) =
+            (path("keys.txt"), path("games.pgn"), path("book.bin"));
+        let listing: Vec<String> = (0..polyglot::NUM_RANDOMS as u64)
+            .map(|index| format!("0x{:016X}", index.wrapping_mul(0x9E37_79B9_7F4A_7C15)))
+            .collect();
+        std::fs::write(&keys_file, listing.join("\n"))?;
+        std::fs::write(
+            &pgn_file,
+            "[Event \"A\"]\n\n1. e4 e5 2. Nf3 1-0\n\n\
+             [Event \"B\"]\n\n1. e4 c5 1/2-1/2\n\n\
+             [Event \"C\"]\n\n1. e4 Ke7 0-1\n",
+        )?;
+
+        let lines = run_cli(&[
+            "book",
+            pgn_file.to_str().unwrap(),
+            "--keys",
+            keys_file.to_str().unwrap(),
+            "--output",
+            book_file.to_str().unwrap(),
+            "--max-plies",
+            "2",
+        ])?;
+        assert_eq!(
+            lines,
+            [
+                format!(
+                    "Skipped game 3 of {}: illegal move `Ke7`",
+                    pgn_file.display()
+                ),
+                "Games: 3 (1 skipped)".to_string(),
+                format!("Wrote 2 moves of 2 positions to {}", book_file.display()),
+            ]
+        );
+        let book = Book::open(&book_file, Keys::read(&keys_file)?)?;
+        let mut board = Board::new();
+        let moves: Vec<(String, u16)> = book
+            .moves(&board)
+            .into_iter()
+            .map(|(mov, weight)| (mov.to_string(), weight))
+            .collect();
+        assert_eq!(moves, [("e2e4".to_string(), 3)]);
+        let e4 = board.move_from_uci("e2e4")?;
+        board.make_move(&e4);
+        // Black only lost with e5, which has no weight
+        let moves: Vec<String> = book
+            .moves(&board)
+            .iter()
+            .map(|(mov, _)| mov.to_string())
+            .collect();
+        assert_eq!(moves, ["c7c5"]);
+        Ok(())
+    }
+
+    #[test]
+    fn endgame_tablebase() -> Result<(), crate::cli::Error> {
+        let dir = std::env::temp_dir().join(format!("tnewt_{}_tablebases", std::process::id()));
+        let dir_arg = dir.to_str().unwrap();
+        let lines = run_cli(&["tablebase", "generate", "KQK", "--dir", dir_arg])?;
+        assert_eq!(
+            lines,
+            [
+                format!(
+                    "KK: longest mate in 0 moves, written to {}",
+                    dir.join("KK.tnb").display()
+                ),
+                format!(
+                    "KQK: longest mate in 10 moves, written to {}",
+                    dir.join("KQK.tnb").display()
+                ),
+            ]
+        );
+        // The tables in the directory aren't generated again
+        assert!(run_cli(&["tablebase", "generate", "KKQ", "--dir", dir_arg])?.is_empty());
+
+        let probe = |fen: &str| run_cli(&["tablebase", "probe", fen, "--dir", dir_arg]);
+        assert_eq!(
+            probe("k7/8/1K6/8/8/8/7Q/8 w - - 0 1")?,
+            ["Win: mate in 1 moves", "Best move: Qh8#"]
+        );
+        assert_eq!(
+            probe("k6Q/8/1K6/8/8/8/8/8 b - - 0 1")?,
+            ["Loss: checkmated"]
+        );
+        assert_eq!(
+            probe("7K/8/8/8/8/8/1k6/1Q6 b - - 0 1")?,
+            ["Draw", "Best move: Kxb1"]
+        );
+        assert_eq!(probe("startpos")?, ["Not in the tablebases"]);
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn solve_problem() -> Result<(), crate::cli::Error> {
+        let mut lines = run_cli(&["solve", "1R6/k7/8/8/6R1/p7/K7/8 w - - 2 5", "#2"])?;
+        assert!(lines.pop().unwrap().starts_with("Nodes: "));
+        assert_eq!(
+            lines,
+            [
+                "#2:",
+                "  5. Rb3!",
+                "    5... Ka6",
+                "      6. Ra4#",
+                "    5... Ka8",
+                "      6. Ra4#",
+                "  5. Rb1!",
+                "    5... Ka6",
+                "      6. Ra4#",
+                "    5... Ka8",
+                "      6. Ra4#",
+                "  5. Rgb4!",
+                "    5... Ka6",
+                "      6. Ra8#",
+                "      6. Ra4#",
+                "Cooked, 3 keys: 5. Rb3, 5. Rb1, 5. Rgb4",
+                "Dual after 5. Rgb4 Ka6: 6. Ra8#, 6. Ra4#",
+            ]
+        );
+
+        let mut lines = run_cli(&["solve", "k7/8/1K6/8/8/8/8/7R b - - 0 1", "h#1"])?;
+        lines.pop();
+        assert_eq!(lines, ["h#1:", "  1... Kb8!", "    2. Rh8#"]);
+        let mut lines = run_cli(&["solve", "startpos", "s#1"])?;
+        lines.pop();
+        assert_eq!(lines, ["s#1: no solution"]);
+        Ok(())
+    }
+
+    #[test]
+    fn generate_training_data() -> Result<(), crate::cli::Error> {
+        let path = std::env::temp_dir().join(format!("tnewt_{}_data.txt", std::process::id()));
+        let lines = run_cli(&[
+            "datagen",
+            "--output",
+            path.to_str().unwrap(),
+            "--games",
+            "2",
+            "--depth",
+            "1",
+            "--max-moves",
+            "10",
+            "--format",
+            "text",
+            "--seed",
+            "50",
+        ])?;
+        let data = std::fs::read_to_string(&path)?;
+        std::fs::remove_file(&path)?;
+        assert_eq!(lines[0], "Games: 2");
+        let wrote = format!(
+            "Wrote {} positions to {} in ",
+            data.lines().count(),
+            path.display()
+        );
+        assert!(lines[1].starts_with(&wrote), "{}", lines[1]);
+        for line in data.lines() {
+            let [fen, score, result] = line.split(" | ").collect::<Vec<_>>()[..] else {
+                panic!("{line}");
+            };
+            assert!(
+                Board::from_fen(fen).is_ok() && score.parse::<i16>().is_ok(),
+                "{line}"
+            );
+            // Games adjudicated after 10 moves are drawn
+            assert_eq!(result, "0.5", "{line}");
+        }
+        Ok(())
+    }
+
+    fn play(script: &str, human: Color, fen: &str) -> (Game, Vec<String>) {
+        let options = crate::play::Options {
+            human,
+            limits: SearchLimits::depth(2),
+            start: Board::from_fen(fen).unwrap(),
+        };
+        let mut output = vec![];
+        let game = crate::play::run(Cursor::new(script.to_string()), &mut output, options).unwrap();
+        let lines = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect();
+        (game, lines)
+    }
+
+    #[test]
+    fn play_against_engine() {
+        let (game, lines) = play(
+            "e4\nNf9\nhint\nundo\nd2d4\nresign\nNf3\n",
+            Color::White,
+            board::STARTING_FEN,
+        );
+        assert!(lines[2].starts_with("8  r n b q k b n r"));
+        assert_eq!(count(&lines, "tnewt plays "), 2);
+        assert_eq!(count(&lines, "> Illegal move `Nf9`"), 1);
+        assert_eq!(count(&lines, "> Hint: "), 1);
+        assert_eq!(count(&lines, "> Black wins by resignation (0-1)"), 1);
+        assert_eq!(count(&lines, "> The game is over"), 1);
+        assert_eq!(game.moves().len(), 2);
+        assert_eq!(game.san_moves()[0], "d4");
+        assert!(game.to_pgn().starts_with("[Event \"Casual game\"]"));
+        assert!(game.to_pgn().contains("[White \"Human\"]"));
+
+        // The engine moves first, and its mate in one ends the game
+        let (game, lines) = play("", Color::White, "6k1/8/8/8/8/8/r4PPP/6K1 b - - 0 1");
+        assert_eq!(count(&lines, "tnewt plays Ra1#"), 1);
+        assert_eq!(
+            game.result(),
+            Some((GameState::Victory(Color::Black), Termination::Checkmate))
+        );
+        assert_eq!(count(&lines, "Black wins by checkmate (0-1)"), 1);
+    }
+
+    #[test]
+    fn match_statistics() {
+        let score = Score {
+            wins: 60,
+            draws: 20,
+            losses: 20,
+        };
+        assert_eq!(score.games(), 100);
+        assert!((score.ratio() - 0.7).abs() < 1e-9);
+        let (elo, error) = score.elo().unwrap();
+        assert!((elo - 147.2).abs() < 0.1, "{elo}");
+        assert!(error > 50.0 && error < 100.0, "{error}");
+        assert_eq!(
+            Score {
+                wins: 3,
+                draws: 0,
+                losses: 0
+            }
+            .elo(),
+            None
+        );
+        let (elo, _) = Score {
+            wins: 10,
+            draws: 5,
+            losses: 10,
+        }
+        .elo()
+        .unwrap();
+        assert_eq!(elo, 0.0);
+
+        let sprt = Sprt {
+            elo0: 0.0,
+            elo1: 10.0,
+            alpha: 0.05,
+            beta: 0.05,
+        };
+        let (lower, upper) = sprt.bounds();
+        assert!((lower + 2.944).abs() < 1e-3 && (upper - 2.944).abs() < 1e-3);
+        assert_eq!(sprt.verdict(&Score::default()), Verdict::Continue);
+        assert_eq!(
+            sprt.verdict(&Score {
+                wins: 30,
+                draws: 40,
+                losses: 30
+            }),
+            Verdict::Continue
+        );
+        assert_eq!(
+            sprt.verdict(&Score {
+                wins: 600,
+                draws: 400,
+                losses: 300
+            }),
+            Verdict::AcceptH1
+        );
+        assert_eq!(
+            sprt.verdict(&Score {
+                wins: 300,
+                draws: 400,
+                losses: 600
+            }),
+            Verdict::AcceptH0
+        );
+    }
+
+    #[test]
+    fn engine_spec() {
+        let spec: EngineSpec = "tnewt,name=no-nmp,null_move_pruning=false,depth=4,hash=1"
+            .parse()
+            .unwrap();
+        assert_eq!(spec.name, "no-nmp");
+        assert_eq!(spec.limits, SearchLimits::depth(4));
+        let EngineKind::InProcess {
+            hash_mb,
+            threads,
+            config,
+        } = spec.kind
+        else {
+            panic!()
+        };
+        assert_eq!((hash_mb, threads), (1, 1));
+        assert!(!config.null_move_pruning && config.late_move_reductions);
+
+        let spec: EngineSpec = "uci:/usr/bin/stockfish,Skill Level=3".parse().unwrap();
+        assert_eq!(spec.name, "stockfish");
+        assert_eq!(
+            spec.kind,
+            EngineKind::Uci {
+                path: "/usr/bin/stockfish".to_string(),
+                options: vec![("Skill Level".to_string(), "3".to_string())],
+            }
+        );
+        assert!("uci:".parse::<EngineSpec>().is_err());
+        assert!("tnewt,depth".parse::<EngineSpec>().is_err());
+    }
+
+    #[test]
+    fn self_play_match() {
+        let pgn = std::env::temp_dir().join(format!("tnewt_match_{}.pgn", std::process::id()));
+        let options = match_runner::Options {
+            engines: [
+                "tnewt,depth=1".parse().unwrap(),
+                "tnewt,depth=2,hash=1".parse().unwrap(),
+            ],
+            games: 4,
+            concurrency: 2,
+            openings: match_runner::default_openings(),
+            limits: SearchLimits::default(),
+            time_control: None,
+            max_plies: 40,
+            sprt: Some(Sprt {
+                elo0: 0.0,
+                elo1: 10.0,
+                alpha: 0.05,
+                beta: 0.05,
+            }),
+            pgn: Some(pgn.clone()),
+        };
+        let mut output = vec![];
+        let score = match_runner::run(options, &mut output).unwrap();
+        assert_eq!(score.games(), 4);
+
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<String> = output.lines().map(str::to_string).collect();
+        assert_eq!(count(&lines, "Finished game "), 4);
+        assert_eq!(count(&lines, "Score of tnewt 1 vs tnewt 2: "), 4);
+        assert_eq!(count(&lines, "SPRT: llr "), 4);
+        assert_eq!(count(&lines, "Finished game 1 (tnewt 1 vs tnewt 2)"), 1);
+        assert_eq!(count(&lines, "Finished game 2 (tnewt 2 vs tnewt 1)"), 1);
+
+        let pgn = std::fs::read_to_string(pgn).unwrap();
+        assert_eq!(pgn.matches("[Event \"tnewt match\"]").count(), 4);
+        // Both games of a pair start from the same opening
+        let first_opening = format!("[FEN \"{}\"]", match_runner::default_openings()[0].to_fen());
+        assert_eq!(pgn.matches(&first_opening).count(), 2);
+    }
 }
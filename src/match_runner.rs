@@ -0,0 +1,605 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tnewt_board::board::{Board, GameState};
+use tnewt_board::color::Color;
+use tnewt_board::game::{self, Game, Termination};
+use tnewt_board::mov::Move;
+use tnewt_board::search::{SearchConfig, SearchLimits};
+use tnewt_board::smp::LazySmp;
+use tnewt_board::tt;
+
+use crate::background::SEARCH_STACK_SIZE;
+use crate::cli::{self, Error};
+use crate::stats::{Score, Sprt, Verdict};
+
+/// The openings played when no opening file is given, as moves in SAN from the
+/// starting position.
+const DEFAULT_OPENINGS: [&str; 10] = [
+    "e4 e5 Nf3 Nc6",
+    "e4 c5 Nf3 d6",
+    "e4 e6 d4 d5",
+    "e4 c6 d4 d5",
+    "d4 d5 c4 e6",
+    "d4 d5 c4 c6",
+    "d4 Nf6 c4 e6",
+    "d4 Nf6 c4 g6",
+    "c4 e5 Nc3 Nf6",
+    "Nf3 d5 g3 Nf6",
+];
+
+/// How an engine plays its moves.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EngineKind {
+    /// Searches in this process.
+    InProcess {
+        hash_mb: usize,
+        threads: usize,
+        config: SearchConfig,
+    },
+    /// A UCI engine running as a subprocess, which is sent [`options`] before the match.
+    Uci {
+        path: String,
+        options: Vec<(String, String)>,
+    },
+}
+
+/// One of the two players of a match, written as `tnewt` or `uci:<path>`,
+/// followed by comma separated `key=value` options.
+///
+/// Both kinds take a `name`, and `depth`, `nodes` and `movetime` (in milliseconds)
+/// limits that apply on top of the match's. `tnewt` also takes `hash`, `threads`
+/// and the boolean fields of [`SearchConfig`,] and the options of a UCI engine
+/// are set with `setoption`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EngineSpec {
+    pub name: String,
+    pub kind: EngineKind,
+    pub limits: SearchLimits,
+}
+
+impl FromStr for EngineSpec {
+    type Err = Error;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let mut items = spec.split(',');
+        let mut kind = match items.next() {
+            Some("tnewt") => EngineKind::InProcess {
+                hash_mb: tt::DEFAULT_SIZE_MB,
+                threads: 1,
+                config: SearchConfig::default(),
+            },
+            Some(uci) if uci.len() > "uci:".len() && uci.starts_with("uci:") => EngineKind::Uci {
+                path: uci["uci:".len()..].to_string(),
+                options: vec![],
+            },
+            _ => return Err(Error::Usage(format!("invalid engine `{spec}`"))),
+        };
+        let mut name = None;
+        let mut limits = SearchLimits::default();
+        for item in items {
+            let Some((key, value)) = item.split_once('=') else {
+                return Err(Error::Usage(format!("invalid engine option `{item}`")));
+            };
+            match (key, &mut kind) {
+                ("name", _) => name = Some(value.to_string()),
+                ("depth", _) => limits.depth = Some(cli::parse(value, "depth")?),
+                ("nodes", _) => limits.nodes = Some(cli::parse(value, "number of nodes")?),
+                ("movetime", _) => {
+                    limits.movetime = Some(Duration::from_millis(cli::parse(value, "move time")?));
+                }
+                ("hash", EngineKind::InProcess { hash_mb, .. }) => {
+                    *hash_mb = cli::parse(value, "hash size")?;
+                }
+                ("threads", EngineKind::InProcess { threads, .. }) => {
+                    *threads = cli::parse(value, "number of threads")?;
+                }
+                (key, EngineKind::InProcess { config, .. }) => {
+                    let toggle = config_toggle(config, key)
+                        .ok_or_else(|| Error::Usage(format!("invalid engine option `{item}`")))?;
+                    *toggle = cli::parse(value, key)?;
+                }
+                (key, EngineKind::Uci { options, .. }) => {
+                    options.push((key.to_string(), value.to_string()));
+                }
+            }
+        }
+        let name = name.unwrap_or_else(|| match &kind {
+            EngineKind::InProcess { .. } => "tnewt".to_string(),
+            EngineKind::Uci { path, .. } => Path::new(path).file_name().map_or_else(
+                || path.clone(),
+                |file_name| file_name.to_string_lossy().to_string(),
+            ),
+        });
+        Ok(EngineSpec { name, kind, limits })
+    }
+}
+
+fn config_toggle<'a>(config: &'a mut SearchConfig, key: &str) -> Option<&'a mut bool> {
+    match key {
+        "null_move_pruning" => Some(&mut config.null_move_pruning),
+        "late_move_reductions" => Some(&mut config.late_move_reductions),
+        "reverse_futility_pruning" => Some(&mut config.reverse_futility_pruning),
+        "futility_pruning" => Some(&mut config.futility_pruning),
+        "mate_distance_pruning" => Some(&mut config.mate_distance_pruning),
+        "check_extensions" => Some(&mut config.check_extensions),
+        _ => None,
+    }
+}
+
+/// A clock of [`base`] for the whole game, which gains [`increment`] after every move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeControl {
+    pub base: Duration,
+    pub increment: Duration,
+}
+
+impl FromStr for TimeControl {
+    type Err = Error;
+
+    /// Reads a time control written as `<seconds>[+<increment>]`, e.g. `10+0.1`.
+    fn from_str(tc: &str) -> Result<Self, Self::Err> {
+        let (base, increment) = tc.split_once('+').unwrap_or((tc, "0"));
+        let seconds = |value: &str| {
+            value
+                .parse::<f64>()
+                .ok()
+                .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+                .map(Duration::from_secs_f64)
+                .ok_or_else(|| Error::Usage(format!("invalid time control `{tc}`")))
+        };
+        Ok(TimeControl {
+            base: seconds(base)?,
+            increment: seconds(increment)?,
+        })
+    }
+}
+
+pub struct Options {
+    pub engines: [EngineSpec; 2],
+    pub games: usize,
+    /// The number of games played at the same time.
+    pub concurrency: usize,
+    /// Every opening is played twice in a row, with the engines swapping colors.
+    pub openings: Vec<Board>,
+    /// The limits of every move, on top of each engine's own.
+    pub limits: SearchLimits,
+    pub time_control: Option<TimeControl>,
+    /// Games still being played after this many plies are drawn by adjudication.
+    pub max_plies: usize,
+    /// The test that stops the match once it is decided, from the first engine's side.
+    pub sprt: Option<Sprt>,
+    pub pgn: Option<PathBuf>,
+}
+
+/// The openings played when no opening file is given.
+///
+/// # Panics
+///
+/// Panics if one of the built in openings is illegal.
+#[must_use]
+pub fn default_openings() -> Vec<Board> {
+    DEFAULT_OPENINGS
+        .iter()
+        .map(|moves| {
+            let mut board = Board::new();
+            for san in moves.split_whitespace() {
+                let mov = board
+                    .move_from_san(san)
+                    .expect("Built in openings are legal");
+                board.make_move(&mov);
+            }
+            board
+        })
+        .collect()
+}
+
+/// Reads an opening per line of the FEN or EPD file at [`path`,] skipping
+/// empty lines and lines starting with `#`.
+///
+/// # Errors
+///
+/// This function will return an error if the file can't be read or has an invalid position.
+pub fn read_openings(path: &str) -> Result<Vec<Board>, Error> {
+    let mut openings = vec![];
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.is_empty() || fields[0].starts_with('#') {
+            continue;
+        }
+        // EPD has no move counters, and may be followed by operations instead
+        let counters = match fields.get(4..6) {
+            Some(counters) if counters.iter().all(|field| field.parse::<u32>().is_ok()) => {
+                counters.join(" ")
+            }
+            _ => "0 1".to_string(),
+        };
+        let position = fields.get(..4).unwrap_or(&fields).join(" ");
+        openings.push(Board::from_fen(&format!("{position} {counters}"))?);
+    }
+    if openings.is_empty() {
+        return Err(Error::Usage(format!("no openings in `{path}`")));
+    }
+    Ok(openings)
+}
+
+/// Something that plays moves in a game.
+trait Player {
+    fn new_game(&mut self) -> Result<(), Error>;
+
+    /// Returns the move to play in the current position of [`game`,] searched within [`limits`.]
+    fn best_move(&mut self, game: &Game, limits: &SearchLimits) -> Result<Move, Error>;
+}
+
+fn spawn_player(spec: &EngineSpec) -> Result<Box<dyn Player>, Error> {
+    Ok(match &spec.kind {
+        EngineKind::InProcess {
+            hash_mb,
+            threads,
+            config,
+        } => {
+            let mut smp = LazySmp::new(*threads, *hash_mb);
+            smp.set_config(*config);
+            Box::new(InProcessPlayer { smp })
+        }
+        EngineKind::Uci { path, options } => Box::new(UciPlayer::spawn(&spec.name, path, options)?),
+    })
+}
+
+struct InProcessPlayer {
+    smp: LazySmp,
+}
+
+impl Player for InProcessPlayer {
+    fn new_game(&mut self) -> Result<(), Error> {
+        self.smp.clear();
+        Ok(())
+    }
+
+    fn best_move(&mut self, game: &Game, limits: &SearchLimits) -> Result<Move, Error> {
+        let stop = Arc::new(AtomicBool::new(false));
+        self.smp
+            .search(game.board(), limits, &stop, |_| ())
+            .best_move
+            .ok_or_else(|| Error::Engine("tnewt found no move to play".to_string()))
+    }
+}
+
+struct UciPlayer {
+    name: String,
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl UciPlayer {
+    /// Starts the UCI engine at [`path`,] and sets its [`options`.]
+    fn spawn(name: &str, path: &str, options: &[(String, String)]) -> Result<Self, Error> {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| Error::Engine(format!("failed to start `{path}`: {e}")))?;
+        let exited = || Error::Engine(format!("`{name}` exited"));
+        let stdin = child.stdin.take().ok_or_else(exited)?;
+        let stdout = BufReader::new(child.stdout.take().ok_or_else(exited)?);
+        let mut player = UciPlayer {
+            name: name.to_string(),
+            child,
+            stdin,
+            stdout,
+        };
+        player.send("uci")?;
+        player.read_until("uciok")?;
+        for (option, value) in options {
+            player.send(&format!("setoption name {option} value {value}"))?;
+        }
+        Ok(player)
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), Error> {
+        writeln!(self.stdin, "{command}")?;
+        self.stdin.flush()?;
+        Ok(())
+    }
+
+    /// Reads lines until one starting with [`token`,] which is returned.
+    fn read_until(&mut self, token: &str) -> Result<String, Error> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.stdout.read_line(&mut line)? == 0 {
+                return Err(Error::Engine(format!("`{}` exited", self.name)));
+            }
+            if line.split_whitespace().next() == Some(token) {
+                return Ok(line);
+            }
+        }
+    }
+}
+
+impl Player for UciPlayer {
+    fn new_game(&mut self) -> Result<(), Error> {
+        self.send("ucinewgame")?;
+        self.send("isready")?;
+        self.read_until("readyok")?;
+        Ok(())
+    }
+
+    fn best_move(&mut self, game: &Game, limits: &SearchLimits) -> Result<Move, Error> {
+        let moves: Vec<String> = game.moves().iter().map(ToString::to_string).collect();
+        self.send(&format!(
+            "position fen {} moves {}",
+            game.start().to_fen(),
+            moves.join(" ")
+        ))?;
+        self.send(&go_command(limits))?;
+        let line = self.read_until("bestmove")?;
+        let uci = line.split_whitespace().nth(1).unwrap_or_default();
+        game.board()
+            .clone()
+            .move_from_uci(uci)
+            .map_err(|_| Error::Engine(format!("`{}` played the illegal move `{uci}`", self.name)))
+    }
+}
+
+impl Drop for UciPlayer {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let _ = self.child.wait();
+    }
+}
+
+/// The UCI `go` command searching within [`limits`.]
+fn go_command(limits: &SearchLimits) -> String {
+    let millis = |duration: Duration| duration.as_millis().to_string();
+    let mut command = "go".to_string();
+    for (name, value) in [
+        ("depth", limits.depth.map(|depth| depth.to_string())),
+        ("nodes", limits.nodes.map(|nodes| nodes.to_string())),
+        ("movetime", limits.movetime.map(millis)),
+        ("wtime", limits.wtime.map(millis)),
+        ("btime", limits.btime.map(millis)),
+        ("winc", limits.winc.map(millis)),
+        ("binc", limits.binc.map(millis)),
+    ] {
+        if let Some(value) = value {
+            command.push_str(&format!(" {name} {value}"));
+        }
+    }
+    command
+}
+
+fn color_index(color: Color) -> usize {
+    match color {
+        Color::White => 0,
+        Color::Black => 1,
+    }
+}
+
+/// A finished game, and which engine played white.
+struct Played {
+    index: usize,
+    white: usize,
+    game: Game,
+}
+
+/// Plays a match between the two engines of [`options`,] reporting the score
+/// after every game to [`out`,] and returns the score of the first engine.
+///
+/// # Errors
+///
+/// This function will return an error if an engine fails, or the games can't be saved.
+pub fn run(mut options: Options, out: &mut impl Write) -> Result<Score, Error> {
+    if options.engines[0].name == options.engines[1].name {
+        options.engines[0].name.push_str(" 1");
+        options.engines[1].name.push_str(" 2");
+    }
+    let options = Arc::new(options);
+    let next_game = Arc::new(AtomicUsize::new(0));
+    let stop = Arc::new(AtomicBool::new(false));
+    let (sender, receiver) = mpsc::channel();
+    let workers = (0..options.concurrency.clamp(1, options.games.max(1)))
+        .map(|_| {
+            let (options, next_game, stop, sender) = (
+                Arc::clone(&options),
+                Arc::clone(&next_game),
+                Arc::clone(&stop),
+                sender.clone(),
+            );
+            thread::Builder::new()
+                .stack_size(SEARCH_STACK_SIZE)
+                .spawn(move || {
+                    if let Err(e) = play_games(&options, &next_game, &stop, &sender) {
+                        let _ = sender.send(Err(e));
+                    }
+                })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    drop(sender);
+
+    let mut pgn = options.pgn.as_ref().map(File::create).transpose()?;
+    let mut score = Score::default();
+    let mut error = None;
+    for played in receiver {
+        let played = match played {
+            Ok(played) => played,
+            Err(e) => {
+                stop.store(true, Ordering::Relaxed);
+                error.get_or_insert(e);
+                continue;
+            }
+        };
+        let (state, termination) = played
+            .game
+            .result()
+            .expect("Games are played until they end");
+        match state {
+            GameState::Victory(color) if color_index(color) == played.white => score.wins += 1,
+            GameState::Victory(_) => score.losses += 1,
+            _ => score.draws += 1,
+        }
+        if let Some(pgn) = &mut pgn {
+            writeln!(pgn, "{}", played.game.to_pgn())?;
+        }
+        let names = [
+            &options.engines[played.white].name,
+            &options.engines[1 - played.white].name,
+        ];
+        writeln!(
+            out,
+            "Finished game {} ({} vs {}): {} {{{termination}}}",
+            played.index + 1,
+            names[0],
+            names[1],
+            game::result_str(state)
+        )?;
+        report(&options, &score, out)?;
+        if options
+            .sprt
+            .is_some_and(|sprt| sprt.verdict(&score) != Verdict::Continue)
+        {
+            stop.store(true, Ordering::Relaxed);
+        }
+    }
+    for worker in workers {
+        worker.join().expect("Match thread panicked");
+    }
+    match error {
+        Some(e) => Err(e),
+        None => Ok(score),
+    }
+}
+
+/// Writes the score of the match so far, the Elo difference it suggests and the SPRT's verdict.
+fn report(options: &Options, score: &Score, out: &mut impl Write) -> Result<(), Error> {
+    writeln!(
+        out,
+        "Score of {} vs {}: {} - {} - {} [{:.3}] {}",
+        options.engines[0].name,
+        options.engines[1].name,
+        score.wins,
+        score.losses,
+        score.draws,
+        score.ratio(),
+        score.games()
+    )?;
+    match score.elo() {
+        Some((elo, error)) => writeln!(out, "Elo difference: {elo:.1} +/- {error:.1}")?,
+        None => writeln!(out, "Elo difference: unknown")?,
+    }
+    if let Some(sprt) = options.sprt {
+        let (lower, upper) = sprt.bounds();
+        writeln!(
+            out,
+            "SPRT: llr {:.2} ({lower:.2}, {upper:.2}) [{}, {}], {}",
+            sprt.llr(score),
+            sprt.elo0,
+            sprt.elo1,
+            sprt.verdict(score)
+        )?;
+    }
+    Ok(())
+}
+
+/// Plays games until every game has been started or the match is stopped,
+/// sending each to [`sender`] once it ends.
+fn play_games(
+    options: &Options,
+    next_game: &AtomicUsize,
+    stop: &AtomicBool,
+    sender: &mpsc::Sender<Result<Played, Error>>,
+) -> Result<(), Error> {
+    let mut players = [
+        spawn_player(&options.engines[0])?,
+        spawn_player(&options.engines[1])?,
+    ];
+    loop {
+        let index = next_game.fetch_add(1, Ordering::Relaxed);
+        if index >= options.games || stop.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let opening = &options.openings[index / 2 % options.openings.len()];
+        let white = index % 2;
+        let game = play_game(&mut players, white, opening, options, index)?;
+        if sender.send(Ok(Played { index, white, game })).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+/// Plays the game numbered [`index`] from [`opening`,] with the player at [`white`] playing white.
+fn play_game(
+    players: &mut [Box<dyn Player>; 2],
+    white: usize,
+    opening: &Board,
+    options: &Options,
+    index: usize,
+) -> Result<Game, Error> {
+    let mut game = Game::new(opening);
+    game.set_tag("Event", "tnewt match");
+    game.set_tag("Round", &(index + 1).to_string());
+    game.set_tag("White", &options.engines[white].name);
+    game.set_tag("Black", &options.engines[1 - white].name);
+    for player in players.iter_mut() {
+        player.new_game()?;
+    }
+
+    // The time left on each color's clock
+    let mut clocks = options.time_control.map(|tc| [tc.base; 2]);
+    let (_, termination) = loop {
+        if let Some(result) = game.result() {
+            break result;
+        }
+        if game.moves().len() >= options.max_plies {
+            game.set_result(GameState::Draw, Termination::Adjudication);
+            continue;
+        }
+        let turn = game.board().state().turn();
+        let engine = match turn {
+            Color::White => white,
+            Color::Black => 1 - white,
+        };
+        let spec = &options.engines[engine];
+        let limits = SearchLimits {
+            depth: spec.limits.depth.or(options.limits.depth),
+            nodes: spec.limits.nodes.or(options.limits.nodes),
+            movetime: spec.limits.movetime.or(options.limits.movetime),
+            wtime: clocks.map(|clocks| clocks[0]),
+            btime: clocks.map(|clocks| clocks[1]),
+            winc: options.time_control.map(|tc| tc.increment),
+            binc: options.time_control.map(|tc| tc.increment),
+            ..SearchLimits::default()
+        };
+
+        let start = Instant::now();
+        let mov = players[engine].best_move(&game, &limits)?;
+        if let (Some(clocks), Some(tc)) = (&mut clocks, options.time_control) {
+            let clock = &mut clocks[color_index(turn)];
+            match clock.checked_sub(start.elapsed()) {
+                Some(left) => *clock = left + tc.increment,
+                None => {
+                    game.set_result(GameState::Victory(turn.opposite()), Termination::Time);
+                    continue;
+                }
+            }
+        }
+        game.play(&mov);
+    };
+    let pgn_termination = match termination {
+        Termination::Time => "time forfeit",
+        Termination::Adjudication => "adjudication",
+        _ => "normal",
+    };
+    game.set_tag("Termination", pgn_termination);
+    Ok(game)
+}
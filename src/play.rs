@@ -0,0 +1,265 @@
+use std::io::{BufRead, Write};
+use std::time::Duration;
+
+use tnewt_board::board::{Board, GameState};
+use tnewt_board::color::Color;
+use tnewt_board::game::{self, Game, Termination};
+use tnewt_board::mov::Move;
+use tnewt_board::san;
+use tnewt_board::search::{SearchLimits, SearchResult};
+use tnewt_board::smp::LazySmp;
+
+use crate::background::BackgroundSearch;
+use crate::cli::Error;
+
+/// The time the engine thinks about each move unless told otherwise.
+pub const DEFAULT_MOVE_TIME: Duration = Duration::from_secs(1);
+/// The engine accepts a draw offer once its position is worse than this, in centipawns.
+const DRAW_ACCEPT_SCORE: i32 = -50;
+
+const HELP: &str = "\
+Enter a move in SAN (e.g. `Nf3`, `exd5`, `O-O`, `e8=Q`) or UCI (e.g. `g1f3`), or a command:
+  board        show the board again
+  undo         take back your last move and the engine's reply
+  hint         suggest a move
+  draw         offer a draw
+  resign       resign the game
+  save <path>  save the game as PGN
+  quit         leave the game";
+
+pub struct Options {
+    /// The color the human plays.
+    pub human: Color,
+    /// The limits of the engine's search for each of its moves and for hints.
+    pub limits: SearchLimits,
+    pub start: Board,
+}
+
+/// A game between a human entering moves on the terminal and the engine.
+struct Session {
+    game: Game,
+    /// `None` only while a search is running.
+    smp: Option<LazySmp>,
+    human: Color,
+    limits: SearchLimits,
+}
+
+/// Plays a game against the engine, reading the human's moves and commands from
+/// [`input`] and showing the board from their side on [`out`,] until they quit
+/// or the input ends. Returns the game, which may be over or not.
+///
+/// # Errors
+///
+/// This function will return an error if [`input`] can't be read, or [`out`]
+/// or a saved game can't be written.
+pub fn run(mut input: impl BufRead, out: &mut impl Write, options: Options) -> Result<Game, Error> {
+    let mut game = Game::new(&options.start);
+    let engine_name = format!("tnewt {}", env!("CARGO_PKG_VERSION"));
+    let (white, black) = match options.human {
+        Color::White => ("Human", engine_name.as_str()),
+        Color::Black => (engine_name.as_str(), "Human"),
+    };
+    game.set_tag("Event", "Casual game");
+    game.set_tag("White", white);
+    game.set_tag("Black", black);
+    let mut session = Session {
+        game,
+        smp: Some(LazySmp::default()),
+        human: options.human,
+        limits: options.limits,
+    };
+
+    writeln!(out, "Type `help` for the commands.")?;
+    session.show(out)?;
+    let mut line = String::new();
+    loop {
+        if session.is_engine_turn() {
+            session.engine_move(out)?;
+            continue;
+        }
+        write!(out, "> ")?;
+        out.flush()?;
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            writeln!(out)?;
+            break;
+        }
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            [] => {}
+            ["help"] => writeln!(out, "{HELP}")?,
+            ["board"] => session.show(out)?,
+            ["undo" | "takeback"] => session.takeback(out)?,
+            ["hint"] => session.hint(out)?,
+            ["draw"] => session.offer_draw(out)?,
+            ["resign"] => session.resign(out)?,
+            ["save", path] => {
+                std::fs::write(path, session.game.to_pgn())?;
+                writeln!(out, "Saved the game to {path}")?;
+            }
+            ["quit" | "exit"] => break,
+            [text] => session.human_move(text, out)?,
+            _ => writeln!(
+                out,
+                "Unknown command `{}`, type `help` for the commands.",
+                line.trim()
+            )?,
+        }
+    }
+    Ok(session.game)
+}
+
+impl Session {
+    fn is_over(&self) -> bool {
+        self.game.result().is_some()
+    }
+
+    fn is_engine_turn(&self) -> bool {
+        !self.is_over() && self.game.board().state().turn() != self.human
+    }
+
+    /// Searches the current position on a thread with a large enough stack.
+    fn search(&mut self) -> SearchResult {
+        let smp = self.smp.take().expect("No search is running");
+        let search = BackgroundSearch::start(
+            smp,
+            self.game.board().clone(),
+            self.limits.clone(),
+            |_, _, _| {},
+            |_| {},
+        );
+        let (smp, result) = search.join();
+        self.smp = Some(smp);
+        result.expect("The search was not discarded")
+    }
+
+    fn engine_move(&mut self, out: &mut impl Write) -> Result<(), Error> {
+        let mov = self
+            .search()
+            .best_move
+            .expect("The engine has a legal move while the game is being played");
+        writeln!(out, "tnewt plays {}", san::to_san(self.game.board(), &mov))?;
+        self.play(&mov, out)
+    }
+
+    fn human_move(&mut self, text: &str, out: &mut impl Write) -> Result<(), Error> {
+        if self.is_over() {
+            writeln!(
+                out,
+                "The game is over, `undo` to take back moves or `quit` to leave."
+            )?;
+            return Ok(());
+        }
+        let mut board = self.game.board().clone();
+        match board
+            .move_from_san(text)
+            .or_else(|_| board.move_from_uci(text))
+        {
+            Ok(mov) => self.play(&mov, out),
+            Err(_) => Ok(writeln!(
+                out,
+                "Illegal move `{text}`, type `help` for the commands."
+            )?),
+        }
+    }
+
+    fn play(&mut self, mov: &Move, out: &mut impl Write) -> Result<(), Error> {
+        self.game.play(mov);
+        self.show(out)?;
+        self.announce_result(out)
+    }
+
+    /// Takes back the human's last move, along with the engine's reply if it made one.
+    fn takeback(&mut self, out: &mut impl Write) -> Result<(), Error> {
+        let plies = match self.game.board().state().turn() == self.human {
+            true => 2,
+            false => 1,
+        };
+        if self.game.moves().len() < plies {
+            writeln!(out, "There is no move of yours to take back.")?;
+            return Ok(());
+        }
+        for _ in 0..plies {
+            self.game.takeback();
+        }
+        self.show(out)
+    }
+
+    fn hint(&mut self, out: &mut impl Write) -> Result<(), Error> {
+        if self.is_over() {
+            writeln!(out, "The game is over.")?;
+            return Ok(());
+        }
+        let result = self.search();
+        if let Some(mov) = result.best_move {
+            writeln!(out, "Hint: {}", san::to_san(self.game.board(), &mov))?;
+        }
+        Ok(())
+    }
+
+    /// Offers the engine a draw, which it accepts if it thinks it is worse.
+    fn offer_draw(&mut self, out: &mut impl Write) -> Result<(), Error> {
+        if self.is_over() {
+            writeln!(out, "The game is over.")?;
+            return Ok(());
+        }
+        // The score is from the human's side, as it is their turn
+        let engine_score = -self.search().score;
+        if engine_score > DRAW_ACCEPT_SCORE {
+            writeln!(out, "tnewt declines the draw.")?;
+            return Ok(());
+        }
+        writeln!(out, "tnewt accepts the draw.")?;
+        self.game
+            .set_result(GameState::Draw, Termination::Agreement);
+        self.announce_result(out)
+    }
+
+    fn resign(&mut self, out: &mut impl Write) -> Result<(), Error> {
+        if self.is_over() {
+            writeln!(out, "The game is over.")?;
+            return Ok(());
+        }
+        let winner = self.human.opposite();
+        self.game
+            .set_result(GameState::Victory(winner), Termination::Resignation);
+        self.announce_result(out)
+    }
+
+    /// Shows the board from the human's side, with the last move and who is to move.
+    fn show(&self, out: &mut impl Write) -> Result<(), Error> {
+        let board = self.game.board();
+        writeln!(out)?;
+        writeln!(out, "{}", board.diagram(self.human))?;
+        writeln!(out)?;
+        if let Some(san) = self.game.san_moves().last() {
+            writeln!(out, "Last move: {san}")?;
+        }
+        let check = if board.in_check() { ", in check" } else { "" };
+        writeln!(out, "{} to move{check}", color_name(board.state().turn()))?;
+        Ok(())
+    }
+
+    fn announce_result(&self, out: &mut impl Write) -> Result<(), Error> {
+        let Some((state, termination)) = self.game.result() else {
+            return Ok(());
+        };
+        let outcome = match state {
+            GameState::Victory(color) => format!("{} wins", color_name(color)),
+            _ => "Draw".to_string(),
+        };
+        writeln!(
+            out,
+            "{outcome} by {termination} ({})",
+            game::result_str(state)
+        )?;
+        Ok(())
+    }
+}
+
+fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::White => "White",
+        Color::Black => "Black",
+    }
+}
@@ -0,0 +1,135 @@
+/// The z-score of a two-sided 95% confidence interval.
+const Z_95: f64 = 1.959_963_985;
+
+/// The results of a match from one engine's side.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Score {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl Score {
+    #[must_use]
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// The points scored per game, where a win is worth 1 and a draw 1/2.
+    #[must_use]
+    pub fn ratio(&self) -> f64 {
+        (f64::from(self.wins) + f64::from(self.draws) / 2.0) / f64::from(self.games())
+    }
+
+    /// The variance of the points scored in a single game.
+    fn variance(&self) -> f64 {
+        let ratio = self.ratio();
+        let games = f64::from(self.games());
+        (f64::from(self.wins) * (1.0 - ratio).powi(2)
+            + f64::from(self.draws) * (0.5 - ratio).powi(2)
+            + f64::from(self.losses) * ratio.powi(2))
+            / games
+    }
+
+    /// The Elo difference the score suggests, and the half width of its 95%
+    /// confidence interval, or `None` until the engine has both scored and
+    /// dropped points.
+    ///
+    /// See: [Match Statistics](https://www.chessprogramming.org/Match_Statistics)
+    #[must_use]
+    pub fn elo(&self) -> Option<(f64, f64)> {
+        let ratio = self.ratio();
+        if !(ratio > 0.0 && ratio < 1.0) {
+            return None;
+        }
+        let margin = Z_95 * (self.variance() / f64::from(self.games())).sqrt();
+        let low = elo_from_ratio((ratio - margin).max(f64::EPSILON));
+        let high = elo_from_ratio((ratio + margin).min(1.0 - f64::EPSILON));
+        Some((elo_from_ratio(ratio), (high - low) / 2.0))
+    }
+}
+
+/// The Elo difference at which the stronger player is expected to score [`ratio`.]
+fn elo_from_ratio(ratio: f64) -> f64 {
+    400.0 * (ratio / (1.0 - ratio)).log10()
+}
+
+/// The points per game expected from the player who is [`elo`] stronger.
+fn ratio_from_elo(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+/// Whether a sequential probability ratio test has decided between its hypotheses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// The engine is no more than `elo0` stronger.
+    AcceptH0,
+    /// The engine is at least `elo1` stronger.
+    AcceptH1,
+    Continue,
+}
+
+impl std::fmt::Display for Verdict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Verdict::AcceptH0 => "H0 accepted",
+            Verdict::AcceptH1 => "H1 accepted",
+            Verdict::Continue => "continue",
+        })
+    }
+}
+
+/// A sequential probability ratio test of whether an engine is `elo0` or `elo1`
+/// stronger than its opponent, which stops a match as soon as the games played
+/// tell them apart with the error rates `alpha` and `beta`.
+///
+/// See: [Sequential Probability Ratio Test](https://www.chessprogramming.org/Sequential_Probability_Ratio_Test)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    /// The probability of accepting H1 when H0 is true.
+    pub alpha: f64,
+    /// The probability of accepting H0 when H1 is true.
+    pub beta: f64,
+}
+
+impl Sprt {
+    /// The log-likelihood ratios at which H0 and H1 are accepted.
+    #[must_use]
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    /// The log-likelihood ratio of H1 over H0 given [`score`,] approximating the
+    /// points of each game as normally distributed.
+    #[must_use]
+    pub fn llr(&self, score: &Score) -> f64 {
+        if score.games() == 0 {
+            return 0.0;
+        }
+        let variance = score.variance();
+        if variance == 0.0 {
+            return 0.0;
+        }
+        let (ratio0, ratio1) = (ratio_from_elo(self.elo0), ratio_from_elo(self.elo1));
+        f64::from(score.games()) * (ratio1 - ratio0) * (2.0 * score.ratio() - ratio0 - ratio1)
+            / (2.0 * variance)
+    }
+
+    #[must_use]
+    pub fn verdict(&self, score: &Score) -> Verdict {
+        let llr = self.llr(score);
+        let (lower, upper) = self.bounds();
+        if llr >= upper {
+            Verdict::AcceptH1
+        } else if llr <= lower {
+            Verdict::AcceptH0
+        } else {
+            Verdict::Continue
+        }
+    }
+}
@@ -0,0 +1,424 @@
+use std::io::{BufRead, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tnewt_board::board::{Algorithm, Board};
+use tnewt_board::polyglot::{Book, Keys, Selection};
+use tnewt_board::search::{self, SearchLimits, SearchResult};
+use tnewt_board::smp::LazySmp;
+use tnewt_board::tablebase::Tablebases;
+use tnewt_board::tt;
+
+use crate::background::{new_board, write_line, BackgroundSearch};
+
+const MAX_HASH_MB: usize = 65_536;
+const MAX_THREADS: usize = 256;
+const MAX_MULTI_PV: usize = 256;
+const DEFAULT_MOVE_OVERHEAD_MS: u64 = 10;
+const MAX_MOVE_OVERHEAD_MS: u64 = 5000;
+/// The search currently running on its own thread.
+struct RunningSearch {
+    search: BackgroundSearch,
+    /// The limits to search with once a ponder search is told the opponent played the
+    /// expected move.
+    ponder_limits: Option<SearchLimits>,
+}
+
+/// The options of the opening book, which is only used once it is enabled and
+/// both of its files are set.
+#[derive(Default)]
+struct BookOptions {
+    own_book: bool,
+    file: String,
+    /// The file listing the random numbers of Polyglot keys, see [`Keys::parse`.]
+    keys_file: String,
+    selection: Selection,
+}
+
+/// An engine speaking the Universal Chess Interface, which reads commands from a GUI
+/// and writes its replies back, while searching on another thread.
+///
+/// See: [UCI](https://www.chessprogramming.org/UCI)
+pub struct Engine<W: Write + Send + 'static> {
+    output: Arc<Mutex<W>>,
+    board: Board,
+    /// `None` while the search is running on its own thread.
+    smp: Option<LazySmp>,
+    running: Option<RunningSearch>,
+    move_overhead: Duration,
+    book: BookOptions,
+}
+
+impl<W: Write + Send + 'static> Engine<W> {
+    #[must_use]
+    pub fn new(output: W) -> Self {
+        Engine {
+            output: Arc::new(Mutex::new(output)),
+            board: new_board(),
+            smp: Some(LazySmp::default()),
+            running: None,
+            move_overhead: Duration::from_millis(DEFAULT_MOVE_OVERHEAD_MS),
+            book: BookOptions::default(),
+        }
+    }
+
+    /// Handles a single command, returning `false` once the engine should quit.
+    pub fn handle(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            [] => {}
+            ["uci"] => self.uci(),
+            ["isready"] => write_line(&self.output, "readyok"),
+            ["ucinewgame"] => {
+                self.smp_mut().clear();
+                self.board = new_board();
+            }
+            ["position", args @ ..] => self.position(args),
+            ["setoption", args @ ..] => self.set_option(args),
+            ["go", args @ ..] => self.go(args),
+            ["stop"] => self.stop(),
+            ["ponderhit"] => self.ponderhit(),
+            ["quit"] => {
+                self.stop();
+                return false;
+            }
+            ["debug" | "register", ..] => {}
+            _ => write_line(
+                &self.output,
+                &format!("info string Unknown command: {line}"),
+            ),
+        }
+        true
+    }
+
+    /// Waits for the running search to finish on its own, or stops it if it never
+    /// would, and returns the output.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the search thread panicked.
+    pub fn finish(mut self) -> W {
+        match &self.running {
+            Some(running) if running.search.is_infinite() => self.stop(),
+            _ => self.join(),
+        }
+        let output = Arc::try_unwrap(self.output)
+            .unwrap_or_else(|_| panic!("The search thread has been joined"));
+        output.into_inner().expect("Output lock poisoned")
+    }
+
+    fn uci(&self) {
+        let lines = [
+            format!("id name tnewt {}", env!("CARGO_PKG_VERSION")),
+            "id author tj-moody".to_string(),
+            format!(
+                "option name Hash type spin default {} min 1 max {MAX_HASH_MB}",
+                tt::DEFAULT_SIZE_MB
+            ),
+            format!("option name Threads type spin default 1 min 1 max {MAX_THREADS}"),
+            format!("option name MultiPV type spin default 1 min 1 max {MAX_MULTI_PV}"),
+            format!(
+                "option name Move Overhead type spin default {DEFAULT_MOVE_OVERHEAD_MS} min 0 max {MAX_MOVE_OVERHEAD_MS}"
+            ),
+            "option name Ponder type check default false".to_string(),
+            "option name OwnBook type check default false".to_string(),
+            "option name Book File type string default <empty>".to_string(),
+            "option name Book Keys File type string default <empty>".to_string(),
+            "option name Book Selection type combo default Weighted var Weighted var Best"
+                .to_string(),
+            "option name Tablebase Path type string default <empty>".to_string(),
+            "uciok".to_string(),
+        ];
+        write_line(&self.output, &lines.join("\n"));
+    }
+
+    /// Handles `position (startpos | fen <fen>) [moves <move>...]`, leaving the
+    /// position unchanged if any part of it is invalid.
+    fn position(&mut self, args: &[&str]) {
+        let moves_index = args.iter().position(|&arg| arg == "moves");
+        let (setup, moves) = match moves_index {
+            Some(index) => (&args[..index], &args[index + 1..]),
+            None => (args, &[][..]),
+        };
+        let mut board = match setup {
+            ["startpos"] => new_board(),
+            ["fen", fields @ ..] if (4..=6).contains(&fields.len()) => {
+                // The move counters are optional in positions sent by some GUIs
+                let fen = [fields, &["0", "1"][fields.len() - 4..]].concat().join(" ");
+                match Board::from_fen(&fen) {
+                    Ok(mut board) => {
+                        board.set_algorithm(Algorithm::Unmove);
+                        board
+                    }
+                    Err(e) => {
+                        self.info_string(&format!("Invalid FEN {fen}: {e}"));
+                        return;
+                    }
+                }
+            }
+            _ => {
+                self.info_string(&format!("Invalid position: {}", args.join(" ")));
+                return;
+            }
+        };
+        for uci in moves {
+            match board.move_from_uci(uci) {
+                Ok(mov) => board.make_move(&mov),
+                Err(e) => {
+                    self.info_string(&format!("{e}"));
+                    return;
+                }
+            }
+        }
+        self.stop();
+        self.board = board;
+    }
+
+    /// Handles `setoption name <name> [value <value>]`, where names are case insensitive.
+    fn set_option(&mut self, args: &[&str]) {
+        let value_index = args
+            .iter()
+            .position(|&arg| arg == "value")
+            .unwrap_or(args.len());
+        let name = args[..value_index]
+            .iter()
+            .skip_while(|&&arg| arg == "name")
+            .copied()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase();
+        let value = args.get(value_index + 1..).unwrap_or_default().join(" ");
+
+        let value = match value.as_str() {
+            "<empty>" => String::new(),
+            _ => value,
+        };
+
+        match (name.as_str(), value.parse::<usize>()) {
+            ("hash", Ok(mb)) => self.smp_mut().set_hash(mb.clamp(1, MAX_HASH_MB)),
+            ("threads", Ok(threads)) => self.smp_mut().set_threads(threads.min(MAX_THREADS)),
+            ("multipv", Ok(lines)) => self.smp_mut().set_multi_pv(lines.min(MAX_MULTI_PV)),
+            ("move overhead", Ok(ms)) => {
+                self.move_overhead = Duration::from_millis((ms as u64).min(MAX_MOVE_OVERHEAD_MS));
+            }
+            ("ponder", _) => {}
+            ("ownbook", _) => {
+                self.book.own_book = value == "true";
+                self.load_book();
+            }
+            ("book file", _) => {
+                self.book.file = value;
+                self.load_book();
+            }
+            ("book keys file", _) => {
+                self.book.keys_file = value;
+                self.load_book();
+            }
+            ("book selection", _) if value.eq_ignore_ascii_case("weighted") => {
+                self.book.selection = Selection::Weighted;
+                self.load_book();
+            }
+            ("book selection", _) if value.eq_ignore_ascii_case("best") => {
+                self.book.selection = Selection::Best;
+                self.load_book();
+            }
+            ("tablebase path", _) => self.load_tablebases(&value),
+            _ => self.info_string(&format!("Invalid option: {}", args.join(" "))),
+        }
+    }
+
+    /// Handles `go` with any of the standard limits, where `searchmoves` is followed
+    /// by the only moves to choose from.
+    fn go(&mut self, args: &[&str]) {
+        let mut limits = SearchLimits::default();
+        let mut ponder = false;
+        let mut tokens = args.iter();
+        while let Some(&token) = tokens.next() {
+            let mut value = || tokens.next().and_then(|value| value.parse::<u64>().ok());
+            let time = |ms: Option<u64>| ms.map(Duration::from_millis);
+            match token {
+                "wtime" => limits.wtime = time(value()),
+                "btime" => limits.btime = time(value()),
+                "winc" => limits.winc = time(value()),
+                "binc" => limits.binc = time(value()),
+                "movestogo" => limits.movestogo = value().map(|moves| moves as u32),
+                "depth" => limits.depth = value().map(|depth| depth as i32),
+                "nodes" => limits.nodes = value(),
+                // A mate in n moves is found within 2n - 1 plies
+                "mate" => limits.depth = value().map(|moves| (moves as i32 * 2 - 1).max(1)),
+                "movetime" => limits.movetime = time(value()),
+                "infinite" => limits.infinite = true,
+                "ponder" => ponder = true,
+                "searchmoves" => {
+                    // The moves go on until the next keyword, which is never a move
+                    let mut board = self.board.clone();
+                    while let Some(mov) = tokens
+                        .clone()
+                        .next()
+                        .and_then(|uci| board.move_from_uci(uci).ok())
+                    {
+                        limits.searchmoves.push(mov);
+                        tokens.next();
+                    }
+                }
+                _ => {}
+            }
+        }
+        for time in [&mut limits.wtime, &mut limits.btime, &mut limits.movetime] {
+            *time = time.map(|time| time.saturating_sub(self.move_overhead));
+        }
+
+        self.stop();
+        match ponder {
+            true => {
+                let ponder_limits = SearchLimits {
+                    infinite: true,
+                    ..SearchLimits::default()
+                };
+                self.start(ponder_limits, Some(limits));
+            }
+            false => self.start(limits, None),
+        }
+    }
+
+    /// Switches a ponder search to searching with the limits it was given, as the
+    /// opponent played the move it was pondering on.
+    fn ponderhit(&mut self) {
+        let Some(limits) = self
+            .running
+            .as_mut()
+            .and_then(|running| running.ponder_limits.take())
+        else {
+            return;
+        };
+        if let Some(running) = &self.running {
+            running.search.discard();
+        }
+        self.stop();
+        self.start(limits, None);
+    }
+
+    /// Starts searching the current position on another thread, which reports its
+    /// progress and best move to the output. Ponder searches search infinitely.
+    fn start(&mut self, limits: SearchLimits, ponder_limits: Option<SearchLimits>) {
+        let smp = self.smp.take().expect("No search is running");
+        let (info_output, best_move_output) = (Arc::clone(&self.output), Arc::clone(&self.output));
+        let search = BackgroundSearch::start(
+            smp,
+            self.board.clone(),
+            limits,
+            move |result, elapsed, smp| {
+                write_line(&info_output, &info(result, elapsed, smp.tt().hashfull()));
+            },
+            move |result| write_line(&best_move_output, &best_move(result)),
+        );
+        self.running = Some(RunningSearch {
+            search,
+            ponder_limits,
+        });
+    }
+
+    /// Stops the running search, if any, and waits for it to report its best move.
+    fn stop(&mut self) {
+        if let Some(running) = &self.running {
+            running.search.stop();
+        }
+        self.join();
+    }
+
+    fn join(&mut self) {
+        if let Some(running) = self.running.take() {
+            self.smp = Some(running.search.join().0);
+        }
+    }
+
+    /// Loads the book if it is enabled and both of its files are set, and unloads it otherwise.
+    fn load_book(&mut self) {
+        let options = &self.book;
+        let book =
+            match options.own_book && !options.file.is_empty() && !options.keys_file.is_empty() {
+                true => match Keys::read(&options.keys_file)
+                    .and_then(|keys| Book::open(&options.file, keys))
+                {
+                    Ok(book) => Some((Arc::new(book), options.selection)),
+                    Err(e) => {
+                        self.info_string(&format!("Failed to load the book: {e}"));
+                        None
+                    }
+                },
+                false => None,
+            };
+        self.smp_mut().set_book(book);
+    }
+
+    /// Loads the endgame tables in the directory at [`path`,] or unloads them if it is empty.
+    fn load_tablebases(&mut self, path: &str) {
+        let tablebases = match path {
+            "" => None,
+            path => match Tablebases::read_dir(path) {
+                Ok(tablebases) => {
+                    let count = tablebases.tables().count();
+                    self.info_string(&format!("Loaded {count} tables from {path}"));
+                    Some(Arc::new(tablebases))
+                }
+                Err(e) => {
+                    self.info_string(&format!("Failed to load the tablebases: {e}"));
+                    None
+                }
+            },
+        };
+        self.smp_mut().set_tablebases(tablebases);
+    }
+
+    fn smp_mut(&mut self) -> &mut LazySmp {
+        self.stop();
+        self.smp.as_mut().expect("The search has been joined")
+    }
+
+    fn info_string(&self, message: &str) {
+        write_line(&self.output, &format!("info string {message}"));
+    }
+}
+
+/// Reads commands from [`input`] until `quit` or the end of the input, writing
+/// the replies to [`output`,] and returns the output once every search has finished.
+///
+/// # Panics
+///
+/// Panics if [`input`] can't be read.
+pub fn run<R: BufRead, W: Write + Send + 'static>(input: R, output: W) -> W {
+    let mut engine = Engine::new(output);
+    for line in input.lines() {
+        let line = line.expect("Failed to read input");
+        if !engine.handle(&line) {
+            break;
+        }
+    }
+    engine.finish()
+}
+
+fn info(result: &SearchResult, elapsed: Duration, hashfull: usize) -> String {
+    let score = match search::mate_in(result.score) {
+        Some(moves) => format!("mate {moves}"),
+        None => format!("cp {}", result.score),
+    };
+    let ms = elapsed.as_millis().max(1);
+    let nps = u128::from(result.nodes) * 1000 / ms;
+    let pv: Vec<String> = result.pv.iter().map(ToString::to_string).collect();
+    format!(
+        "info depth {} multipv {} score {score} nodes {} nps {nps} hashfull {hashfull} time {} pv {}",
+        result.depth,
+        result.multipv,
+        result.nodes,
+        elapsed.as_millis(),
+        pv.join(" ")
+    )
+}
+
+fn best_move(result: &SearchResult) -> String {
+    match (result.best_move, result.pv.get(1)) {
+        (None, _) => "bestmove 0000".to_string(),
+        (Some(best_move), None) => format!("bestmove {best_move}"),
+        (Some(best_move), Some(ponder)) => format!("bestmove {best_move} ponder {ponder}"),
+    }
+}
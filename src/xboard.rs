@@ -0,0 +1,360 @@
+use std::io::{BufRead, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tnewt_board::board::{Algorithm, Board};
+use tnewt_board::color::Color;
+use tnewt_board::search::{self, SearchLimits, SearchResult};
+use tnewt_board::smp::LazySmp;
+
+use crate::background::{new_board, write_line, BackgroundSearch};
+
+/// Mate scores are reported as this plus the number of moves to mate.
+const MATE_SCORE: i32 = 100_000;
+
+/// The search currently running on its own thread.
+struct RunningSearch {
+    search: BackgroundSearch,
+    /// Whether the best move is played on the board once found, or the search is an analysis.
+    plays_move: bool,
+}
+
+/// An engine speaking the Chess Engine Communication Protocol, which keeps the
+/// game on its board, and thinks whenever it is its turn to move.
+///
+/// See: [Chess Engine Communication Protocol](https://www.chessprogramming.org/Chess_Engine_Communication_Protocol)
+pub struct Engine<W: Write + Send + 'static> {
+    output: Arc<Mutex<W>>,
+    board: Board,
+    /// `None` while the search is running on its own thread.
+    smp: Option<LazySmp>,
+    running: Option<RunningSearch>,
+    /// The color the engine plays, or `None` in force mode, where it plays neither.
+    engine_color: Option<Color>,
+    analyzing: bool,
+    /// Whether thinking output is sent while playing.
+    post: bool,
+    engine_time: Option<Duration>,
+    opponent_time: Option<Duration>,
+    increment: Duration,
+    /// The number of moves per time control, or `None` if the whole game is one.
+    moves_per_session: Option<u32>,
+    move_time: Option<Duration>,
+    max_depth: Option<i32>,
+}
+
+impl<W: Write + Send + 'static> Engine<W> {
+    #[must_use]
+    pub fn new(output: W) -> Self {
+        Engine {
+            output: Arc::new(Mutex::new(output)),
+            board: new_board(),
+            smp: Some(LazySmp::default()),
+            running: None,
+            engine_color: Some(Color::Black),
+            analyzing: false,
+            post: false,
+            engine_time: None,
+            opponent_time: None,
+            increment: Duration::ZERO,
+            moves_per_session: None,
+            move_time: None,
+            max_depth: None,
+        }
+    }
+
+    /// Handles a single command, returning `false` once the engine should quit.
+    pub fn handle(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            [] | ["xboard" | "accepted" | "rejected" | "." | "hard" | "easy" | "random", ..] => {}
+            ["computer" | "name" | "rating" | "ics" | "draw" | "hint" | "bk", ..] => {}
+            ["protover", ..] => self.write(&format!(
+                "feature myname=\"tnewt {}\" ping=1 setboard=1 usermove=1 analyze=1 colors=0 \
+                 sigint=0 sigterm=0 reuse=1 memory=1 smp=1 done=1",
+                env!("CARGO_PKG_VERSION")
+            )),
+            ["new"] => {
+                self.interrupt();
+                self.smp_mut().clear();
+                self.board = new_board();
+                self.engine_color = Some(Color::Black);
+                self.max_depth = None;
+                self.restart_analysis();
+            }
+            ["force"] => {
+                self.interrupt();
+                self.engine_color = None;
+            }
+            ["go"] => {
+                self.interrupt();
+                self.engine_color = Some(self.board.state().turn());
+                self.think();
+            }
+            ["?"] => self.stop(),
+            ["usermove", uci] => self.user_move(uci),
+            ["setboard", fen @ ..] => self.set_board(&fen.join(" ")),
+            ["undo"] => self.undo(1),
+            ["remove"] => self.undo(2),
+            ["result", ..] => {
+                self.interrupt();
+                self.engine_color = None;
+            }
+            ["time", centiseconds] => self.engine_time = parse_centiseconds(centiseconds),
+            ["otim", centiseconds] => self.opponent_time = parse_centiseconds(centiseconds),
+            ["level", moves_per_session, _base, increment] => {
+                self.moves_per_session = moves_per_session.parse().ok().filter(|&moves| moves > 0);
+                let increment = increment.parse::<f64>().unwrap_or_default().max(0.0);
+                self.increment = Duration::from_secs_f64(increment);
+            }
+            ["st", seconds] => self.move_time = seconds.parse().ok().map(Duration::from_secs),
+            ["sd", depth] => self.max_depth = depth.parse().ok(),
+            ["post"] => self.post = true,
+            ["nopost"] => self.post = false,
+            ["analyze"] => {
+                self.interrupt();
+                self.analyzing = true;
+                self.engine_color = None;
+                self.restart_analysis();
+            }
+            ["exit"] => {
+                self.interrupt();
+                self.analyzing = false;
+            }
+            ["ping", n] => {
+                self.wait();
+                self.write(&format!("pong {n}"));
+            }
+            ["memory", mb] => match mb.parse::<usize>() {
+                Ok(mb) => self.smp_mut().set_hash(mb.max(1)),
+                Err(_) => self.error("invalid memory size", line),
+            },
+            ["cores", threads] => match threads.parse::<usize>() {
+                Ok(threads) => self.smp_mut().set_threads(threads),
+                Err(_) => self.error("invalid number of cores", line),
+            },
+            ["quit"] => {
+                self.interrupt();
+                return false;
+            }
+            _ => self.error("unknown command", line),
+        }
+        true
+    }
+
+    /// Waits for the engine to play its move if it is thinking, and returns the output.
+    pub fn finish(mut self) -> W {
+        self.wait();
+        self.interrupt();
+        let output = Arc::try_unwrap(self.output)
+            .unwrap_or_else(|_| panic!("The search thread has been joined"));
+        output.into_inner().expect("Output lock poisoned")
+    }
+
+    /// Plays the opponent's move, after which the engine either thinks about its
+    /// reply or analyses the new position.
+    fn user_move(&mut self, uci: &str) {
+        self.wait();
+        self.interrupt();
+        match self.board.move_from_uci(uci) {
+            Ok(mov) => self.board.make_move(&mov),
+            Err(_) => {
+                self.write(&format!("Illegal move: {uci}"));
+                return;
+            }
+        }
+        self.restart_analysis();
+        self.think();
+    }
+
+    fn set_board(&mut self, fen: &str) {
+        self.interrupt();
+        match Board::from_fen(fen) {
+            Ok(mut board) => {
+                board.set_algorithm(Algorithm::Unmove);
+                self.board = board;
+            }
+            Err(e) => self.write(&format!("tellusererror Illegal position: {e}")),
+        }
+        self.restart_analysis();
+    }
+
+    /// Takes back up to [`plies`] moves, stopping at the position the game was set up from.
+    fn undo(&mut self, plies: usize) {
+        self.wait();
+        self.interrupt();
+        for _ in 0..plies {
+            if self.board.state().last_move().is_none() {
+                break;
+            }
+            self.board.unmake_move();
+        }
+        self.restart_analysis();
+    }
+
+    /// Starts thinking about the engine's move if it is its turn.
+    fn think(&mut self) {
+        if self.analyzing || self.engine_color != Some(self.board.state().turn()) {
+            return;
+        }
+        let limits = self.limits();
+        self.start(limits, true);
+    }
+
+    fn restart_analysis(&mut self) {
+        if !self.analyzing {
+            return;
+        }
+        let limits = SearchLimits {
+            infinite: true,
+            ..SearchLimits::default()
+        };
+        self.start(limits, false);
+    }
+
+    /// The limits of the engine's search, from the clocks and the time control.
+    fn limits(&self) -> SearchLimits {
+        let turn = self.board.state().turn();
+        let (wtime, btime) = match turn {
+            Color::White => (self.engine_time, self.opponent_time),
+            Color::Black => (self.opponent_time, self.engine_time),
+        };
+        let moves_played = self.board.state().fullmove_count().saturating_sub(1);
+        SearchLimits {
+            depth: self.max_depth,
+            movetime: self.move_time,
+            wtime,
+            btime,
+            winc: Some(self.increment),
+            binc: Some(self.increment),
+            movestogo: self
+                .moves_per_session
+                .map(|moves| moves - moves_played % moves),
+            ..SearchLimits::default()
+        }
+    }
+
+    /// Starts searching the current position on another thread, which sends its
+    /// thinking output and, unless analysing, plays its move once done.
+    fn start(&mut self, limits: SearchLimits, plays_move: bool) {
+        let smp = self.smp.take().expect("No search is running");
+        let (thinking_output, move_output) = (Arc::clone(&self.output), Arc::clone(&self.output));
+        let post = self.post || !plays_move;
+        let search = BackgroundSearch::start(
+            smp,
+            self.board.clone(),
+            limits,
+            move |result, elapsed, _| {
+                if post && result.multipv == 1 {
+                    write_line(&thinking_output, &thinking(result, elapsed));
+                }
+            },
+            move |result| {
+                if let (true, Some(best_move)) = (plays_move, result.best_move) {
+                    write_line(&move_output, &format!("move {best_move}"));
+                }
+            },
+        );
+        self.running = Some(RunningSearch { search, plays_move });
+    }
+
+    /// Makes the engine move now if it is thinking.
+    fn stop(&mut self) {
+        if let Some(running) = self.running.as_ref().filter(|running| running.plays_move) {
+            running.search.stop();
+        }
+        self.wait();
+    }
+
+    /// Waits for the engine to play its move if it is thinking, after which its
+    /// move is played on the board. An analysis keeps running.
+    fn wait(&mut self) {
+        if self
+            .running
+            .as_ref()
+            .is_some_and(|running| !running.plays_move)
+        {
+            return;
+        }
+        if let Some(running) = self.running.take() {
+            self.join(running);
+        }
+    }
+
+    /// Stops any search without playing its move, unless the move has already
+    /// been sent, in which case the GUI has played it and so must the engine.
+    fn interrupt(&mut self) {
+        if let Some(running) = self.running.take() {
+            running.search.discard();
+            running.search.stop();
+            self.join(running);
+        }
+    }
+
+    /// Waits for [`running`] to finish, and plays its move if it sent one.
+    fn join(&mut self, running: RunningSearch) {
+        let (smp, result) = running.search.join();
+        self.smp = Some(smp);
+        let best_move = result
+            .filter(|_| running.plays_move)
+            .and_then(|result| result.best_move);
+        if let Some(best_move) = best_move {
+            self.board.make_move(&best_move);
+        }
+    }
+
+    fn smp_mut(&mut self) -> &mut LazySmp {
+        self.interrupt();
+        self.smp.as_mut().expect("The search has been joined")
+    }
+
+    fn write(&self, line: &str) {
+        write_line(&self.output, line);
+    }
+
+    fn error(&self, kind: &str, command: &str) {
+        self.write(&format!("Error ({kind}): {command}"));
+    }
+}
+
+/// Reads commands from [`input`] until `quit` or the end of the input, writing
+/// the replies to [`output`,] and returns the output once the engine has moved.
+///
+/// # Panics
+///
+/// Panics if [`input`] can't be read.
+pub fn run<R: BufRead, W: Write + Send + 'static>(input: R, output: W) -> W {
+    let mut engine = Engine::new(output);
+    for line in input.lines() {
+        let line = line.expect("Failed to read input");
+        if !engine.handle(&line) {
+            break;
+        }
+    }
+    engine.finish()
+}
+
+fn parse_centiseconds(centiseconds: &str) -> Option<Duration> {
+    centiseconds
+        .parse::<u64>()
+        .ok()
+        .map(|centiseconds| Duration::from_millis(centiseconds * 10))
+}
+
+/// Formats [`result`] as a line of thinking output: the depth, the score in
+/// centipawns, the time in centiseconds, the nodes and the principal variation.
+fn thinking(result: &SearchResult, elapsed: Duration) -> String {
+    let score = match search::mate_in(result.score) {
+        Some(moves) if moves > 0 => MATE_SCORE + moves,
+        Some(moves) => -MATE_SCORE + moves,
+        None => result.score,
+    };
+    let pv: Vec<String> = result.pv.iter().map(ToString::to_string).collect();
+    format!(
+        "{} {score} {} {} {}",
+        result.depth,
+        elapsed.as_millis() / 10,
+        result.nodes,
+        pv.join(" ")
+    )
+}
@@ -1,7 +1,7 @@
 use color_eyre::eyre::Result;
 use colored::Colorize;
 
-use crate::{castling, color, coordinate, mov, piece};
+use crate::{castling, color, coordinate, mov, piece, zobrist};
 
 use color::Color;
 use coordinate::Coordinate;
@@ -75,9 +75,45 @@ pub enum Error {
     NoKing,
     UndoFromFirstMove,
     InvalidCastlingMove(usize),
+    InvalidCastlingChar(char),
     InvalidDirectionIndex(usize),
+    IllegalMove(String),
+    MissingFenFields(String),
 }
 
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::InvalidCoordinate(coordinate) => write!(f, "invalid square `{coordinate}`"),
+            Error::InvalidFenBoardLength(fen) => {
+                write!(f, "the board of FEN `{fen}` does not have 64 squares")
+            }
+            Error::InvalidHalfmoveStr(e) => write!(f, "invalid halfmove clock: {e}"),
+            Error::InvalidFullmoveStr(e) => write!(f, "invalid fullmove number: {e}"),
+            Error::InvalidColorStr(color) => {
+                write!(f, "invalid side to move `{color}`, expected `w` or `b`")
+            }
+            Error::InvalidPieceChar(c) => write!(f, "invalid piece `{c}`"),
+            Error::PieceFromEmptySquare => write!(f, "there is no piece on an empty square"),
+            Error::MoveEmptySquare => write!(f, "there is no piece to move on an empty square"),
+            Error::MoveOppositeColor => write!(f, "the piece to move is not of the side to move"),
+            Error::NoKing => write!(f, "a king is missing from the board"),
+            Error::UndoFromFirstMove => write!(f, "there is no move to undo"),
+            Error::InvalidCastlingMove(index) => {
+                write!(f, "invalid castling move to square {index}")
+            }
+            Error::InvalidCastlingChar(c) => write!(f, "invalid castling right `{c}`"),
+            Error::InvalidDirectionIndex(index) => write!(f, "invalid direction index {index}"),
+            Error::IllegalMove(mov) => write!(f, "illegal move `{mov}`"),
+            Error::MissingFenFields(fen) => {
+                write!(f, "FEN `{fen}` does not have 6 fields")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
 #[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
 pub struct State {
     turn: Color,
@@ -90,6 +126,44 @@ pub struct State {
     last_move: Option<Move>,
     last_ep_taken_index: Option<usize>,
     king_indices: KingIndices,
+    /// The Zobrist hash of the position, kept up to date as moves are made.
+    hash: u64,
+}
+
+impl State {
+    pub fn turn(&self) -> Color {
+        self.turn
+    }
+
+    pub fn game_state(&self) -> GameState {
+        self.game_state
+    }
+
+    pub fn castling_state(&self) -> castling::State {
+        self.castling_state
+    }
+
+    pub fn ep_index(&self) -> Option<usize> {
+        self.ep_index
+    }
+
+    pub fn halfmove_clock(&self) -> u32 {
+        self.halfmove_clock
+    }
+
+    pub fn fullmove_count(&self) -> u32 {
+        self.fullmove_count
+    }
+
+    /// The move that led to this position. Only tracked by the [`Unmove`] algorithm.
+    pub fn last_move(&self) -> Option<Move> {
+        self.last_move
+    }
+
+    /// The Zobrist hash of the position, see [`Board::hash`.]
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
@@ -108,6 +182,12 @@ pub struct Board {
     pub store_history: bool,
 }
 
+impl Default for Board {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Board {
     fn slider_gen_moves(&self, moves: &mut Vec<Move>, start_index: usize, piece: Piece) {
         let start_square = self.squares[start_index];
@@ -238,46 +318,39 @@ impl Board {
     }
 
     fn gen_castling_moves(&self, moves: &mut Vec<Move>) {
-        self.state
-            .castling_state
-            .get_moves(&self.state.turn)
-            .into_iter()
-            .for_each(|mov| {
-                let castling_squares = castling::get_squares(&mov)
-                    .expect("Generate invalid castling move from `castling_state.gen_moves()");
-                for &empty_index in castling_squares.empty_indices {
-                    if self.squares[empty_index].is_some() {
-                        return;
-                    };
-                }
-                match self.squares[castling_squares.king_start_index] {
-                    Some(piece) => match piece.kind {
-                        Kind::King => {
-                            if piece.color != self.state.turn {
-                                return;
-                            }
-                        }
-                        _ => return,
-                    },
-                    None => return,
-                };
-                match self.squares[castling_squares.rook_start_index] {
-                    Some(piece) => match piece.kind {
-                        Kind::Rook => {
-                            if piece.color != self.state.turn {
-                                return;
-                            }
-                        }
-                        _ => return,
-                    },
-                    None => return,
-                };
-                moves.push(mov);
-            });
+        let color = self.state.turn;
+        let king_index = self.state.king_indices.get(color);
+        if king_index / 8 != castling::back_rank_index(color) / 8 {
+            return;
+        }
+        for mov in self.state.castling_state.get_moves(&color, king_index) {
+            let Some(castling_squares) = self.state.castling_state.get_squares(&mov, &color) else {
+                continue;
+            };
+            if self.squares[castling_squares.rook_start_index]
+                != Some(Piece {
+                    kind: Kind::Rook,
+                    color,
+                })
+            {
+                continue;
+            }
+            if castling_squares
+                .empty_indices()
+                .iter()
+                .any(|&index| self.squares[index].is_some())
+            {
+                continue;
+            }
+            if self.is_attacked(&castling_squares.check_indices(), color) {
+                continue;
+            }
+            moves.push(mov);
+        }
     }
 
-    fn gen_pseudo_legal_moves(&self) -> Vec<Move> {
-        let mut moves = Vec::with_capacity(50);
+    /// Generates the moves of every piece of the color whose turn it is, except castling.
+    fn gen_piece_moves(&self, moves: &mut Vec<Move>) {
         for index in 0..64 {
             let square = self.squares[index];
             if let Some(piece) = square {
@@ -286,20 +359,38 @@ impl Board {
                 }
                 match piece.kind {
                     Kind::Bishop | Kind::Rook | Kind::Queen | Kind::King => {
-                        self.slider_gen_moves(&mut moves, index, piece)
+                        self.slider_gen_moves(moves, index, piece)
                     }
-                    Kind::Knight => self.knight_gen_moves(&mut moves, index),
-                    Kind::Pawn => self.pawn_gen_moves(&mut moves, index),
+                    Kind::Knight => self.knight_gen_moves(moves, index),
+                    Kind::Pawn => self.pawn_gen_moves(moves, index),
                 };
             }
         }
+    }
+
+    fn gen_pseudo_legal_moves(&self) -> Vec<Move> {
+        let mut moves = Vec::with_capacity(50);
+        self.gen_piece_moves(&mut moves);
         self.gen_castling_moves(&mut moves);
 
         moves
     }
 
+    /// Castling never captures, so only the moves of each piece need to be filtered.
+    fn gen_pseudo_legal_captures(&self) -> Vec<Move> {
+        let mut moves = Vec::with_capacity(50);
+        self.gen_piece_moves(&mut moves);
+        moves.retain(|mov| {
+            self.squares[mov.target_index].is_some()
+                || mov.promotion_kind.is_some()
+                || (Some(mov.target_index) == self.state.ep_index
+                    && self.squares[mov.start_index].is_some_and(|piece| piece.kind == Kind::Pawn))
+        });
+        moves
+    }
+
     /// Only checks if the color whose turn it is has their piece at `index` being attacked
-    fn is_attacked(&mut self, indices: &[usize], king_color: Color) -> bool {
+    pub(crate) fn is_attacked(&self, indices: &[usize], king_color: Color) -> bool {
         for &start_index in indices {
             for &knight_index in KNIGHT_THREAT_INDICES[start_index] {
                 if let Some(piece) = self.squares[knight_index] {
@@ -367,40 +458,53 @@ impl Board {
     /// Generates a Vec of all legal moves in the current position.
     pub fn gen_legal_moves(&mut self) -> Vec<Move> {
         let mut moves = self.gen_pseudo_legal_moves();
-        moves.retain(|mov| {
-            let is_castling = mov.is_castling(&self.squares).unwrap();
+        self.retain_legal(&mut moves);
+        moves
+    }
 
-            match self.algorithm {
-                Algorithm::Clone => {
-                    let mut board = self.clone();
-                    board.make_move(mov);
+    /// Generates a Vec of all legal captures and promotions in the current position,
+    /// including en passant captures and underpromotions.
+    pub fn gen_legal_captures(&mut self) -> Vec<Move> {
+        let mut moves = self.gen_pseudo_legal_captures();
+        self.retain_legal(&mut moves);
+        moves
+    }
 
-                    let king_index = board.state.king_indices.get(board.state.turn.opposite());
-                    let check_indices = if is_castling {
-                        castling::get_squares(mov).unwrap().check_indices.to_vec()
-                    } else {
-                        vec![king_index]
-                    };
+    /// Returns the legal move written as [`uci`] in coordinate notation, e.g. "e2e4" or "e7e8q".
+    /// Castling is written as the king moving two squares, or as the king capturing
+    /// its own rook in Chess960.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if no legal move is written as [`uci`.]
+    pub fn move_from_uci(&mut self, uci: &str) -> Result<Move, Error> {
+        self.gen_legal_moves()
+            .into_iter()
+            .find(|mov| mov.to_string() == uci)
+            .ok_or_else(|| Error::IllegalMove(uci.to_string()))
+    }
 
-                    !board.is_attacked(&check_indices, board.state.turn.opposite())
-                }
-                Algorithm::Unmove => {
-                    self.make_move(mov);
+    /// Removes the moves that would leave the king of the color whose turn it is in check.
+    fn retain_legal(&mut self, moves: &mut Vec<Move>) {
+        // Castling moves through attacked squares are already excluded when generated,
+        // so only the king's final square needs to be checked here.
+        moves.retain(|mov| match self.algorithm {
+            Algorithm::Clone => {
+                let mut board = self.clone();
+                board.make_move(mov);
 
-                    let king_index = self.state.king_indices.get(self.state.turn.opposite());
-                    let check_indices = if is_castling {
-                        castling::get_squares(mov).unwrap().check_indices.to_vec()
-                    } else {
-                        vec![king_index]
-                    };
+                let king_index = board.state.king_indices.get(board.state.turn.opposite());
+                !board.is_attacked(&[king_index], board.state.turn.opposite())
+            }
+            Algorithm::Unmove => {
+                self.make_move(mov);
 
-                    let is_attacked = self.is_attacked(&check_indices, self.state.turn.opposite());
-                    self.unmake_move();
-                    !is_attacked
-                }
+                let king_index = self.state.king_indices.get(self.state.turn.opposite());
+                let is_attacked = self.is_attacked(&[king_index], self.state.turn.opposite());
+                self.unmake_move();
+                !is_attacked
             }
         });
-        moves
     }
 
     /// Returns a playable board at the first move, except with position specified by [`chars`.]
@@ -454,14 +558,14 @@ impl Board {
                 _ => return Err(Error::InvalidPieceChar(*piece_char)),
             };
         }
-        Ok(Board {
+        let mut board = Board {
             squares,
             state: State {
                 turn: Color::White,
-                castling_state: castling::State {
-                    white: castling::Rights::Both,
-                    black: castling::Rights::Both,
-                },
+                castling_state: castling::State::new(
+                    castling::Rights::Both,
+                    castling::Rights::Both,
+                ),
                 ep_index: None,
                 halfmove_clock: 0,
                 fullmove_count: 1,
@@ -470,12 +574,106 @@ impl Board {
                 last_move: None,
                 last_ep_taken_index: None,
                 king_indices: Board::gen_king_indices(&squares)?,
+                hash: 0,
             },
             state_history: vec![],
             history: vec![],
             algorithm: Algorithm::Clone,
             store_history: false,
-        })
+        };
+        board.refresh_hash();
+        Ok(board)
+    }
+
+    /// Sets up the position with the pieces of [`squares`] and [`turn`] to move,
+    /// without castling rights, an en passant square or a history, e.g. to go
+    /// through many positions without parsing FEN.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a king is missing from [`squares`.]
+    pub fn set_position(&mut self, squares: [Option<Piece>; 64], turn: Color) -> Result<(), Error> {
+        self.state = State {
+            turn,
+            castling_state: castling::State::new(
+                castling::Rights::Neither,
+                castling::Rights::Neither,
+            ),
+            ep_index: None,
+            halfmove_clock: 0,
+            fullmove_count: 1,
+            game_state: GameState::Playing,
+            last_captured_square: None,
+            last_move: None,
+            last_ep_taken_index: None,
+            king_indices: Board::gen_king_indices(&squares)?,
+            hash: 0,
+        };
+        self.squares = squares;
+        self.state_history.clear();
+        self.history.clear();
+        self.refresh_hash();
+        Ok(())
+    }
+
+    /// Sets up the position with the pieces of [`squares`] and every other field of a
+    /// FEN, without a history.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a king is missing from [`squares`.]
+    pub(crate) fn set_fen_fields(
+        &mut self,
+        squares: [Option<Piece>; 64],
+        turn: Color,
+        castling_state: castling::State,
+        ep_index: Option<usize>,
+        halfmove_clock: u32,
+        fullmove_count: u32,
+    ) -> Result<(), Error> {
+        self.set_position(squares, turn)?;
+        self.state.castling_state = castling_state;
+        self.state.ep_index = ep_index;
+        self.state.halfmove_clock = halfmove_clock;
+        self.state.fullmove_count = fullmove_count;
+        self.refresh_hash();
+        Ok(())
+    }
+
+    /// Sets up the position before the side that isn't to move played a move, with
+    /// the pieces of [`squares`,] [`ep_index`] and [`halfmove_clock`,] and the castling
+    /// rights of the current position.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a king is missing from [`squares`.]
+    pub(crate) fn set_predecessor(
+        &mut self,
+        squares: [Option<Piece>; 64],
+        ep_index: Option<usize>,
+        halfmove_clock: u32,
+    ) -> Result<(), Error> {
+        let turn = self.state.turn.opposite();
+        self.state = State {
+            turn,
+            ep_index,
+            halfmove_clock,
+            fullmove_count: match turn {
+                Color::White => self.state.fullmove_count,
+                Color::Black => self.state.fullmove_count.saturating_sub(1).max(1),
+            },
+            game_state: GameState::Playing,
+            last_captured_square: None,
+            last_move: None,
+            last_ep_taken_index: None,
+            king_indices: Board::gen_king_indices(&squares)?,
+            ..self.state
+        };
+        self.squares = squares;
+        self.state_history.clear();
+        self.history.clear();
+        self.refresh_hash();
+        Ok(())
     }
 
     /// Returns a playable board.
@@ -493,13 +691,9 @@ impl Board {
     /// See: [Forsyth-Edwards_Notation](https://en.wikipedia.org/wiki/Forsyth-Edwards_Notation)
     pub fn from_fen(fen: &str) -> Result<Self, Error> {
         let mut squares: Vec<char> = vec![];
-        let mut iter = fen.split(' ');
-        let pieces = iter.next().expect("Invalid FEN");
-        let turn = iter.next().expect("Invalid FEN");
-        let castling_rights = iter.next().expect("Invalid FEN");
-        let ep_square = iter.next().expect("Invalid FEN");
-        let halfmove_clock = iter.next().expect("Invalid FEN");
-        let fullmove_count = iter.next().expect("Invalid FEN");
+        let [pieces, turn, castling_rights, ep_square, halfmove_clock, fullmove_count] =
+            <[&str; 6]>::try_from(fen.split(' ').collect::<Vec<_>>())
+                .map_err(|_| Error::MissingFenFields(fen.to_string()))?;
         for row in pieces.split('/') {
             for c in row.chars() {
                 match c {
@@ -521,7 +715,7 @@ impl Board {
 
         let mut board: Board = Board::from_chars(&array)?;
         board.state.turn = Color::from(turn)?;
-        board.state.castling_state = castling::State::from(castling_rights);
+        board.state.castling_state = castling::State::from_fen(castling_rights, &board.squares)?;
         board.state.ep_index = Coordinate::from(ep_square)?.map(Coordinate::into_index);
         board.state.halfmove_clock = match halfmove_clock.parse::<u32>() {
             Ok(v) => v,
@@ -531,12 +725,14 @@ impl Board {
             Ok(v) => v,
             Err(e) => return Err(Error::InvalidFullmoveStr(e)),
         };
+        board.refresh_hash();
         Ok(board)
     }
 
     /// Change the board's current turn.
     fn change_turn(&mut self) {
         self.state.turn = self.state.turn.opposite();
+        self.state.hash ^= zobrist::turn_key();
     }
 
     /// Play [`mov`] on the board, and update the state and history accordingly.
@@ -548,12 +744,23 @@ impl Board {
     /// or is illegal.
     pub fn make_move(&mut self, mov: &Move) {
         let moving_piece = self.squares[mov.start_index].expect("Move empty square");
-        let captured_square = self.squares[mov.target_index];
+        let color = self.state.turn;
+
+        // In Chess960, castling is encoded as the king capturing its own rook,
+        // so the target square is only captured if the move is not castling.
+        let castling_squares = match moving_piece.kind {
+            Kind::King => self.state.castling_state.get_squares(mov, &color),
+            _ => None,
+        };
+        let captured_square = match castling_squares {
+            Some(_) => None,
+            None => self.squares[mov.target_index],
+        };
 
         if self.algorithm == Algorithm::Unmove {
             self.state_history.push(self.state);
         }
-        let color = self.state.turn;
+        let (castling_state, ep_index) = (self.state.castling_state, self.state.ep_index);
 
         // The index of the pawn being captured via en passant, if any
         let ep_taken_index: Option<usize> = (|| {
@@ -576,29 +783,21 @@ impl Board {
         if self.algorithm == Algorithm::Unmove {
             self.state.last_ep_taken_index = ep_taken_index;
             self.state.last_move = Some(*mov);
-            self.state.last_captured_square = Some(self.squares[mov.target_index]);
+            self.state.last_captured_square = Some(captured_square);
         }
 
         self.state.ep_index = None;
         match captured_square {
             Some(piece) => {
-                match piece.kind {
-                    Kind::King => {}
-                    Kind::Rook => {
-                        match mov.target_index {
-                            0 | 56 => self
-                                .state
-                                .castling_state
-                                .revoke(castling::Rights::Queenside, &color.opposite()),
-                            7 | 63 => self
-                                .state
-                                .castling_state
-                                .revoke(castling::Rights::Kingside, &color.opposite()),
-                            _ => (),
-                        };
+                if piece.kind == Kind::Rook {
+                    if let Some(right) = self
+                        .state
+                        .castling_state
+                        .rook_right(mov.target_index, &color.opposite())
+                    {
+                        self.state.castling_state.revoke(right, &color.opposite());
                     }
-                    _ => (),
-                };
+                }
                 self.state.halfmove_clock = 0;
             }
             None => {
@@ -608,31 +807,29 @@ impl Board {
             }
         };
 
-        let mut is_castling = false;
         match moving_piece.kind {
             Kind::King => {
                 self.state
                     .castling_state
                     .revoke(castling::Rights::Both, &self.state.turn);
-                is_castling = mov
-                    .is_castling(&self.squares)
-                    .expect("Square containing king is non-empty");
+                let king_index = match castling_squares {
+                    Some(castling_squares) => castling_squares.king_target_index,
+                    None => mov.target_index,
+                };
                 match self.state.turn {
-                    Color::White => self.state.king_indices.white = mov.target_index,
-                    Color::Black => self.state.king_indices.black = mov.target_index,
+                    Color::White => self.state.king_indices.white = king_index,
+                    Color::Black => self.state.king_indices.black = king_index,
                 }
             }
-            Kind::Rook => match mov.start_index {
-                0 | 56 => self
+            Kind::Rook => {
+                if let Some(right) = self
                     .state
                     .castling_state
-                    .revoke(castling::Rights::Queenside, &color),
-                7 | 63 => self
-                    .state
-                    .castling_state
-                    .revoke(castling::Rights::Kingside, &color),
-                _ => (),
-            },
+                    .rook_right(mov.start_index, &color)
+                {
+                    self.state.castling_state.revoke(right, &color);
+                }
+            }
             Kind::Pawn => {
                 self.state.halfmove_clock = 0;
                 let offset: isize = mov.target_index as isize - mov.start_index as isize;
@@ -644,7 +841,8 @@ impl Board {
             }
             _ => (),
         };
-        if self.state.halfmove_clock >= 50 {
+        // Fifty moves by each player without a capture or pawn move
+        if self.state.halfmove_clock >= 100 {
             self.state.game_state = GameState::Draw;
         }
 
@@ -653,31 +851,53 @@ impl Board {
         }
 
         if let Some(pawn_index) = ep_taken_index {
-            self.squares[pawn_index] = None;
+            self.set_square(pawn_index, None);
         }
-        self.squares[mov.target_index] = self.squares[mov.start_index];
-        self.squares[mov.start_index] = None;
-
-        if is_castling {
-            let castling_squares =
-                castling::get_squares(mov).expect("`is_castling` guarantees moves is castling");
-            self.squares[castling_squares.rook_target_index] = Some(Piece {
-                kind: Kind::Rook,
-                color,
-            });
-            self.squares[castling_squares.rook_start_index] = None;
+
+        if let Some(castling_squares) = castling_squares {
+            // The king and rook may land on each other's starting squares,
+            // so both are removed before either is placed.
+            self.set_square(castling_squares.king_start_index, None);
+            self.set_square(castling_squares.rook_start_index, None);
+            self.set_square(castling_squares.king_target_index, Some(moving_piece));
+            self.set_square(
+                castling_squares.rook_target_index,
+                Some(Piece {
+                    kind: Kind::Rook,
+                    color,
+                }),
+            );
+        } else {
+            self.set_square(mov.target_index, self.squares[mov.start_index]);
+            self.set_square(mov.start_index, None);
         }
+
         if let Some(kind) = mov.promotion_kind {
-            self.squares[mov.target_index] = Some(Piece { kind, color });
-            self.squares[mov.start_index] = None;
+            self.set_square(mov.target_index, Some(Piece { kind, color }));
         }
 
+        self.state.hash ^= zobrist::state_key(castling_state, ep_index)
+            ^ zobrist::state_key(self.state.castling_state, self.state.ep_index);
         if self.state.turn == Color::Black {
             self.state.fullmove_count += 1;
         }
         self.change_turn();
     }
 
+    /// Puts [`square`] on [`index`,] and updates the hash for the piece that leaves
+    /// and the piece that arrives.
+    fn set_square(&mut self, index: usize, square: Square) {
+        for piece in [self.squares[index], square].into_iter().flatten() {
+            self.state.hash ^= zobrist::piece_key(piece, index);
+        }
+        self.squares[index] = square;
+    }
+
+    /// Recomputes the hash from scratch, after the position was set up.
+    fn refresh_hash(&mut self) {
+        self.state.hash = self.zobrist_hash();
+    }
+
     /// Undo the board's most recent move.
     ///
     /// # Errors
@@ -699,6 +919,26 @@ impl Board {
             self.history.pop();
         }
 
+        // The restored state still holds the king's index from before the move,
+        // so castling is recognized by the king having made the move.
+        if self.state.king_indices.get(color) == last_move.start_index {
+            if let Some(castling_squares) =
+                self.state.castling_state.get_squares(&last_move, &color)
+            {
+                self.squares[castling_squares.king_target_index] = None;
+                self.squares[castling_squares.rook_target_index] = None;
+                self.squares[castling_squares.king_start_index] = Some(Piece {
+                    kind: Kind::King,
+                    color,
+                });
+                self.squares[castling_squares.rook_start_index] = Some(Piece {
+                    kind: Kind::Rook,
+                    color,
+                });
+                return;
+            }
+        }
+
         if let Some(index) = last_ep_taken_index {
             self.squares[index] = Some(Piece {
                 kind: Kind::Pawn,
@@ -717,28 +957,39 @@ impl Board {
         } else {
             self.squares[last_move.start_index] = last_moved_square;
         }
+    }
 
-        // Can use `is_castling` method because moving piece (king) has
-        // already been reset
-        if last_move
-            .is_castling(&self.squares)
-            .expect("Last move must be a valid move")
-        {
-            let castling_squares =
-                castling::get_squares(&last_move).expect("Last move was castling");
-            self.squares[castling_squares.rook_start_index] = Some(Piece {
-                kind: Kind::Rook,
-                color,
-            });
-            self.squares[castling_squares.rook_target_index] = None;
+    /// Pass the turn to the opponent without moving, clearing the en passant square.
+    /// Must be undone with [`unmake_null_move`] before any other move is undone,
+    /// and must not be played while in check.
+    ///
+    /// See: [Null Move](https://www.chessprogramming.org/Null_Move)
+    pub fn make_null_move(&mut self) {
+        self.state_history.push(self.state);
+        if let Some(ep_index) = self.state.ep_index {
+            self.state.hash ^= zobrist::ep_key(ep_index % 8);
+        }
+        self.state.ep_index = None;
+        self.state.last_move = None;
+        self.state.last_captured_square = None;
+        self.state.last_ep_taken_index = None;
+        self.state.halfmove_clock += 1;
+        if self.state.turn == Color::Black {
+            self.state.fullmove_count += 1;
         }
+        self.change_turn();
+    }
+
+    /// Undo a null move made with [`make_null_move`.]
+    pub fn unmake_null_move(&mut self) {
+        self.state = self.state_history.pop().expect("Undo from first move");
     }
 
     /// Set the algorith of the board to [`Unmove`] or [`Clone`.]
     /// * [`Clone`] will clone the current board before making a move to test if that
-    /// move leaves the player in check.
+    ///   move leaves the player in check.
     /// * [`Unmove`] makes a move on the current board to test if that move leaves the
-    /// player in check, before undoing that move.
+    ///   player in check, before undoing that move.
     ///
     /// Clone requires cloning the entire board, but does not require a mutable reference,
     /// and therefore can be parallelized, and does not require storing state history.
@@ -751,10 +1002,12 @@ impl Board {
 
     pub fn set_castling_state(&mut self, rights: &str) {
         self.state.castling_state = castling::State::from(rights);
+        self.refresh_hash();
     }
 
     pub fn set_state(&mut self, state: State) {
         self.state = state;
+        self.refresh_hash();
     }
 
     /// If player has a legal move, i.e. there is some [`mov`,] play it, otherwise
@@ -832,11 +1085,7 @@ impl Board {
     //
     //      "This function will return an error if move generation fails"
 
-    /// Creates a new [`Board`].
-    ///
-    /// # Panics
-    ///
-    /// Panics if .
+    /// Creates a new [`Board`] in the starting position.
     pub fn new() -> Self {
         Self::from_chars(&[
             'r', 'n', 'b', 'q', 'k', 'b', 'n', 'r', 'p', 'p', 'p', 'p', 'p', 'p', 'p', 'p', ' ',
@@ -849,16 +1098,46 @@ impl Board {
 
     /// Returns the current position and state in FEN notation.
     /// See: [Forsyth-Edwards_Notation](https://en.wikipedia.org/wiki/Forsyth-Edwards_Notation)
-    pub fn to_fen(&self) -> &str {
-        let mut squares = [' '; 64];
-        for (i, square) in self.squares().iter().enumerate() {
-            squares[i] = Piece::square_to_char(square);
-        }
-        for char in &squares {
-            let _ = char;
-            todo!()
+    pub fn to_fen(&self) -> String {
+        let mut pieces = String::new();
+        for (rank_index, rank) in self.squares.chunks(8).enumerate() {
+            if rank_index != 0 {
+                pieces.push('/');
+            }
+            let mut empty_count = 0;
+            for square in rank {
+                if square.is_none() {
+                    empty_count += 1;
+                    continue;
+                }
+                if empty_count != 0 {
+                    pieces.push_str(&empty_count.to_string());
+                    empty_count = 0;
+                }
+                pieces.push(Piece::square_to_char(square));
+            }
+            if empty_count != 0 {
+                pieces.push_str(&empty_count.to_string());
+            }
         }
-        todo!()
+
+        let turn = match self.state.turn {
+            Color::White => "w",
+            Color::Black => "b",
+        };
+        let castling_rights = match self.state.castling_state.to_str() {
+            rights if rights.is_empty() => "-".to_string(),
+            rights => rights,
+        };
+        let ep_square = match self.state.ep_index {
+            Some(index) => Coordinate::from_index(index).to_string(),
+            None => "-".to_string(),
+        };
+
+        format!(
+            "{pieces} {turn} {castling_rights} {ep_square} {} {}",
+            self.state.halfmove_clock, self.state.fullmove_count
+        )
     }
 
     /// Prints the current position in a human-readable format.
@@ -874,12 +1153,36 @@ impl Board {
         }
     }
 
+    /// Draws the current position as text from [`perspective`'s] side of the board,
+    /// with rank labels on the left and file labels below.
+    pub fn diagram(&self, perspective: Color) -> String {
+        let mut ranks: Vec<String> = self
+            .squares
+            .chunks(8)
+            .enumerate()
+            .map(|(rank_index, rank)| {
+                let mut squares: Vec<char> = rank.iter().map(Piece::square_to_char).collect();
+                if perspective == Color::Black {
+                    squares.reverse();
+                }
+                let squares: Vec<String> = squares.iter().map(char::to_string).collect();
+                format!("{}  {}", 8 - rank_index, squares.join(" "))
+            })
+            .collect();
+        let mut files: Vec<String> = ('a'..='h').map(String::from).collect();
+        if perspective == Color::Black {
+            ranks.reverse();
+            files.reverse();
+        }
+        format!("{}\n\n   {}", ranks.join("\n"), files.join(" "))
+    }
+
     /// Displays a list of moves on the current board in a human-readable format.
     ///
     /// # Arguments
     /// * [`moves`] - A slice of the moves to be displayed.
     /// * [`shown_pieces`] - A Vec of which kinds pieces to show moves for,
-    /// or all pieces if empty.
+    ///   or all pieces if empty.
     /// * [`show_castling`] - Whether or not to show castling moves.
     ///
     /// # Errors
@@ -890,7 +1193,7 @@ impl Board {
         Move::dbg_moves(
             moves
                 .iter()
-                .map(|&m| {
+                .filter_map(|&m| {
                     if shown_pieces.is_empty() {
                         return Some(m);
                     }
@@ -902,13 +1205,32 @@ impl Board {
                     }
                     None
                 })
-                .flatten()
                 .collect::<Vec<_>>()
                 .as_slice(),
             &self.squares(),
         );
     }
 
+    /// Returns whether the king of the player whose turn it is is attacked.
+    pub fn in_check(&self) -> bool {
+        let king_index = self.state.king_indices.get(self.state.turn);
+        self.is_attacked(&[king_index], self.state.turn)
+    }
+
+    /// Returns whether the current position occurred before, since the last capture or
+    /// pawn move. Only positions in the state history are seen, so the board must be
+    /// played with the [`Unmove`] algorithm.
+    pub fn is_repetition(&self) -> bool {
+        self.state_history
+            .iter()
+            .rev()
+            .take(self.state.halfmove_clock as usize)
+            // Only positions with the same player to move can be the same
+            .skip(1)
+            .step_by(2)
+            .any(|state| state.hash == self.state.hash)
+    }
+
     /// Returns the index of the [`color`'s] king.
     ///
     /// # Errors
@@ -1030,13 +1352,22 @@ impl Board {
     /// Play a random game up to [`move_limit`] moves.
     /// Will leave the board in the last position of the game.
     pub fn play_random_game(&mut self, move_limit: u32) -> Result<GameState, Error> {
-        use rand::{seq::SliceRandom, thread_rng};
+        self.play_random_game_with_rng(move_limit, &mut rand::thread_rng())
+    }
+
+    /// Play a random game up to [`move_limit`] moves, choosing every move with [`rng`,]
+    /// so that a seeded [`rng`] always plays the same game.
+    /// Will leave the board in the last position of the game.
+    pub fn play_random_game_with_rng(
+        &mut self,
+        move_limit: u32,
+        rng: &mut impl rand::Rng,
+    ) -> Result<GameState, Error> {
+        use rand::seq::SliceRandom;
         for _ in 0..move_limit {
             let moves = self.gen_legal_moves();
 
-            let mut rng = thread_rng();
-            let mov = moves.choose(&mut rng);
-            // let mov = moves.into_iter().next();
+            let mov = moves.choose(rng);
             self.play_legal_move(mov);
             if self.state().game_state != GameState::Playing {
                 break;
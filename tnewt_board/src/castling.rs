@@ -1,6 +1,7 @@
 use crate::board;
 use crate::mov::Move;
 use crate::color::Color;
+use crate::piece::{Kind, Piece};
 
 #[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
 pub enum Rights {
@@ -29,26 +30,6 @@ impl Rights {
             Rights::Both      => "kq",
         }
     }
-    #[must_use]
-    pub fn gen_moves(&self, color: &Color) -> Vec<Move> {
-        use Rights as R;
-        if *self == R::Neither { return vec![]; }
-
-        let kingside = match color {
-            Color::White => Move::new(60, 62),
-            Color::Black => Move::new(4, 6),
-        };
-        if *self == R::Kingside { return vec![kingside]; }
-
-        let queenside = match color {
-            Color::White => Move::new(60,58),
-            Color::Black => Move::new(4, 2 ),
-        };
-        if *self == R::Queenside { return vec![queenside] }
-
-        vec![kingside, queenside]
-    }
-
     pub fn revoke(&mut self, right: Rights) {
         use Rights as R;
         match right {
@@ -76,96 +57,270 @@ impl Rights {
 pub struct State {
     pub white: Rights,
     pub black: Rights,
+    /// The file of the rook each player castles kingside with.
+    pub kingside_file: usize,
+    /// The file of the rook each player castles queenside with.
+    pub queenside_file: usize,
+    /// Whether castling moves are encoded as the king capturing its own rook, as in
+    /// Chess960, rather than as the king moving two squares.
+    pub chess960: bool,
 }
 impl State {
     #[must_use]
     pub fn from(rights: &str) -> Self {
         if rights == "-" {
-            return State {
-                white: Rights::Neither,
-                black: Rights::Neither,
-            }
+            return State::new(Rights::Neither, Rights::Neither);
         }
         let white_k = rights.contains('K');
         let white_q = rights.contains('Q');
         let black_k = rights.contains('k');
         let black_q = rights.contains('q');
 
+        State::new(
+            Rights::from(white_k, white_q),
+            Rights::from(black_k, black_q),
+        )
+    }
+
+    /// Creates castling state for the standard starting rook files.
+    #[must_use]
+    pub fn new(white: Rights, black: Rights) -> Self {
         State {
-            white: Rights::from(white_k, white_q),
-            black: Rights::from(black_k, black_q),
+            white,
+            black,
+            kingside_file: 7,
+            queenside_file: 0,
+            chess960: false,
+        }
+    }
+
+    /// Generates castling state from the castling field of a FEN string, which may be in
+    /// standard, X-FEN, or Shredder-FEN notation.
+    ///
+    /// `KQkq` refer to the outermost rook on either side of the king, while the letters
+    /// `A`..`H` and `a`..`h` refer to the rook on that file. Chess960 encoding is used
+    /// if the king or castling rooks do not start on their standard squares.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if [`rights`] contains an invalid character.
+    pub fn from_fen(rights: &str, squares: &[Option<Piece>; 64]) -> Result<Self, board::Error> {
+        let mut state = State::new(Rights::Neither, Rights::Neither);
+        if rights == "-" {
+            return Ok(state);
+        }
+        let mut kingside_file = None;
+        let mut queenside_file = None;
+        for c in rights.chars() {
+            let color = if c.is_ascii_uppercase() {
+                Color::White
+            } else {
+                Color::Black
+            };
+            let back_rank = back_rank_index(color);
+            let king_file = (0..8).find(|&file| {
+                squares[back_rank + file]
+                    == Some(Piece {
+                        kind: Kind::King,
+                        color,
+                    })
+            });
+            let is_rook = |file: usize| {
+                squares[back_rank + file]
+                    == Some(Piece {
+                        kind: Kind::Rook,
+                        color,
+                    })
+            };
+
+            let (right, file) = match c.to_ascii_lowercase() {
+                'k' => {
+                    let file = king_file.and_then(|king| (king + 1..8).rev().find(|&f| is_rook(f)));
+                    (Rights::Kingside, file.unwrap_or(7))
+                }
+                'q' => {
+                    let file = king_file.and_then(|king| (0..king).find(|&f| is_rook(f)));
+                    (Rights::Queenside, file.unwrap_or(0))
+                }
+                file @ 'a'..='h' => {
+                    state.chess960 = true;
+                    let file = file as usize - 'a' as usize;
+                    match king_file {
+                        Some(king) if file < king => (Rights::Queenside, file),
+                        _ => (Rights::Kingside, file),
+                    }
+                }
+                _ => return Err(board::Error::InvalidCastlingChar(c)),
+            };
+            match right {
+                Rights::Kingside => kingside_file = Some(file),
+                _ => queenside_file = Some(file),
+            }
+            let rights = match color {
+                Color::White => &mut state.white,
+                Color::Black => &mut state.black,
+            };
+            *rights = match (*rights, right) {
+                (Rights::Neither, right) => right,
+                (existing, right) if existing == right => right,
+                _ => Rights::Both,
+            };
+            if king_file.is_some_and(|file| file != 4) {
+                state.chess960 = true;
+            }
         }
+        state.kingside_file = kingside_file.unwrap_or(7);
+        state.queenside_file = queenside_file.unwrap_or(0);
+        if state.kingside_file != 7 || state.queenside_file != 0 {
+            state.chess960 = true;
+        }
+        Ok(state)
     }
+
     #[must_use]
     pub fn to_str(&self) -> String {
-        format!(
-            "{}{}",
-            self.white.to_str().to_ascii_uppercase(),
-            self.black.to_str().to_ascii_lowercase().as_str()
-        )
+        if !self.chess960 {
+            return format!(
+                "{}{}",
+                self.white.to_str().to_ascii_uppercase(),
+                self.black.to_str().to_ascii_lowercase().as_str()
+            );
+        }
+        let file_char = |file: usize| (b'a' + file as u8) as char;
+        let mut string = String::new();
+        for (rights, color) in [(self.white, Color::White), (self.black, Color::Black)] {
+            for (right, file) in [
+                (Rights::Kingside, self.kingside_file),
+                (Rights::Queenside, self.queenside_file),
+            ] {
+                if rights == right || rights == Rights::Both {
+                    string.push(match color {
+                        Color::White => file_char(file).to_ascii_uppercase(),
+                        Color::Black => file_char(file),
+                    });
+                }
+            }
+        }
+        string
     }
+
     #[must_use]
-    pub fn get_moves(&self, color: &Color) -> Vec<Move> {
+    pub fn rights(&self, color: &Color) -> Rights {
         match color {
-            Color::White => self.white.gen_moves(color),
-            Color::Black => self.black.gen_moves(color),
+            Color::White => self.white,
+            Color::Black => self.black,
         }
     }
+
+    /// Generates the castling moves [`color`'s] king at [`king_index`] has the right to
+    /// play, without checking whether they are legal.
+    #[must_use]
+    pub fn get_moves(&self, color: &Color, king_index: usize) -> Vec<Move> {
+        [Rights::Kingside, Rights::Queenside]
+            .into_iter()
+            .filter(|&right| self.has_right(right, color))
+            .map(|right| Move::new(king_index, self.target_index(right, color)))
+            .collect()
+    }
+
     pub fn revoke(&mut self, right: Rights, color: &Color) {
         match color {
             Color::White => self.white.revoke(right),
             Color::Black => self.black.revoke(right),
         }
     }
+
+    /// Returns which castling right is lost when a rook moves from or is captured on
+    /// [`index`,] if any.
+    #[must_use]
+    pub fn rook_right(&self, index: usize, color: &Color) -> Option<Rights> {
+        let back_rank = back_rank_index(*color);
+        if index == back_rank + self.kingside_file {
+            Some(Rights::Kingside)
+        } else if index == back_rank + self.queenside_file {
+            Some(Rights::Queenside)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the squares involved in [`mov`] if it is a castling move by [`color`'s]
+    /// king, given that [`color`] still has the right to castle.
+    #[must_use]
+    pub fn get_squares(&self, mov: &Move, color: &Color) -> Option<Squares> {
+        let back_rank = back_rank_index(*color);
+        if mov.start_index / 8 != back_rank / 8 || (!self.chess960 && mov.start_index % 8 != 4) {
+            return None;
+        }
+        [Rights::Kingside, Rights::Queenside]
+            .into_iter()
+            .find(|&right| {
+                self.has_right(right, color) && mov.target_index == self.target_index(right, color)
+            })
+            .map(|right| {
+                let (rook_file, king_target_file, rook_target_file) = match right {
+                    Rights::Kingside => (self.kingside_file, 6, 5),
+                    _ => (self.queenside_file, 2, 3),
+                };
+                Squares {
+                    king_start_index: mov.start_index,
+                    king_target_index: back_rank + king_target_file,
+                    rook_start_index: back_rank + rook_file,
+                    rook_target_index: back_rank + rook_target_file,
+                }
+            })
+    }
+
+    fn has_right(&self, right: Rights, color: &Color) -> bool {
+        let rights = self.rights(color);
+        rights == right || rights == Rights::Both
+    }
+
+    /// The target index of a castling move, which is the king's destination in
+    /// standard chess, and the castling rook's square in Chess960.
+    fn target_index(&self, right: Rights, color: &Color) -> usize {
+        let back_rank = back_rank_index(*color);
+        match (right, self.chess960) {
+            (Rights::Kingside, false) => back_rank + 6,
+            (_, false) => back_rank + 2,
+            (Rights::Kingside, true) => back_rank + self.kingside_file,
+            (_, true) => back_rank + self.queenside_file,
+        }
+    }
+}
+
+/// The index of the a-file square on [`color`'s] back rank.
+#[must_use]
+pub fn back_rank_index(color: Color) -> usize {
+    match color {
+        Color::White => 56,
+        Color::Black => 0,
+    }
 }
 
-pub struct Squares<'a> {
-    pub empty_indices: &'a [usize],
-    pub check_indices: &'a [usize],
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Squares {
     pub king_start_index: usize,
+    pub king_target_index: usize,
     pub rook_start_index: usize,
     pub rook_target_index: usize,
 }
 
-/// Generate all relevant squares based on a castling move `mov`.
-///
-/// # Errors
-///
-/// This function will return an error if `mov` is not a valid castling move.
-pub fn get_squares(mov: &Move) -> Result<Squares, board::Error> {
-    if mov.indices() == (60, 62) {
-        Ok(Squares {
-            empty_indices: &[61, 62],
-            check_indices: &[60, 61, 62],
-            king_start_index: 60,
-            rook_start_index: 63,
-            rook_target_index: 61,
-        })
-    } else if mov.indices() == (4, 6) {
-        Ok(Squares {
-            empty_indices: &[5, 6],
-            check_indices: &[4, 5, 6],
-            king_start_index: 4,
-            rook_start_index: 7,
-            rook_target_index: 5,
-        })
-    } else if mov.indices() == (60, 58) {
-        Ok(Squares {
-            empty_indices: &[57, 58, 59],
-            check_indices: &[58, 59, 60],
-            king_start_index: 60,
-            rook_start_index: 56,
-            rook_target_index: 59,
-        })
-    } else if mov.indices() == (4, 2) {
-        Ok(Squares {
-            empty_indices: &[1, 2, 3],
-            check_indices: &[2, 3, 4],
-            king_start_index: 4,
-            rook_start_index: 0,
-            rook_target_index: 3,
-        })
-    } else {
-        Err(board::Error::InvalidCastlingMove(mov.target_index))
+impl Squares {
+    /// The squares that must be empty, apart from the castling king and rook.
+    #[must_use]
+    pub fn empty_indices(&self) -> Vec<usize> {
+        let span = |a: usize, b: usize| a.min(b)..=a.max(b);
+        span(self.king_start_index, self.king_target_index)
+            .chain(span(self.rook_start_index, self.rook_target_index))
+            .filter(|&index| index != self.king_start_index && index != self.rook_start_index)
+            .collect()
+    }
+
+    /// The squares the king passes through, none of which may be attacked.
+    #[must_use]
+    pub fn check_indices(&self) -> Vec<usize> {
+        let (start, target) = (self.king_start_index, self.king_target_index);
+        (start.min(target)..=start.max(target)).collect()
     }
 }
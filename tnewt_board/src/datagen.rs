@@ -0,0 +1,279 @@
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::board::{Board, GameState};
+use crate::color::Color;
+use crate::game::Game;
+use crate::ordering;
+use crate::packed::{Error, PackedPosition, PACKED_SIZE};
+use crate::search::{self, SearchLimits, Searcher};
+use crate::tt::{self, TranspositionTable};
+
+/// The size of a sample in the binary format in bytes: a packed position, the
+/// score as a little-endian `i16` and the result as a byte.
+pub const SAMPLE_SIZE: usize = PACKED_SIZE + 3;
+/// Every game searches as deep as the engine's main thread, which may run on a larger stack.
+const WORKER_STACK_SIZE: usize = 16 * 1024 * 1024;
+
+/// How samples are written: [`SAMPLE_SIZE`] bytes each, or a line of text each.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Format {
+    Binary,
+    /// `<fen> | <score> | <result>`, with the result `1.0`, `0.5` or `0.0`.
+    Text,
+}
+
+/// A position of a self-play game, labelled with its search score and the result
+/// of the game, both from white's point of view.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Sample {
+    pub position: PackedPosition,
+    /// The score in centipawns.
+    pub score: i16,
+    /// The result of the game, where a game adjudicated before it ended is drawn.
+    pub result: GameState,
+}
+
+impl Sample {
+    /// The result as a byte: 2 for a white win, 1 for a draw and 0 for a black win.
+    fn result_byte(&self) -> u8 {
+        match self.result {
+            GameState::Victory(Color::White) => 2,
+            GameState::Victory(Color::Black) => 0,
+            GameState::Draw | GameState::Playing => 1,
+        }
+    }
+
+    #[must_use]
+    pub fn to_bytes(&self) -> [u8; SAMPLE_SIZE] {
+        let mut bytes = [0; SAMPLE_SIZE];
+        bytes[..PACKED_SIZE].copy_from_slice(&self.position.to_bytes());
+        bytes[PACKED_SIZE..PACKED_SIZE + 2].copy_from_slice(&self.score.to_le_bytes());
+        bytes[PACKED_SIZE + 2] = self.result_byte();
+        bytes
+    }
+
+    /// # Errors
+    ///
+    /// This function will return an error if the last byte isn't a result.
+    pub fn from_bytes(bytes: [u8; SAMPLE_SIZE]) -> Result<Self, Error> {
+        let result = match bytes[PACKED_SIZE + 2] {
+            2 => GameState::Victory(Color::White),
+            1 => GameState::Draw,
+            0 => GameState::Victory(Color::Black),
+            byte => return Err(Error::InvalidPacking(format!("invalid result {byte}"))),
+        };
+        Ok(Sample {
+            position: PackedPosition::from_bytes(
+                bytes[..PACKED_SIZE]
+                    .try_into()
+                    .expect("A sample starts with a position"),
+            ),
+            score: i16::from_le_bytes([bytes[PACKED_SIZE], bytes[PACKED_SIZE + 1]]),
+            result,
+        })
+    }
+
+    /// Writes the sample as a line of the text format, without the line break.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the position isn't a packed position.
+    pub fn to_text(&self) -> Result<String, Error> {
+        let result = match self.result_byte() {
+            2 => "1.0",
+            0 => "0.0",
+            _ => "0.5",
+        };
+        Ok(format!(
+            "{} | {} | {result}",
+            self.position.unpack()?.to_fen(),
+            self.score
+        ))
+    }
+}
+
+/// Writes samples one after another to a stream in a [`Format`.]
+pub struct SampleWriter<W: Write> {
+    writer: W,
+    format: Format,
+    written: u64,
+}
+
+impl<W: Write> SampleWriter<W> {
+    pub fn new(writer: W, format: Format) -> Self {
+        SampleWriter {
+            writer,
+            format,
+            written: 0,
+        }
+    }
+
+    /// # Errors
+    ///
+    /// This function will return an error if the stream can't be written.
+    pub fn write(&mut self, sample: &Sample) -> Result<(), Error> {
+        match self.format {
+            Format::Binary => self.writer.write_all(&sample.to_bytes())?,
+            Format::Text => writeln!(self.writer, "{}", sample.to_text()?)?,
+        }
+        self.written += 1;
+        Ok(())
+    }
+
+    /// The number of samples written so far.
+    #[must_use]
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    /// Flushes the samples written and returns the stream.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the stream can't be flushed.
+    pub fn finish(mut self) -> Result<W, Error> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    pub games: u64,
+    /// The number of games played at once, each on its own thread.
+    pub threads: usize,
+    /// The number of random plies each game opens with, which are never sampled.
+    pub random_plies: u32,
+    /// The limits of the search of each move, which should be the same for every
+    /// position, e.g. a depth or a number of nodes, for the scores to compare.
+    pub limits: SearchLimits,
+    /// Games still being played after this many plies are adjudicated as draws.
+    pub max_plies: u32,
+    /// The size of each thread's transposition table in MB.
+    pub hash_mb: usize,
+    /// The game with each index opens with the same random plies for the same seed.
+    pub seed: u64,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            games: 100,
+            threads: 1,
+            random_plies: 8,
+            limits: SearchLimits::depth(6),
+            max_plies: 400,
+            hash_mb: tt::DEFAULT_SIZE_MB,
+            seed: 0,
+        }
+    }
+}
+
+/// Plays [`options`'] games against itself, and writes the quiet positions of
+/// each game with [`writer`] as soon as it ends, in the order the games end.
+/// Returns the number of games played.
+///
+/// A position is quiet if the side to move isn't in check and the search's best
+/// move is neither a capture nor a promotion, so that its score is close to its
+/// static evaluation. Positions whose score is a mate are left out too.
+///
+/// # Errors
+///
+/// This function will return an error if a sample can't be written, after which
+/// the games being played are finished and thrown away.
+pub fn generate(options: &Options, writer: &mut SampleWriter<impl Write>) -> Result<u64, Error> {
+    let next_game = AtomicU64::new(0);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..options.threads.max(1) {
+            let (sender, next_game) = (sender.clone(), &next_game);
+            thread::Builder::new()
+                .stack_size(WORKER_STACK_SIZE)
+                .spawn_scoped(scope, move || {
+                    let tt = Arc::new(TranspositionTable::new(options.hash_mb));
+                    loop {
+                        let index = next_game.fetch_add(1, Ordering::Relaxed);
+                        // Sending fails once the samples can't be written any more
+                        if index >= options.games
+                            || sender.send(play_game(options, index, &tt)).is_err()
+                        {
+                            break;
+                        }
+                    }
+                })
+                .expect("Failed to spawn data generation thread");
+        }
+        drop(sender);
+
+        let mut games = 0;
+        for samples in receiver {
+            for sample in &samples {
+                writer.write(sample)?;
+            }
+            games += 1;
+        }
+        Ok(games)
+    })
+}
+
+/// Plays the game with [`index`] from a random opening, and returns its quiet
+/// positions labelled with the result.
+fn play_game(options: &Options, index: u64, tt: &Arc<TranspositionTable>) -> Vec<Sample> {
+    let mut rng = StdRng::seed_from_u64(options.seed.wrapping_add(index));
+    let start = loop {
+        let mut board = Board::new();
+        if board.play_random_game_with_rng(options.random_plies, &mut rng) == Ok(GameState::Playing)
+        {
+            break board;
+        }
+    };
+    tt.clear();
+    let stop = Arc::new(AtomicBool::new(false));
+    let mut game = Game::new(&start);
+    let mut positions = vec![];
+    let result = loop {
+        if let Some((state, _)) = game.result() {
+            break state;
+        }
+        if game.moves().len() >= options.max_plies as usize {
+            break GameState::Draw;
+        }
+        let board = game.board();
+        let result = Searcher::with_table(board, Arc::clone(tt)).search_limited(
+            &options.limits,
+            &stop,
+            |_| (),
+        );
+        let Some(mov) = result.best_move else {
+            break GameState::Draw;
+        };
+        let quiet = !board.in_check()
+            && !ordering::is_capture(board, &mov)
+            && mov.promotion_kind.is_none()
+            && !search::is_mate_score(result.score);
+        if quiet {
+            let score = match board.state().turn() {
+                Color::White => result.score,
+                Color::Black => -result.score,
+            };
+            if let Ok(position) = PackedPosition::pack(board) {
+                positions.push((position, score));
+            }
+        }
+        game.play(&mov);
+    };
+    positions
+        .into_iter()
+        .map(|(position, score)| Sample {
+            position,
+            score: score.clamp(i16::MIN.into(), i16::MAX.into()) as i16,
+            result,
+        })
+        .collect()
+}
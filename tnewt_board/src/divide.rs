@@ -0,0 +1,465 @@
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use crate::board::{self, Algorithm, Board};
+use crate::mov::Move;
+
+/// The number of positions reachable after each legal move, keyed by the move
+/// in coordinate notation, e.g. `"e2e4"`.
+pub type Divide = BTreeMap<String, u64>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Board(board::Error),
+    Io(String),
+    InvalidLine(String),
+    EngineExited,
+    NoReference { fen: String, depth: i32 },
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Board(e) => write!(f, "{e}"),
+            Error::Io(e) => write!(f, "{e}"),
+            Error::InvalidLine(line) => write!(f, "invalid divide line `{line}`"),
+            Error::EngineExited => write!(f, "the reference engine exited"),
+            Error::NoReference { fen, depth } => {
+                write!(f, "no reference listing of `{fen}` at depth {depth}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<board::Error> for Error {
+    fn from(error: board::Error) -> Self {
+        Error::Board(error)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error.to_string())
+    }
+}
+
+/// A trusted source of divide listings to compare our move generator against.
+pub trait Reference {
+    /// Returns the divide listing of the position [`fen`] at [`depth`.]
+    ///
+    /// # Errors
+    ///
+    /// This function will return [`Error::NoReference`] if the reference has no
+    /// listing for the requested position and depth.
+    fn divide(&mut self, fen: &str, depth: i32) -> Result<Divide, Error>;
+}
+
+/// Divide listings captured from another engine into a text file, of the root
+/// position and of any positions below it, so that a divergence can be narrowed
+/// down as deep as the listings go.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Listing {
+    /// The divide of each position at each depth, keyed by its FEN.
+    pub sections: Vec<(String, i32, Divide)>,
+}
+
+impl Listing {
+    /// Parses divide listings whose first one is of the position [`fen`] at [`depth`.]
+    ///
+    /// Every line of the form `<move>: <count>` is read as an entry, and any other
+    /// line (such as "Nodes searched: 20" or a blank line) is ignored. Further
+    /// positions start with the UCI commands that divide them, e.g.
+    /// `position startpos moves e2e4` followed by `go perft 3`, so a listing can be
+    /// captured by writing each command to the file before the engine's output.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a move line has an invalid count, or
+    /// a `position` or `go perft` command is invalid.
+    pub fn parse(fen: &str, depth: i32, text: &str) -> Result<Self, Error> {
+        let mut sections = vec![(fen.to_string(), depth, vec![])];
+        let mut position = fen.to_string();
+        for line in text.lines() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["position", args @ ..] => position = parse_position(args)?,
+                ["go", "perft", depth] => {
+                    let depth = depth
+                        .parse()
+                        .map_err(|_| Error::InvalidLine(line.to_string()))?;
+                    // The root's listing is left out if the text starts with a command instead
+                    if let [(_, _, lines)] = sections.as_slice() {
+                        if lines.is_empty() {
+                            sections.clear();
+                        }
+                    }
+                    sections.push((position.clone(), depth, vec![]));
+                }
+                _ => {
+                    if let Some((_, _, lines)) = sections.last_mut() {
+                        lines.push(line);
+                    }
+                }
+            }
+        }
+        Ok(Listing {
+            sections: sections
+                .into_iter()
+                .map(|(fen, depth, lines)| Ok((fen, depth, parse_divide(lines.into_iter())?)))
+                .collect::<Result<_, Error>>()?,
+        })
+    }
+
+    /// Reads divide listings whose first one is of the position [`fen`] at [`depth`]
+    /// from the file at [`path`.]
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file cannot be read, or if it
+    /// cannot be parsed.
+    pub fn from_file(fen: &str, depth: i32, path: &str) -> Result<Self, Error> {
+        Listing::parse(fen, depth, &std::fs::read_to_string(path)?)
+    }
+}
+
+impl Reference for Listing {
+    fn divide(&mut self, fen: &str, depth: i32) -> Result<Divide, Error> {
+        self.sections
+            .iter()
+            .find(|(section_fen, section_depth, _)| {
+                *section_depth == depth && same_position(fen, section_fen)
+            })
+            .map(|(_, _, divide)| divide.clone())
+            .ok_or_else(|| Error::NoReference {
+                fen: fen.to_string(),
+                depth,
+            })
+    }
+}
+
+/// The FEN of the arguments of a UCI `position` command, e.g. `startpos moves e2e4`.
+fn parse_position(args: &[&str]) -> Result<String, Error> {
+    let (mut board, moves) = match args {
+        ["startpos", moves @ ..] => (Board::new(), moves),
+        ["fen", rest @ ..] => {
+            let fields = rest
+                .iter()
+                .position(|&word| word == "moves")
+                .unwrap_or(rest.len());
+            (Board::from_fen(&rest[..fields].join(" "))?, &rest[fields..])
+        }
+        _ => return Err(Error::InvalidLine(format!("position {}", args.join(" ")))),
+    };
+    for uci in moves.iter().skip(1) {
+        let mov = board.move_from_uci(uci)?;
+        board.make_move(&mov);
+    }
+    Ok(board.to_fen())
+}
+
+/// A local UCI engine, queried with `go perft` for each position.
+#[derive(Debug)]
+pub struct UciEngine {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl UciEngine {
+    /// Starts the engine at [`path`] with [`args`.]
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the engine cannot be started.
+    pub fn spawn(path: &str, args: &[&str]) -> Result<Self, Error> {
+        let mut child = Command::new(path)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().ok_or(Error::EngineExited)?;
+        let stdout = BufReader::new(child.stdout.take().ok_or(Error::EngineExited)?);
+        Ok(UciEngine {
+            child,
+            stdin,
+            stdout,
+        })
+    }
+}
+
+impl Reference for UciEngine {
+    fn divide(&mut self, fen: &str, depth: i32) -> Result<Divide, Error> {
+        writeln!(self.stdin, "position fen {fen}")?;
+        writeln!(self.stdin, "go perft {depth}")?;
+        self.stdin.flush()?;
+
+        let mut lines = vec![];
+        loop {
+            let mut line = String::new();
+            if self.stdout.read_line(&mut line)? == 0 {
+                return Err(Error::EngineExited);
+            }
+            if line.trim_start().starts_with("Nodes searched") {
+                break;
+            }
+            lines.push(line);
+        }
+        parse_divide(lines.iter().map(String::as_str))
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        let _ = writeln!(self.stdin, "quit");
+        let _ = self.stdin.flush();
+        let _ = self.child.wait();
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mismatch {
+    /// Both generators play [`mov`], but reach a different number of positions.
+    Count {
+        mov: String,
+        ours: u64,
+        reference: u64,
+    },
+    /// The reference plays [`mov`], but we do not generate it.
+    Missing { mov: String, reference: u64 },
+    /// We generate [`mov`], but the reference does not play it.
+    Extra { mov: String, ours: u64 },
+}
+
+/// The deepest position at which our move generator was found to disagree
+/// with a reference.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    /// The position in which the disagreeing move is played.
+    pub fen: String,
+    /// The depth the position was divided at.
+    pub depth: i32,
+    /// The moves played from the root position to reach [`fen`.]
+    pub path: Vec<Move>,
+    pub mismatch: Mismatch,
+}
+
+impl std::fmt::Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let path: Vec<String> = self.path.iter().map(Move::to_string).collect();
+        writeln!(f, "Position: {}", self.fen)?;
+        writeln!(f, "Moves from root: {}", path.join(" "))?;
+        writeln!(f, "Depth: {}", self.depth)?;
+        match &self.mismatch {
+            Mismatch::Count {
+                mov,
+                ours,
+                reference,
+            } => write!(f, "{mov}: {ours} positions, reference has {reference}"),
+            Mismatch::Missing { mov, reference } => {
+                write!(
+                    f,
+                    "{mov}: not generated, reference has {reference} positions"
+                )
+            }
+            Mismatch::Extra { mov, ours } => {
+                write!(
+                    f,
+                    "{mov}: generated with {ours} positions, but illegal in reference"
+                )
+            }
+        }
+    }
+}
+
+/// Divides the current position at [`depth`] with our own move generator.
+///
+/// Only requires a mutable reference when the [`unmove`] algorithm is being used.
+pub fn divide(board: &mut Board, depth: i32) -> Divide {
+    let mut divide = Divide::new();
+    for mov in board.gen_legal_moves() {
+        divide.insert(mov.to_string(), num_positions_after(board, &mov, depth));
+    }
+    divide
+}
+
+/// Divides like [`divide`,] with the moves shared out between [`threads`] threads,
+/// each counting on its own copy of [`board`.]
+pub fn divide_parallel(board: &Board, depth: i32, threads: usize) -> Divide {
+    let moves = board.clone().gen_legal_moves();
+    let next_move = AtomicUsize::new(0);
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.max(1))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut board = board.clone();
+                    let mut divide = Divide::new();
+                    while let Some(mov) = moves.get(next_move.fetch_add(1, Ordering::Relaxed)) {
+                        divide.insert(mov.to_string(), num_positions_after(&mut board, mov, depth));
+                    }
+                    divide
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("Divide thread panicked"))
+            .collect()
+    })
+}
+
+/// The number of positions at [`depth`] from the current position that start with [`mov`.]
+fn num_positions_after(board: &mut Board, mov: &Move, depth: i32) -> u64 {
+    let num_positions = match board.algorithm() {
+        Algorithm::Clone => {
+            let mut board = board.clone();
+            board.make_move(mov);
+            board.depth_num_positions(depth - 1)
+        }
+        Algorithm::Unmove => {
+            board.make_move(mov);
+            let n = board.depth_num_positions(depth - 1);
+            board.unmake_move();
+            n
+        }
+    };
+    u64::from(num_positions)
+}
+
+/// Compares our divide of [`board`] at [`depth`] against [`reference`,] and recursively
+/// descends into the first mismatching move until the exact position and move
+/// at which the generators disagree is found.
+///
+/// Returns `None` if the listings agree. If the reference runs out of listings
+/// while descending, the deepest mismatch it could confirm is returned.
+///
+/// # Errors
+///
+/// This function will return an error if the reference fails to provide the
+/// listing of the root position.
+pub fn find_divergence(
+    board: &Board,
+    depth: i32,
+    reference: &mut impl Reference,
+) -> Result<Option<Divergence>, Error> {
+    let mut board = board.clone();
+    let mut depth = depth;
+    let mut path: Vec<Move> = vec![];
+    let mut divergence: Option<Divergence> = None;
+
+    while depth > 0 {
+        let fen = board.to_fen();
+        let theirs = match reference.divide(&fen, depth) {
+            Ok(divide) => divide,
+            Err(Error::NoReference { .. }) if divergence.is_some() => break,
+            Err(e) => return Err(e),
+        };
+        let ours = divide(&mut board, depth);
+
+        let Some(mismatch) = first_mismatch(&ours, &theirs) else {
+            break;
+        };
+        let next_move = match &mismatch {
+            Mismatch::Count { mov, .. } => board
+                .gen_legal_moves()
+                .into_iter()
+                .find(|m| m.to_string() == *mov),
+            Mismatch::Missing { .. } | Mismatch::Extra { .. } => None,
+        };
+        divergence = Some(Divergence {
+            fen,
+            depth,
+            path: path.clone(),
+            mismatch,
+        });
+
+        let Some(mov) = next_move else {
+            break;
+        };
+        if board.algorithm() == Algorithm::Clone {
+            board.set_algorithm(Algorithm::Unmove);
+        }
+        board.make_move(&mov);
+        path.push(mov);
+        depth -= 1;
+    }
+    Ok(divergence)
+}
+
+/// Missing and extra moves are reported before count mismatches, since they
+/// pinpoint the disagreement without having to descend any further.
+fn first_mismatch(ours: &Divide, theirs: &Divide) -> Option<Mismatch> {
+    for (mov, &reference) in theirs {
+        if !ours.contains_key(mov) {
+            return Some(Mismatch::Missing {
+                mov: mov.clone(),
+                reference,
+            });
+        }
+    }
+    for (mov, &count) in ours {
+        match theirs.get(mov) {
+            None => {
+                return Some(Mismatch::Extra {
+                    mov: mov.clone(),
+                    ours: count,
+                })
+            }
+            Some(&reference) if reference != count => {
+                return Some(Mismatch::Count {
+                    mov: mov.clone(),
+                    ours: count,
+                    reference,
+                })
+            }
+            Some(_) => (),
+        }
+    }
+    None
+}
+
+fn parse_divide<'a>(lines: impl Iterator<Item = &'a str>) -> Result<Divide, Error> {
+    let mut divide = Divide::new();
+    for line in lines {
+        let Some((mov, count)) = line.trim().split_once(':') else {
+            continue;
+        };
+        let mov = mov.trim();
+        if !is_coordinate_move(mov) {
+            continue;
+        }
+        match count.trim().parse::<u64>() {
+            Ok(count) => divide.insert(mov.to_ascii_lowercase(), count),
+            Err(_) => return Err(Error::InvalidLine(line.to_string())),
+        };
+    }
+    Ok(divide)
+}
+
+fn is_coordinate_move(mov: &str) -> bool {
+    let chars: Vec<char> = mov.to_ascii_lowercase().chars().collect();
+    let is_square =
+        |file: char, rank: char| ('a'..='h').contains(&file) && ('1'..='8').contains(&rank);
+    match chars.len() {
+        4 => is_square(chars[0], chars[1]) && is_square(chars[2], chars[3]),
+        5 => {
+            is_square(chars[0], chars[1])
+                && is_square(chars[2], chars[3])
+                && matches!(chars[4], 'q' | 'r' | 'b' | 'n')
+        }
+        _ => false,
+    }
+}
+
+/// Compares only the position, turn, castling rights and en passant square,
+/// since move counters do not change the number of positions.
+fn same_position(fen1: &str, fen2: &str) -> bool {
+    let fields1: Vec<&str> = fen1.split_whitespace().take(4).collect();
+    let fields2: Vec<&str> = fen2.split_whitespace().take(4).collect();
+    fields1 == fields2
+}
@@ -0,0 +1,225 @@
+use crate::board::Board;
+use crate::color::Color;
+use crate::piece::Kind;
+
+/// The material value of a piece of [`kind`] in centipawns.
+#[must_use]
+#[rustfmt::skip]
+pub fn value(kind: Kind) -> i32 {
+    match kind {
+        Kind::Pawn   => 100,
+        Kind::Knight => 320,
+        Kind::Bishop => 330,
+        Kind::Rook   => 500,
+        Kind::Queen  => 900,
+        Kind::King   => 0,
+    }
+}
+
+/// The material value of a piece of [`kind`] in centipawns once most pieces
+/// have been traded, where pawns and rooks gain value, and minor pieces lose it.
+#[must_use]
+#[rustfmt::skip]
+pub fn endgame_value(kind: Kind) -> i32 {
+    match kind {
+        Kind::Pawn   => 120,
+        Kind::Knight => 300,
+        Kind::Bishop => 320,
+        Kind::Rook   => 530,
+        Kind::Queen  => 940,
+        Kind::King   => 0,
+    }
+}
+
+/// The game phase of a position with all non-pawn pieces on the board.
+pub const MAX_PHASE: i32 = 24;
+
+/// How much a piece of [`kind`] contributes to the game phase.
+#[must_use]
+#[rustfmt::skip]
+pub fn phase_weight(kind: Kind) -> i32 {
+    match kind {
+        Kind::Knight | Kind::Bishop => 1,
+        Kind::Rook                  => 2,
+        Kind::Queen                 => 4,
+        Kind::Pawn | Kind::King     => 0,
+    }
+}
+
+// Piece-square tables from white's perspective, laid out as the board is indexed,
+// so that a8 is the first entry. Black's tables are mirrored vertically.
+// Only kings and pawns have separate endgame tables.
+//
+// See: [Simplified Evaluation Function](https://www.chessprogramming.org/Simplified_Evaluation_Function)
+
+#[rustfmt::skip]
+const PAWN_TABLE: [i32; 64] = [// {{{
+     0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+     5,  5, 10, 25, 25, 10,  5,  5,
+     0,  0,  0, 20, 20,  0,  0,  0,
+     5, -5,-10,  0,  0,-10, -5,  5,
+     5, 10, 10,-20,-20, 10, 10,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];// }}}
+
+#[rustfmt::skip]
+const KNIGHT_TABLE: [i32; 64] = [// {{{
+    -50,-40,-30,-30,-30,-30,-40,-50,
+    -40,-20,  0,  0,  0,  0,-20,-40,
+    -30,  0, 10, 15, 15, 10,  0,-30,
+    -30,  5, 15, 20, 20, 15,  5,-30,
+    -30,  0, 15, 20, 20, 15,  0,-30,
+    -30,  5, 10, 15, 15, 10,  5,-30,
+    -40,-20,  0,  5,  5,  0,-20,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50,
+];// }}}
+
+#[rustfmt::skip]
+const BISHOP_TABLE: [i32; 64] = [// {{{
+    -20,-10,-10,-10,-10,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  5,  5, 10, 10,  5,  5,-10,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+    -10, 10, 10, 10, 10, 10, 10,-10,
+    -10,  5,  0,  0,  0,  0,  5,-10,
+    -20,-10,-10,-10,-10,-10,-10,-20,
+];// }}}
+
+#[rustfmt::skip]
+const ROOK_TABLE: [i32; 64] = [// {{{
+     0,  0,  0,  0,  0,  0,  0,  0,
+     5, 10, 10, 10, 10, 10, 10,  5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+     0,  0,  0,  5,  5,  0,  0,  0,
+];// }}}
+
+#[rustfmt::skip]
+const QUEEN_TABLE: [i32; 64] = [// {{{
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+     -5,  0,  5,  5,  5,  5,  0, -5,
+      0,  0,  5,  5,  5,  5,  0, -5,
+    -10,  5,  5,  5,  5,  5,  0,-10,
+    -10,  0,  5,  0,  0,  0,  0,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20,
+];// }}}
+
+#[rustfmt::skip]
+const KING_TABLE: [i32; 64] = [// {{{
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -20,-30,-30,-40,-40,-30,-30,-20,
+    -10,-20,-20,-20,-20,-20,-20,-10,
+     20, 20,  0,  0,  0,  0, 20, 20,
+     20, 30, 10,  0,  0, 10, 30, 20,
+];// }}}
+
+#[rustfmt::skip]
+const PAWN_ENDGAME_TABLE: [i32; 64] = [// {{{
+     0,  0,  0,  0,  0,  0,  0,  0,
+    80, 80, 80, 80, 80, 80, 80, 80,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    30, 30, 30, 30, 30, 30, 30, 30,
+    15, 15, 15, 15, 15, 15, 15, 15,
+     5,  5,  5,  5,  5,  5,  5,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];// }}}
+
+#[rustfmt::skip]
+const KING_ENDGAME_TABLE: [i32; 64] = [// {{{
+    -50,-40,-30,-20,-20,-30,-40,-50,
+    -30,-20,-10,  0,  0,-10,-20,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-30,  0,  0,  0,  0,-30,-30,
+    -50,-30,-30,-30,-30,-30,-30,-50,
+];// }}}
+
+fn table(kind: Kind) -> &'static [i32; 64] {
+    match kind {
+        Kind::Pawn => &PAWN_TABLE,
+        Kind::Knight => &KNIGHT_TABLE,
+        Kind::Bishop => &BISHOP_TABLE,
+        Kind::Rook => &ROOK_TABLE,
+        Kind::Queen => &QUEEN_TABLE,
+        Kind::King => &KING_TABLE,
+    }
+}
+
+fn endgame_table(kind: Kind) -> &'static [i32; 64] {
+    match kind {
+        Kind::Pawn => &PAWN_ENDGAME_TABLE,
+        Kind::King => &KING_ENDGAME_TABLE,
+        _ => table(kind),
+    }
+}
+
+/// The index into white's piece-square tables of [`color`'s] piece at [`index`.]
+#[must_use]
+pub fn table_index(index: usize, color: Color) -> usize {
+    match color {
+        Color::White => index,
+        Color::Black => index ^ 56,
+    }
+}
+
+impl Board {
+    /// Returns the phase of the game from the non-pawn material left on the board,
+    /// from 0 once only kings and pawns remain, to [`MAX_PHASE`] in the opening.
+    pub fn game_phase(&self) -> i32 {
+        let phase: i32 = self
+            .squares
+            .iter()
+            .flatten()
+            .map(|piece| phase_weight(piece.kind))
+            .sum();
+        phase.min(MAX_PHASE)
+    }
+
+    /// Returns a static evaluation of the current position in centipawns from the
+    /// perspective of the player whose turn it is, combining material and
+    /// piece-square tables.
+    ///
+    /// Separate midgame and endgame scores are interpolated by [`game_phase`.]
+    pub fn evaluate(&self) -> i32 {
+        let mut midgame = 0;
+        let mut endgame = 0;
+        for (index, square) in self.squares.iter().enumerate() {
+            if let Some(piece) = square {
+                let table_index = table_index(index, piece.color);
+                let midgame_score = value(piece.kind) + table(piece.kind)[table_index];
+                let endgame_score =
+                    endgame_value(piece.kind) + endgame_table(piece.kind)[table_index];
+                match piece.color {
+                    Color::White => {
+                        midgame += midgame_score;
+                        endgame += endgame_score;
+                    }
+                    Color::Black => {
+                        midgame -= midgame_score;
+                        endgame -= endgame_score;
+                    }
+                }
+            }
+        }
+        let phase = self.game_phase();
+        let score = (midgame * phase + endgame * (MAX_PHASE - phase)) / MAX_PHASE;
+        match self.state.turn() {
+            Color::White => score,
+            Color::Black => -score,
+        }
+    }
+}
@@ -0,0 +1,469 @@
+use std::io::BufRead;
+
+use crate::board::{self, Algorithm, Board, GameState};
+use crate::color::Color;
+use crate::mov::Move;
+use crate::piece::Kind;
+use crate::san;
+
+/// Why a game ended.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Termination {
+    Checkmate,
+    Stalemate,
+    /// Fifty moves by each player without a capture or a pawn move.
+    FiftyMoves,
+    /// The same position occurred for the third time.
+    Repetition,
+    /// Neither player has the material left to checkmate.
+    InsufficientMaterial,
+    Resignation,
+    /// The players agreed to a draw.
+    Agreement,
+    /// A player ran out of time.
+    Time,
+    /// The game was stopped before it ended, e.g. at a move limit.
+    Adjudication,
+}
+
+impl std::fmt::Display for Termination {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Termination::Checkmate => "checkmate",
+            Termination::Stalemate => "stalemate",
+            Termination::FiftyMoves => "fifty-move rule",
+            Termination::Repetition => "threefold repetition",
+            Termination::InsufficientMaterial => "insufficient material",
+            Termination::Resignation => "resignation",
+            Termination::Agreement => "agreement",
+            Termination::Time => "time forfeit",
+            Termination::Adjudication => "adjudication",
+        })
+    }
+}
+
+/// Writes a result the way PGN does, i.e. "1-0", "0-1", "1/2-1/2", or "*" if the game
+/// is still being played.
+#[must_use]
+pub fn result_str(state: GameState) -> &'static str {
+    match state {
+        GameState::Victory(Color::White) => "1-0",
+        GameState::Victory(Color::Black) => "0-1",
+        GameState::Draw => "1/2-1/2",
+        GameState::Playing => "*",
+    }
+}
+
+/// A game played from a starting position, which keeps the moves played, and
+/// the positions reached to tell when the game is over.
+///
+/// See: [Portable Game Notation](https://www.chessprogramming.org/Portable_Game_Notation)
+#[derive(Debug, Clone)]
+pub struct Game {
+    /// The PGN tag pairs, in the order they are written.
+    pub tags: Vec<(String, String)>,
+    start: Board,
+    board: Board,
+    moves: Vec<Move>,
+    /// The hash of every position of the game, including the current one.
+    hashes: Vec<u64>,
+    result: Option<(GameState, Termination)>,
+}
+
+impl Game {
+    /// Starts a game from [`start`,] with the tags of the Seven Tag Roster left unknown.
+    /// The `Result` tag is always written as the result of the game.
+    #[must_use]
+    pub fn new(start: &Board) -> Self {
+        let mut board = start.clone();
+        board.set_algorithm(Algorithm::Unmove);
+        let tags = ["Event", "Site", "Date", "Round", "White", "Black", "Result"]
+            .into_iter()
+            .map(|tag| {
+                let value = match tag {
+                    "Date" => "????.??.??",
+                    "Result" => "*",
+                    _ => "?",
+                };
+                (tag.to_string(), value.to_string())
+            })
+            .collect();
+        Game {
+            tags,
+            start: board.clone(),
+            hashes: vec![board.hash()],
+            board,
+            moves: vec![],
+            result: None,
+        }
+    }
+
+    /// Sets the value of [`tag`,] adding it after the other tags if it is new.
+    pub fn set_tag(&mut self, tag: &str, value: &str) {
+        match self.tags.iter_mut().find(|(name, _)| name == tag) {
+            Some((_, old)) => *old = value.to_string(),
+            None => self.tags.push((tag.to_string(), value.to_string())),
+        }
+    }
+
+    /// The current position.
+    #[must_use]
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    #[must_use]
+    pub fn start(&self) -> &Board {
+        &self.start
+    }
+
+    #[must_use]
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    /// The result and how the game ended, or `None` while it is being played.
+    #[must_use]
+    pub fn result(&self) -> Option<(GameState, Termination)> {
+        self.result
+    }
+
+    /// Ends the game for a reason the position can't tell, e.g. a resignation.
+    pub fn set_result(&mut self, state: GameState, termination: Termination) {
+        self.result = Some((state, termination));
+    }
+
+    /// Plays the legal move [`mov`,] and returns the result if it ended the game.
+    pub fn play(&mut self, mov: &Move) -> Option<(GameState, Termination)> {
+        self.board.make_move(mov);
+        self.moves.push(*mov);
+        self.hashes.push(self.board.hash());
+        self.result = self.rules_result();
+        self.result
+    }
+
+    /// Takes back the last move, which also takes back the end of the game.
+    pub fn takeback(&mut self) -> Option<Move> {
+        let mov = self.moves.pop()?;
+        self.board.unmake_move();
+        self.hashes.pop();
+        self.result = self.rules_result();
+        Some(mov)
+    }
+
+    /// The result of the current position by the rules alone, if the game is over.
+    fn rules_result(&mut self) -> Option<(GameState, Termination)> {
+        let turn = self.board.state().turn();
+        if self.board.gen_legal_moves().is_empty() {
+            return Some(match self.board.in_check() {
+                true => (GameState::Victory(turn.opposite()), Termination::Checkmate),
+                false => (GameState::Draw, Termination::Stalemate),
+            });
+        }
+        if self.board.state().halfmove_clock() >= 100 {
+            return Some((GameState::Draw, Termination::FiftyMoves));
+        }
+        let hash = self.board.hash();
+        if self.hashes.iter().filter(|&&seen| seen == hash).count() >= 3 {
+            return Some((GameState::Draw, Termination::Repetition));
+        }
+        if is_insufficient_material(&self.board) {
+            return Some((GameState::Draw, Termination::InsufficientMaterial));
+        }
+        None
+    }
+
+    /// The moves of the game in Standard Algebraic Notation.
+    #[must_use]
+    pub fn san_moves(&self) -> Vec<String> {
+        let mut board = self.start.clone();
+        self.moves
+            .iter()
+            .map(|mov| {
+                let san = san::to_san(&board, mov);
+                board.make_move(mov);
+                san
+            })
+            .collect()
+    }
+
+    /// Writes the game in PGN, with a FEN tag if it didn't start from the
+    /// standard starting position.
+    #[must_use]
+    pub fn to_pgn(&self) -> String {
+        let result = result_str(self.result.map_or(GameState::Playing, |(state, _)| state));
+        let mut tags = self.tags.clone();
+        for (_, value) in tags.iter_mut().filter(|(tag, _)| tag == "Result") {
+            *value = result.to_string();
+        }
+        let start_fen = self.start.to_fen();
+        if start_fen != board::STARTING_FEN {
+            tags.push(("SetUp".to_string(), "1".to_string()));
+            tags.push(("FEN".to_string(), start_fen));
+        }
+        let mut pgn: String = tags
+            .iter()
+            .map(|(tag, value)| format!("[{tag} \"{}\"]\n", value.replace('"', "\\\"")))
+            .collect();
+        pgn.push('\n');
+
+        let mut tokens = vec![];
+        let mut fullmove = self.start.state().fullmove_count();
+        let mut turn = self.start.state().turn();
+        for (index, san) in self.san_moves().into_iter().enumerate() {
+            match turn {
+                Color::White => tokens.push(format!("{fullmove}.")),
+                Color::Black if index == 0 => tokens.push(format!("{fullmove}...")),
+                Color::Black => {}
+            }
+            tokens.push(san);
+            if turn == Color::Black {
+                fullmove += 1;
+            }
+            turn = turn.opposite();
+        }
+        tokens.push(result.to_string());
+
+        // Movetext lines are kept within 80 characters
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > 80 {
+                pgn.push_str(&line);
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        pgn.push_str(&line);
+        pgn.push('\n');
+        pgn
+    }
+}
+
+/// Reads a result the way PGN writes it, see [`result_str`.]
+#[must_use]
+pub fn parse_result(result: &str) -> Option<GameState> {
+    match result {
+        "1-0" => Some(GameState::Victory(Color::White)),
+        "0-1" => Some(GameState::Victory(Color::Black)),
+        "1/2-1/2" => Some(GameState::Draw),
+        "*" => Some(GameState::Playing),
+        _ => None,
+    }
+}
+
+/// A game as read from PGN, whose moves are only checked when it is replayed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    /// The moves of the main line in SAN, without move numbers, annotations,
+    /// comments or variations.
+    pub moves: Vec<String>,
+    /// The result that ends the movetext, or else the `Result` tag's.
+    pub result: GameState,
+}
+
+impl PgnGame {
+    #[must_use]
+    pub fn tag(&self, tag: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(name, _)| name == tag)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The position the game starts from, which is the `FEN` tag's if it has one.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the `FEN` tag is invalid.
+    pub fn start(&self) -> Result<Board, board::Error> {
+        match self.tag("FEN") {
+            Some(fen) => Board::from_fen(fen),
+            None => Ok(Board::new()),
+        }
+    }
+
+    /// Replays the moves of the game with its tags. The result is only kept if the
+    /// rules ended the game, as PGN doesn't say why other games ended.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the start position or a move is invalid.
+    pub fn to_game(&self) -> Result<Game, board::Error> {
+        let mut game = Game::new(&self.start()?);
+        for (tag, value) in &self.tags {
+            if tag != "SetUp" && tag != "FEN" {
+                game.set_tag(tag, value);
+            }
+        }
+        for san in &self.moves {
+            let mov = game.board.move_from_san(san)?;
+            game.play(&mov);
+        }
+        Ok(game)
+    }
+}
+
+/// Reads the games of a PGN file one at a time, so that archives of any size can
+/// be read. Games don't need tags, but end at their result or the next tag section.
+///
+/// See: [Portable Game Notation](https://www.chessprogramming.org/Portable_Game_Notation)
+pub struct PgnReader<R> {
+    input: R,
+    /// A line of the next game read while looking for the end of the previous one.
+    pending: Option<String>,
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(input: R) -> Self {
+        PgnReader {
+            input,
+            pending: None,
+        }
+    }
+
+    fn read_line(&mut self, line: &mut String) -> std::io::Result<usize> {
+        line.clear();
+        match self.pending.take() {
+            Some(pending) => {
+                *line = pending;
+                Ok(line.len())
+            }
+            None => self.input.read_line(line),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = std::io::Result<PgnGame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut tags = vec![];
+        let mut moves = vec![];
+        let mut result = None;
+        let mut has_movetext = false;
+        let mut in_comment = false;
+        let mut variation_depth = 0_usize;
+        let mut line = String::new();
+        while result.is_none() {
+            match self.read_line(&mut line) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(e) => return Some(Err(e)),
+            }
+            let trimmed = line.trim();
+            if trimmed.starts_with('%') {
+                continue;
+            }
+            if !in_comment && variation_depth == 0 && trimmed.starts_with('[') {
+                if has_movetext {
+                    self.pending = Some(line.clone());
+                    break;
+                }
+                tags.extend(parse_tag(trimmed));
+                continue;
+            }
+
+            let mut tokens = vec![];
+            let mut token = String::new();
+            for c in line.chars() {
+                if in_comment {
+                    in_comment = c != '}';
+                    continue;
+                }
+                if matches!(c, '{' | ';' | '(' | ')') || c.is_whitespace() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+                match c {
+                    '{' => in_comment = true,
+                    ';' => break,
+                    '(' => variation_depth += 1,
+                    ')' => variation_depth = variation_depth.saturating_sub(1),
+                    c if c.is_whitespace() => {}
+                    c if variation_depth == 0 => token.push(c),
+                    _ => {}
+                }
+            }
+            tokens.push(token);
+            for token in tokens.iter().filter(|token| !token.is_empty()) {
+                has_movetext = true;
+                if let Some(state) = parse_result(token) {
+                    result = Some(state);
+                    break;
+                }
+                if let Some(san) = strip_move_number(token) {
+                    moves.push(san.to_string());
+                }
+            }
+        }
+        if tags.is_empty() && !has_movetext {
+            return None;
+        }
+        let result = result
+            .or_else(|| {
+                let (_, value) = tags.iter().find(|(name, _)| name == "Result")?;
+                parse_result(value)
+            })
+            .unwrap_or(GameState::Playing);
+        Some(Ok(PgnGame {
+            tags,
+            moves,
+            result,
+        }))
+    }
+}
+
+/// Reads a tag pair written as `[Name "value"]`.
+fn parse_tag(line: &str) -> Option<(String, String)> {
+    let (name, value) = line
+        .strip_prefix('[')?
+        .strip_suffix(']')?
+        .split_once(char::is_whitespace)?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+    Some((
+        name.to_string(),
+        value.replace("\\\"", "\"").replace("\\\\", "\\"),
+    ))
+}
+
+/// Strips the move number from a token of movetext, e.g. `12.` or `12...e5`,
+/// or returns `None` if nothing but the number or an annotation glyph is left.
+fn strip_move_number(token: &str) -> Option<&str> {
+    let digits = token.trim_start_matches(|c: char| c.is_ascii_digit());
+    let san = match digits.starts_with('.') {
+        true => digits.trim_start_matches('.'),
+        false => token,
+    };
+    match san.is_empty() || san.starts_with('$') {
+        true => None,
+        false => Some(san),
+    }
+}
+
+/// Returns whether neither side can possibly checkmate, i.e. only kings are left,
+/// with at most one minor piece, or bishops that all stand on squares of one color.
+#[must_use]
+pub fn is_insufficient_material(board: &Board) -> bool {
+    let mut minors = 0;
+    let mut bishop_square_colors = vec![];
+    for (index, square) in board.squares.iter().enumerate() {
+        match square.map(|piece| piece.kind) {
+            None | Some(Kind::King) => {}
+            Some(Kind::Knight) => minors += 1,
+            Some(Kind::Bishop) => {
+                minors += 1;
+                bishop_square_colors.push((index / 8 + index % 8) % 2);
+            }
+            Some(_) => return false,
+        }
+    }
+    minors <= 1
+        || (minors == bishop_square_colors.len()
+            && bishop_square_colors
+                .windows(2)
+                .all(|pair| pair[0] == pair[1]))
+}
@@ -4,8 +4,25 @@ pub mod board;
 pub mod castling;
 pub mod color;
 pub mod coordinate;
+pub mod datagen;
+pub mod divide;
+pub mod eval;
+pub mod game;
 pub mod mov;
+pub mod ordering;
+pub mod packed;
 pub mod piece;
+pub mod polyglot;
+pub mod retro;
+pub mod san;
+#[cfg(feature = "serde")]
+mod serialization;
+pub mod search;
+pub mod smp;
+pub mod solver;
+pub mod tablebase;
+pub mod tt;
+pub mod zobrist;
 
 pub mod implementations;
 
@@ -28,6 +45,96 @@ mod tests {
         ("r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10", [46, 2079, 89890]),
     ];
 
+    /// Perft results at increasing depths, checked by the tiered perft tests.
+    ///
+    /// Results with at most [`FAST_TIER_MAX_POSITIONS`] positions run by default, and the
+    /// rest run with `cargo test --release -- --ignored`.
+    ///
+    /// See: [Perft Results](https://www.chessprogramming.org/Perft_Results)
+    #[rustfmt::skip]
+    const PERFT_SUITE: &[(&str, &[(i32, u32)])] = &[
+        // Standard positions
+        ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            &[(1, 20), (2, 400), (3, 8902), (4, 197_281), (5, 4_865_609), (6, 119_060_324)]),
+        ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            &[(1, 48), (2, 2039), (3, 97_862), (4, 4_085_603), (5, 193_690_690)]),
+        ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            &[(1, 14), (2, 191), (3, 2812), (4, 43_238), (5, 674_624), (6, 11_030_083)]),
+        ("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            &[(1, 6), (2, 264), (3, 9467), (4, 422_333), (5, 15_833_292)]),
+        ("r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+            &[(1, 6), (2, 264), (3, 9467), (4, 422_333), (5, 15_833_292)]),
+        ("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            &[(1, 44), (2, 1486), (3, 62_379), (4, 2_103_487), (5, 89_941_194)]),
+        ("r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            &[(1, 46), (2, 2079), (3, 89_890), (4, 3_894_594), (5, 164_075_551)]),
+        // Castling rights lost to rook moves and captures
+        ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1",
+            &[(1, 26), (2, 568), (3, 13_744), (4, 314_346), (5, 7_594_526)]),
+        // En passant captures that are illegal, or give discovered check
+        ("3k4/3p4/8/K1P4r/8/8/8/8 b - - 0 1",
+            &[(1, 18), (2, 92), (3, 1670), (6, 1_134_888)]),
+        ("8/8/4k3/8/2p5/8/B2P2K1/8 w - - 0 1",
+            &[(1, 13), (2, 102), (3, 1266), (6, 1_015_133)]),
+        ("8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1",
+            &[(1, 15), (2, 126), (3, 1928), (6, 1_440_467)]),
+        // Castling that gives check, and castling through attacked squares
+        ("5k2/8/8/8/8/8/8/4K2R w K - 0 1",
+            &[(1, 15), (2, 66), (3, 1198), (6, 661_072)]),
+        ("3k4/8/8/8/8/8/8/R3K3 w Q - 0 1",
+            &[(1, 16), (2, 71), (3, 1286), (6, 803_711)]),
+        ("r3k2r/1b4bq/8/8/8/8/7B/R3K2R w KQkq - 0 1",
+            &[(1, 26), (2, 1141), (3, 27_826), (4, 1_274_206)]),
+        ("r3k2r/8/3Q4/8/8/5q2/8/R3K2R b KQkq - 0 1",
+            &[(1, 44), (2, 1494), (3, 50_509), (4, 1_720_476)]),
+        // Promotions out of check, and promotions that give check
+        ("2K2r2/4P3/8/8/8/8/8/3k4 w - - 0 1",
+            &[(1, 11), (2, 133), (3, 1442), (6, 3_821_001)]),
+        ("4k3/1P6/8/8/8/8/K7/8 w - - 0 1",
+            &[(1, 9), (2, 40), (3, 472), (6, 217_342)]),
+        ("8/P1k5/K7/8/8/8/8/8 w - - 0 1",
+            &[(1, 6), (2, 27), (3, 273), (6, 92_683)]),
+        // Discovered check, stalemate and checkmate
+        ("8/8/1P2K3/8/2n5/1q6/8/5k2 b - - 0 1",
+            &[(1, 29), (2, 165), (3, 5160), (5, 1_004_658)]),
+        ("K1k5/8/P7/8/8/8/8/8 w - - 0 1",
+            &[(1, 2), (2, 6), (3, 13), (6, 2217)]),
+        ("8/k1P5/8/1K6/8/8/8/8 w - - 0 1",
+            &[(1, 10), (2, 25), (3, 268), (7, 567_584)]),
+        ("8/8/2k5/5q2/5n2/8/5K2/8 b - - 0 1",
+            &[(1, 37), (2, 183), (3, 6559), (4, 23_527)]),
+        // Chess960
+        ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1",
+            &[(1, 20), (2, 400), (3, 8902), (4, 197_281)]),
+        ("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+            &[(1, 21), (2, 528), (3, 12_189), (4, 326_672), (5, 8_146_062)]),
+        ("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+            &[(1, 21), (2, 807), (3, 18_002), (4, 667_366), (5, 16_253_601)]),
+        ("b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+            &[(1, 20), (2, 479), (3, 10_471), (4, 273_318), (5, 6_417_013)]),
+        ("qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9",
+            &[(1, 22), (2, 593), (3, 13_440), (4, 382_958), (5, 9_183_776)]),
+        ("1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9",
+            &[(1, 28), (2, 1120), (3, 31_058), (4, 1_171_749), (5, 34_030_312)]),
+        ("qnbnr1kr/ppp1b1pp/4p3/3p1p2/8/2NPP3/PPP1BPPP/QNB1R1KR w HEhe - 1 9",
+            &[(1, 29), (2, 899), (3, 26_578), (4, 824_055), (5, 24_851_983)]),
+    ];
+    const FAST_TIER_MAX_POSITIONS: u32 = 100_000;
+
+    fn check_perft_suite(algorithm: Algorithm, is_in_tier: impl Fn(u32) -> bool) {
+        for (fen, results) in PERFT_SUITE {
+            for &(depth, num_positions) in results.iter().filter(|(_, n)| is_in_tier(*n)) {
+                let mut board = Board::from_fen(fen).unwrap();
+                board.set_algorithm(algorithm);
+                assert_eq!(
+                    board.depth_num_positions(depth),
+                    num_positions,
+                    "{fen} at depth {depth} with {algorithm:?}"
+                );
+            }
+        }
+    }
+
     #[rustfmt::skip]
     macro_rules! new {
         () => { Board::new() };
@@ -102,6 +209,1563 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn clone_perft_fast_tier() {
+        check_perft_suite(Algorithm::Clone, |n| n <= FAST_TIER_MAX_POSITIONS);
+    }
+
+    #[test]
+    fn unmove_perft_fast_tier() {
+        check_perft_suite(Algorithm::Unmove, |n| n <= FAST_TIER_MAX_POSITIONS);
+    }
+
+    #[test]
+    #[ignore = "deep perft tier, run with `cargo test --release -- --ignored`"]
+    fn clone_perft_deep_tier() {
+        check_perft_suite(Algorithm::Clone, |n| n > FAST_TIER_MAX_POSITIONS);
+    }
+
+    #[test]
+    #[ignore = "deep perft tier, run with `cargo test --release -- --ignored`"]
+    fn unmove_perft_deep_tier() {
+        check_perft_suite(Algorithm::Unmove, |n| n > FAST_TIER_MAX_POSITIONS);
+    }
+
+    #[test]
+    fn chess960_castling_fen() -> Result<(), board::Error> {
+        for fen in [
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+            "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+        ] {
+            assert_eq!(new!(fen)?.to_fen(), fen);
+        }
+
+        // X-FEN castling letters refer to the outermost rooks
+        let mut board = new!("1r2k1r1/pppppppp/8/8/8/8/PPPPPPPP/1R2K1R1 w KQkq - 0 1")?;
+        assert_eq!(
+            board.to_fen(),
+            "1r2k1r1/pppppppp/8/8/8/8/PPPPPPPP/1R2K1R1 w GBgb - 0 1"
+        );
+        let castling_moves: Vec<String> = board
+            .gen_legal_moves()
+            .iter()
+            .filter(|mov| mov.start_index == 60 && [57, 62].contains(&mov.target_index))
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(castling_moves, ["e1g1", "e1b1"]);
+
+        board.set_algorithm(Algorithm::Unmove);
+        board.make_move(&mov::Move::new(60, 57));
+        assert_eq!(
+            board.to_fen(),
+            "1r2k1r1/pppppppp/8/8/8/8/PPPPPPPP/2KR2R1 b gb - 1 1"
+        );
+        board.unmake_move();
+        assert_eq!(
+            board.to_fen(),
+            "1r2k1r1/pppppppp/8/8/8/8/PPPPPPPP/1R2K1R1 w GBgb - 0 1"
+        );
+        Ok(())
+    }
+
+    /// Mirrors a FEN vertically and swaps the colors of every piece, the player to move,
+    /// the castling rights and the en passant square.
+    fn mirror_fen(fen: &str) -> String {
+        let swap_case = |s: &str| -> String {
+            s.chars()
+                .map(|c| match c.is_ascii_uppercase() {
+                    true => c.to_ascii_lowercase(),
+                    false => c.to_ascii_uppercase(),
+                })
+                .collect()
+        };
+        let fields: Vec<&str> = fen.split(' ').collect();
+        let pieces: Vec<&str> = fields[0].split('/').rev().collect();
+        let turn = if fields[1] == "w" { "b" } else { "w" };
+        let ep_square = fields[3]
+            .replace('3', "x")
+            .replace('6', "3")
+            .replace('x', "6");
+        format!(
+            "{} {turn} {} {ep_square} {} {}",
+            swap_case(&pieces.join("/")),
+            swap_case(fields[2]),
+            fields[4],
+            fields[5]
+        )
+    }
+
+    #[test]
+    fn evaluation_is_color_symmetric() -> Result<(), board::Error> {
+        for (fen, _) in PERFT_SUITE {
+            let board = new!(fen)?;
+            let mirrored = new!(&mirror_fen(fen))?;
+            assert_eq!(board.evaluate(), mirrored.evaluate(), "{fen}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn evaluation_sanity() -> Result<(), board::Error> {
+        assert_eq!(new!().evaluate(), 0);
+
+        // Evaluation is from the perspective of the player to move
+        let white_up_queen = new!("4k3/8/8/8/8/8/8/3QK3 w - - 0 1")?;
+        let black_to_move = new!("4k3/8/8/8/8/8/8/3QK3 b - - 0 1")?;
+        assert!(white_up_queen.evaluate() > 800);
+        assert_eq!(black_to_move.evaluate(), -white_up_queen.evaluate());
+
+        // A knight in the center is worth more than one on the rim
+        let center_knight = new!("4k3/8/8/8/3N4/8/8/4K3 w - - 0 1")?;
+        let rim_knight = new!("4k3/8/8/8/N7/8/8/4K3 w - - 0 1")?;
+        assert!(center_knight.evaluate() > rim_knight.evaluate());
+
+        // Advanced pawns are worth more than pawns on their starting rank
+        let advanced_pawn = new!("4k3/3P4/8/8/8/8/8/4K3 w - - 0 1")?;
+        let home_pawn = new!("4k3/8/8/8/8/8/3P4/4K3 w - - 0 1")?;
+        assert!(advanced_pawn.evaluate() > home_pawn.evaluate());
+        Ok(())
+    }
+
+    #[test]
+    fn game_phase_and_tapered_evaluation() -> Result<(), board::Error> {
+        assert_eq!(new!().game_phase(), eval::MAX_PHASE);
+        assert_eq!(new!("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1")?.game_phase(), 0);
+        assert_eq!(new!("3qk3/8/8/8/8/8/8/2B1K1N1 w - - 0 1")?.game_phase(), 6);
+
+        // The king belongs in the corner with most pieces on the board,
+        // but in the center once only pawns are left
+        let sheltered_king = new!("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQ1RK1 w kq - 0 1")?;
+        let central_king = new!("rnbqkbnr/pppppppp/8/8/4K3/8/PPPPPPPP/RNBQ1R2 w kq - 0 1")?;
+        assert!(sheltered_king.evaluate() > central_king.evaluate());
+
+        let sheltered_king = new!("4k3/pppppppp/8/8/8/8/PPPPPPPP/6K1 w - - 0 1")?;
+        let central_king = new!("4k3/pppppppp/8/8/4K3/8/PPPPPPPP/8 w - - 0 1")?;
+        assert!(sheltered_king.evaluate() < central_king.evaluate());
+        Ok(())
+    }
+
+    #[test]
+    fn search_scores_repetitions_as_draws() -> Result<(), board::Error> {
+        let play = |board: &mut Board, moves: &[&str]| -> Result<(), board::Error> {
+            for uci in moves {
+                let mov = board.move_from_uci(uci)?;
+                board.make_move(&mov);
+            }
+            Ok(())
+        };
+        let mut board = new!();
+        board.set_algorithm(Algorithm::Unmove);
+        play(&mut board, &["g1f3", "g8f6", "f3g1"])?;
+        assert!(!board.is_repetition());
+        play(&mut board, &["f6g8"])?;
+        assert!(board.is_repetition());
+        play(&mut board, &["e2e4"])?;
+        assert!(!board.is_repetition());
+
+        // The losing side repeats the position, and the winning side avoids it
+        let mut board = new!("7k/8/8/8/8/8/8/2Q3K1 w - - 0 1")?;
+        board.set_algorithm(Algorithm::Unmove);
+        play(&mut board, &["c1d1", "h8g8", "d1c1"])?;
+        let result = search::search(&board, 4);
+        assert_eq!(result.best_move, Some(board.move_from_uci("g8h8")?));
+        assert_eq!(result.score, search::DRAW_SCORE);
+        play(&mut board, &["g8h8"])?;
+        let result = search::search(&board, 4);
+        assert_ne!(result.best_move, Some(board.move_from_uci("c1d1")?));
+        assert!(result.score > 0);
+
+        // Down a queen and a rook, white draws by perpetual check
+        let mut board = new!("6k1/6p1/5p2/7Q/8/8/1qr3PP/7K w - - 0 1")?;
+        let result = search::search(&board, 6);
+        assert_eq!(result.best_move, Some(board.move_from_uci("h5e8")?));
+        assert_eq!(result.score, search::DRAW_SCORE);
+        Ok(())
+    }
+
+    #[test]
+    fn search_finds_mate_in_one() -> Result<(), board::Error> {
+        let result = search::search(&new!("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1")?, 3);
+        assert_eq!(result.best_move.map(|m| m.to_string()), Some("a1a8".to_string()));
+        assert_eq!(result.score, search::MATE_SCORE - 1);
+        assert_eq!(search::mate_in(result.score), Some(1));
+        assert_eq!(result.pv.len(), 1);
+        assert!(result.nodes > 0);
+
+        // The side to move is mated, or stalemated
+        let mated = search::search(&new!("R5k1/5ppp/8/8/8/8/5PPP/6K1 b - - 0 1")?, 2);
+        assert_eq!((mated.best_move, mated.score), (None, -search::MATE_SCORE));
+        let stalemated = search::search(&new!("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1")?, 2);
+        assert_eq!((stalemated.best_move, stalemated.score), (None, search::DRAW_SCORE));
+        Ok(())
+    }
+
+    #[test]
+    fn search_wins_material() -> Result<(), board::Error> {
+        let result = search::search(&new!("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1")?, 2);
+        assert_eq!(result.best_move.map(|m| m.to_string()), Some("d2d5".to_string()));
+        assert!(result.score > eval::value(Kind::Rook) / 2);
+
+        // Taking the defended pawn loses the queen
+        let result = search::search(&new!("4k3/2p5/3p4/8/8/8/3Q4/4K3 w - - 0 1")?, 2);
+        assert_ne!(result.best_move.map(|m| m.to_string()), Some("d2d6".to_string()));
+        assert!(result.score > 0);
+        Ok(())
+    }
+
+    #[test]
+    fn search_respects_limits() -> Result<(), board::Error> {
+        use search::SearchLimits;
+        use std::sync::{atomic::AtomicBool, Arc};
+        use std::time::{Duration, Instant};
+
+        let board = new!("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3")?;
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let result = search::search_limited(&board, &SearchLimits::depth(3), &stop);
+        assert_eq!(result.depth, 3);
+        assert_eq!(result, search::search(&board, 3));
+
+        let limits = SearchLimits {
+            nodes: Some(2000),
+            ..Default::default()
+        };
+        let result = search::search_limited(&board, &limits, &stop);
+        assert!(result.best_move.is_some());
+        assert!(result.nodes < 2000 + 1024);
+
+        let limits = SearchLimits {
+            movetime: Some(Duration::from_millis(100)),
+            ..Default::default()
+        };
+        let start = Instant::now();
+        assert!(search::search_limited(&board, &limits, &stop).best_move.is_some());
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        let limits = SearchLimits {
+            wtime: Some(Duration::from_millis(300)),
+            btime: Some(Duration::from_secs(60)),
+            movestogo: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            limits.time_budget(Color::White),
+            Some((Duration::from_millis(125), Duration::from_millis(250)))
+        );
+        let start = Instant::now();
+        assert!(search::search_limited(&board, &limits, &stop).best_move.is_some());
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        // A stopped infinite search still completes its first iteration
+        stop.store(true, std::sync::atomic::Ordering::Relaxed);
+        let limits = SearchLimits {
+            infinite: true,
+            ..Default::default()
+        };
+        let result = search::search_limited(&board, &limits, &stop);
+        assert_eq!(result.depth, 1);
+        assert!(result.best_move.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn zobrist_hash() -> Result<(), board::Error> {
+        let play = |moves: &[&str]| -> Result<u64, board::Error> {
+            let mut board = new!();
+            board.set_algorithm(Algorithm::Unmove);
+            for mov in moves {
+                let legal_moves = board.gen_legal_moves();
+                let mov = legal_moves.iter().find(|m| m.to_string() == *mov);
+                board.make_move(mov.expect("move should be legal"));
+            }
+            Ok(board.hash())
+        };
+        // Transpositions hash the same, but not when the turn or castling rights differ
+        assert_eq!(
+            play(&["g1f3", "g8f6", "b1c3"])?,
+            play(&["b1c3", "g8f6", "g1f3"])?
+        );
+        assert_eq!(play(&[])?, new!().hash());
+        assert_ne!(play(&["g1f3"])?, play(&[])?);
+        assert_ne!(
+            play(&["g1f3", "g8f6", "f3g1", "f6g8"])?,
+            new!("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1")?.hash()
+        );
+        assert_ne!(
+            new!("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w Kkq - 0 1")?.hash(),
+            new!().hash()
+        );
+        assert_ne!(
+            new!("rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 1")?.hash(),
+            new!("rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1")?.hash()
+        );
+
+        let mut board = new!(TEST_FENS[1].0)?;
+        board.set_algorithm(Algorithm::Unmove);
+        let hash = board.hash();
+        for mov in board.gen_legal_moves() {
+            board.make_move(&mov);
+            assert_ne!(board.hash(), hash);
+            board.unmake_move();
+            assert_eq!(board.hash(), hash);
+        }
+
+        // The hash kept up to date by each move is the hash computed from scratch
+        for (fen, _) in PERFT_SUITE {
+            let mut board = new!(fen)?;
+            board.set_algorithm(Algorithm::Unmove);
+            for mov in board.gen_legal_moves() {
+                board.make_move(&mov);
+                assert_eq!(board.hash(), board.zobrist_hash(), "{fen} {mov}");
+                for reply in board.gen_legal_moves() {
+                    board.make_move(&reply);
+                    assert_eq!(board.hash(), board.zobrist_hash(), "{fen} {mov} {reply}");
+                    board.unmake_move();
+                }
+                if !board.in_check() {
+                    board.make_null_move();
+                    assert_eq!(board.hash(), board.zobrist_hash(), "{fen} {mov} null");
+                    board.unmake_null_move();
+                }
+                board.unmake_move();
+            }
+            assert_eq!(board.hash(), board.zobrist_hash(), "{fen}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn transposition_table() {
+        use tt::{Bound, TranspositionTable};
+
+        let tt = TranspositionTable::new(1);
+        assert_eq!(tt.size_mb(), 1);
+        let promotion = mov::Move::new(8, 0).set_promotion_kind(Kind::Knight);
+        tt.store(1, Some(promotion), 7, Bound::Lower, -search::MATE_SCORE + 3);
+        let entry = tt.probe(1).unwrap();
+        assert_eq!(entry.best_move, Some(promotion));
+        assert_eq!((entry.depth, entry.bound), (7, Bound::Lower));
+        assert_eq!(entry.score, -search::MATE_SCORE + 3);
+        assert_eq!(tt.probe(2), None);
+
+        // A shallower entry of another position in the same bucket doesn't replace a deeper one
+        let other = 1 | 1 << 40;
+        tt.store(other, None, 2, Bound::Upper, 10);
+        assert_eq!(tt.probe(1).map(|entry| entry.depth), Some(7));
+        assert_eq!(tt.probe(other).map(|entry| entry.score), Some(10));
+
+        // Entries from previous searches are replaced first
+        tt.new_search();
+        let third = 1 | 1 << 41;
+        tt.store(third, None, 1, Bound::Exact, -5);
+        assert_eq!(tt.probe(1), None);
+        assert_eq!(tt.probe(third).map(|entry| entry.score), Some(-5));
+
+        // Storing without a move keeps the previous best move
+        tt.store(third, Some(promotion), 1, Bound::Exact, -5);
+        tt.store(third, None, 3, Bound::Upper, 0);
+        assert_eq!(tt.probe(third).unwrap().best_move, Some(promotion));
+
+        tt.prefetch(third);
+        tt.clear();
+        assert_eq!(tt.probe(third), None);
+        assert_eq!(tt.hashfull(), 0);
+    }
+
+    #[test]
+    fn move_ordering() -> Result<(), board::Error> {
+        let mut board = new!("4k3/8/8/3q4/2P5/8/4p3/3QK3 w - - 0 1")?;
+        let mut moves = board.gen_legal_moves();
+        let find = |name: &str| *moves.iter().find(|m| m.to_string() == name).unwrap();
+        let (killer, other_quiet, tt_move) = (find("d1a4"), find("d1b3"), find("c4c5"));
+
+        let mut tables = ordering::OrderingTables::new();
+        tables.update_cutoff(Color::White, killer, None, &[other_quiet], 1, 4);
+        assert!(tables.history(Color::White, &killer) > 0);
+        assert!(tables.history(Color::White, &other_quiet) < 0);
+
+        // Pawn takes queen, then queen takes queen, then the king and queen take the pawn
+        tables.order(&board, &mut moves, None, 1);
+        let names: Vec<String> = moves.iter().map(mov::Move::to_string).collect();
+        assert_eq!(names[..2], ["c4d5", "d1d5"]);
+        assert!(moves[2..4].iter().all(|m| ordering::is_capture(&board, m)));
+        assert_eq!(moves[4], killer);
+        assert_eq!(moves.last(), Some(&other_quiet));
+
+        // The TT move always comes first
+        tables.order(&board, &mut moves, Some(tt_move), 1);
+        assert_eq!(moves[0], tt_move);
+        assert_eq!(moves[5], killer);
+        Ok(())
+    }
+
+    #[test]
+    fn capture_generation() -> Result<(), board::Error> {
+        for (fen, _) in TEST_FENS {
+            let mut board = new!(fen)?;
+            for mov in board.gen_legal_moves() {
+                let mut board = board.clone();
+                board.make_move(&mov);
+                let mut expected: Vec<mov::Move> = board
+                    .gen_legal_moves()
+                    .into_iter()
+                    .filter(|m| ordering::is_capture(&board, m) || m.promotion_kind.is_some())
+                    .collect();
+                let mut captures = board.gen_legal_captures();
+                expected.sort();
+                captures.sort();
+                assert_eq!(captures, expected, "{}", board.to_fen());
+            }
+        }
+        assert_eq!(new!(TEST_FENS[1].0)?.gen_legal_captures().len(), 8);
+        Ok(())
+    }
+
+    #[test]
+    fn static_exchange_evaluation() -> Result<(), board::Error> {
+        let see = |fen: &str, name: &str| -> Result<i32, board::Error> {
+            let mut board = new!(fen)?;
+            let moves = board.gen_legal_moves();
+            let mov = moves.iter().find(|m| m.to_string() == name).unwrap();
+            Ok(ordering::see(&board, mov))
+        };
+        assert_eq!(see("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1", "e1e5")?, 100);
+        // Pieces behind attackers join the exchange
+        assert_eq!(
+            see("1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1", "d3e5")?,
+            100 - eval::value(Kind::Knight)
+        );
+        // The king only recaptures on undefended squares
+        assert_eq!(see("4k3/4r3/8/8/8/8/4R3/4K3 w - - 0 1", "e2e7")?, 0);
+        assert_eq!(see("4k3/4r3/8/8/8/8/4R3/4RK2 w - - 0 1", "e2e7")?, 500);
+        assert_eq!(see("3k4/4r3/8/8/8/8/4R3/4RK2 w - - 0 1", "e2e7")?, 500);
+        assert_eq!(see("4k3/4r3/4r3/8/8/8/4R3/4K3 w - - 0 1", "e2e6")?, 0);
+        assert_eq!(see("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6")?, 100);
+        Ok(())
+    }
+
+    #[test]
+    fn quiescence_resolves_exchanges() -> Result<(), board::Error> {
+        // At depth 1, taking the defended pawn only looks good without quiescence
+        let result = search::search(&new!("4k3/8/3p4/4p3/8/8/8/4RK2 w - - 0 1")?, 1);
+        assert_ne!(result.best_move.map(|m| m.to_string()), Some("e1e5".to_string()));
+
+        // Hanging pieces are taken back beyond the horizon
+        let result = search::search(&new!("4k3/8/8/3q4/8/8/3R4/3RK3 b - - 0 1")?, 1);
+        assert_ne!(result.best_move.map(|m| m.to_string()), Some("d5d2".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn null_move() -> Result<(), board::Error> {
+        let mut board = new!("rnbqkbnr/pppp1ppp/8/8/3Pp3/8/PPP1PPPP/RNBQKBNR b KQkq d3 0 2")?;
+        board.set_algorithm(Algorithm::Unmove);
+        let (fen, hash) = (board.to_fen(), board.hash());
+        board.make_null_move();
+        assert_eq!(
+            board.to_fen(),
+            "rnbqkbnr/pppp1ppp/8/8/3Pp3/8/PPP1PPPP/RNBQKBNR w KQkq - 1 3"
+        );
+        assert_ne!(board.hash(), hash);
+        board.unmake_null_move();
+        assert_eq!((board.to_fen(), board.hash()), (fen, hash));
+        Ok(())
+    }
+
+    #[test]
+    fn selective_search() -> Result<(), board::Error> {
+        use search::{SearchConfig, Searcher};
+
+        let full_width = SearchConfig::full_width();
+        let configs = [
+            SearchConfig::default(),
+            full_width,
+            SearchConfig { null_move_pruning: true, ..full_width },
+            SearchConfig { late_move_reductions: true, ..full_width },
+            SearchConfig { reverse_futility_pruning: true, ..full_width },
+            SearchConfig { futility_pruning: true, ..full_width },
+            SearchConfig { mate_distance_pruning: true, ..full_width },
+            SearchConfig { check_extensions: true, ..full_width },
+        ];
+        let search = |fen: &str, depth: i32, config: SearchConfig| {
+            let mut searcher = Searcher::new(&new!(fen)?);
+            searcher.set_config(config);
+            Ok::<_, board::Error>(searcher.search(depth))
+        };
+        // Legal's mate, in two moves
+        let legal = "r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1";
+        for config in configs {
+            let result = search(legal, 4, config)?;
+            assert_eq!(result.best_move.map(|m| m.to_string()), Some("d5f6".to_string()));
+            assert_eq!(search::mate_in(result.score), Some(2), "{config:?}");
+
+            let result = search("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1", 3, config)?;
+            assert_eq!(result.best_move.map(|m| m.to_string()), Some("d2d5".to_string()));
+        }
+
+        let kiwipete = TEST_FENS[1].0;
+        let selective_nodes = search(kiwipete, 4, SearchConfig::default())?.nodes;
+        assert!(selective_nodes < search(kiwipete, 4, full_width)?.nodes);
+        Ok(())
+    }
+
+    #[test]
+    fn principal_variation() -> Result<(), board::Error> {
+        use search::{SearchLimits, Searcher};
+        use std::sync::{atomic::AtomicBool, Arc};
+
+        for fen in [TEST_FENS[0].0, TEST_FENS[1].0, TEST_FENS[3].0] {
+            let mut searcher = Searcher::new(&new!(fen)?);
+            let stop = Arc::new(AtomicBool::new(false));
+            let mut iterations = vec![];
+            let result = searcher.search_limited(&SearchLimits::depth(5), &stop, |result| {
+                iterations.push(result.clone());
+            });
+            assert_eq!(iterations.len(), 5);
+            assert_eq!(iterations.last(), Some(&result));
+            for iteration in iterations {
+                assert_eq!(iteration.pv.first().copied(), iteration.best_move);
+                assert!(iteration.pv.len() as i32 >= iteration.depth);
+                let mut board = new!(fen)?;
+                for mov in &iteration.pv {
+                    assert!(board.gen_legal_moves().contains(mov), "{}", board.to_fen());
+                    board.make_move(mov);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn lazy_smp() -> Result<(), board::Error> {
+        use search::SearchLimits;
+        use std::sync::{atomic::AtomicBool, Arc};
+
+        let mut smp = smp::LazySmp::new(1, 4);
+        smp.set_threads(4);
+        assert_eq!(smp.threads(), 4);
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let legal = "r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1";
+        let result = smp.search(&new!(legal)?, &SearchLimits::depth(4), &stop, |_| ());
+        assert_eq!(result.best_move.map(|m| m.to_string()), Some("d5f6".to_string()));
+        assert_eq!(search::mate_in(result.score), Some(2));
+
+        let fen = TEST_FENS[1].0;
+        let mut iterations = 0;
+        let result = smp.search(&new!(fen)?, &SearchLimits::depth(4), &stop, |_| iterations += 1);
+        assert_eq!(iterations, 4);
+        assert!(result.depth >= 4);
+        assert!(smp.tt().hashfull() > 0);
+        let mut board = new!(fen)?;
+        for mov in &result.pv {
+            assert!(board.gen_legal_moves().contains(mov));
+            board.make_move(mov);
+        }
+
+        // A single thread searches exactly like a lone searcher
+        smp.set_threads(1);
+        smp.clear();
+        let single = smp.search(&new!(fen)?, &SearchLimits::depth(3), &stop, |_| ());
+        assert_eq!(single, search::search(&new!(fen)?, 3));
+        Ok(())
+    }
+
+    #[test]
+    fn multi_pv() -> Result<(), board::Error> {
+        use search::{SearchLimits, Searcher};
+        use std::sync::{atomic::AtomicBool, Arc};
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut searcher = Searcher::new(&new!(TEST_FENS[1].0)?);
+        searcher.set_multi_pv(3);
+        let mut lines = vec![];
+        let result = searcher.search_limited(&SearchLimits::depth(3), &stop, |line| {
+            if line.depth == 3 {
+                lines.push(line.clone());
+            }
+        });
+        assert_eq!(lines.iter().map(|line| line.multipv).collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(result.best_move, lines[0].best_move);
+        for (line, next) in lines.iter().zip(&lines[1..]) {
+            assert_ne!(line.best_move, next.best_move);
+            assert!(line.score >= next.score);
+        }
+
+        // There are never more lines than legal moves
+        let mut searcher = Searcher::new(&new!("k7/8/8/8/8/8/1q6/K7 w - - 0 1")?);
+        searcher.set_multi_pv(5);
+        let mut count = 0;
+        searcher.search_limited(&SearchLimits::depth(1), &stop, |_| count += 1);
+        assert_eq!(count, 1);
+
+        // Only the moves to choose from are searched at the root, however good the others are
+        let mut board = new!("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1")?;
+        let searchmoves = vec![board.move_from_uci("d2d3")?, board.move_from_uci("e1f1")?];
+        let mut searcher = Searcher::new(&board);
+        searcher.set_multi_pv(3);
+        let limits = SearchLimits {
+            searchmoves: searchmoves.clone(),
+            ..SearchLimits::depth(3)
+        };
+        let mut lines = vec![];
+        searcher.search_limited(&limits, &stop, |line| lines.push(line.best_move.unwrap()));
+        assert_eq!(lines.len(), 6);
+        assert!(lines.iter().all(|mov| searchmoves.contains(mov)));
+        Ok(())
+    }
+
+    /// A reference that behaves like our own generator, except that it never
+    /// generates [`missing`] in the position [`fen`.]
+    struct TamperedReference {
+        fen: String,
+        missing: String,
+    }
+
+    impl TamperedReference {
+        fn count(&self, board: &mut Board, depth: i32) -> u64 {
+            if depth <= 0 {
+                return 1;
+            }
+            let mut total = 0;
+            for mov in self.moves(board) {
+                let mut board = board.clone();
+                board.make_move(&mov);
+                total += self.count(&mut board, depth - 1);
+            }
+            total
+        }
+
+        fn moves(&self, board: &mut Board) -> Vec<mov::Move> {
+            let is_tampered = board.to_fen() == self.fen;
+            board
+                .gen_legal_moves()
+                .into_iter()
+                .filter(|mov| !(is_tampered && mov.to_string() == self.missing))
+                .collect()
+        }
+    }
+
+    impl divide::Reference for TamperedReference {
+        fn divide(&mut self, fen: &str, depth: i32) -> Result<divide::Divide, divide::Error> {
+            let mut board = new!(fen)?;
+            let mut listing = divide::Divide::new();
+            for mov in self.moves(&mut board) {
+                let mut board = board.clone();
+                board.make_move(&mov);
+                listing.insert(mov.to_string(), self.count(&mut board, depth - 1));
+            }
+            Ok(listing)
+        }
+    }
+
+    #[test]
+    fn to_fen_round_trip() -> Result<(), board::Error> {
+        for (fen, _) in TEST_FENS {
+            assert_eq!(new!(fen)?.to_fen(), fen);
+        }
+        let mut board = new!();
+        board.make_move(&mov::Move::new(52, 36));
+        assert_eq!(
+            board.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn divide_listing_mismatch() -> Result<(), divide::Error> {
+        let board = new!();
+        let mut listing = divide::Listing::parse(
+            board::STARTING_FEN,
+            2,
+            "a2a3: 20\nb2b3: 20\nc2c3: 20\nd2d3: 20\ne2e3: 20\nf2f3: 20\ng2g3: 20\n\
+             h2h3: 20\na2a4: 20\nb2b4: 20\nc2c4: 20\nd2d4: 20\ne2e4: 20\nf2f4: 20\n\
+             g2g4: 20\nh2h4: 20\nb1a3: 20\nb1c3: 20\ng1f3: 20\ng1h3: 21\n\n\
+             Nodes searched: 401\n",
+        )?;
+        let divergence = divide::find_divergence(&board, 2, &mut listing)?.unwrap();
+        assert!(divergence.path.is_empty());
+        assert_eq!(
+            divergence.mismatch,
+            divide::Mismatch::Count {
+                mov: "g1h3".to_string(),
+                ours: 20,
+                reference: 21
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn divide_listing_sections() -> Result<(), divide::Error> {
+        let mut board = new!();
+        let listing_of = |divide: divide::Divide| -> String {
+            divide
+                .iter()
+                .map(|(mov, count)| format!("{mov}: {count}\n"))
+                .collect()
+        };
+        let mut root = divide::divide(&mut board, 2);
+        *root.get_mut("g1h3").unwrap() += 1;
+        board.make_move(&board.clone().move_from_uci("g1h3")?);
+        let mut after = divide::divide(&mut board, 1);
+        after.insert("e8e7".to_string(), 1);
+        let text = format!(
+            "position startpos\ngo perft 2\n{}Nodes searched: 401\n\n\
+             position startpos moves g1h3\ngo perft 1\n{}",
+            listing_of(root),
+            listing_of(after)
+        );
+        let mut listing = divide::Listing::parse(board::STARTING_FEN, 2, &text)?;
+        assert_eq!(listing.sections.len(), 2);
+
+        let divergence = divide::find_divergence(&new!(), 2, &mut listing)?.unwrap();
+        assert_eq!(divergence.fen, board.to_fen());
+        assert_eq!(divergence.path, [mov::Move::new(62, 47)]);
+        assert_eq!(
+            divergence.mismatch,
+            divide::Mismatch::Missing {
+                mov: "e8e7".to_string(),
+                reference: 1
+            }
+        );
+
+        for text in [
+            "go perft x",
+            "position startpos moves e2e5",
+            "position 8/8 w - - 0 1",
+        ] {
+            assert!(
+                divide::Listing::parse(board::STARTING_FEN, 1, text).is_err(),
+                "{text}"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn divide_finds_deep_divergence() -> Result<(), divide::Error> {
+        let mut board = new!();
+        board.make_move(&mov::Move::new(52, 36));
+        board.make_move(&mov::Move::new(12, 28));
+        let mut reference = TamperedReference {
+            fen: board.to_fen(),
+            missing: "e1e2".to_string(),
+        };
+
+        let mut root = new!();
+        let divergence = divide::find_divergence(&root, 3, &mut reference)?.unwrap();
+        assert_eq!(divergence.fen, board.to_fen());
+        assert_eq!(divergence.depth, 1);
+        assert_eq!(
+            divergence
+                .path
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            ["e2e4", "e7e5"]
+        );
+        assert_eq!(
+            divergence.mismatch,
+            divide::Mismatch::Extra {
+                mov: "e1e2".to_string(),
+                ours: 1
+            }
+        );
+
+        let mut reference = TamperedReference {
+            fen: String::new(),
+            missing: String::new(),
+        };
+        root.set_algorithm(Algorithm::Unmove);
+        assert_eq!(divide::find_divergence(&root, 3, &mut reference)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn standard_algebraic_notation() -> Result<(), board::Error> {
+        let san_of = |fen: &str, uci: &str| -> Result<String, board::Error> {
+            let mut board = new!(fen)?;
+            let mov = board.move_from_uci(uci)?;
+            assert_eq!(board.move_from_san(&san::to_san(&board, &mov))?, mov);
+            Ok(san::to_san(&board, &mov))
+        };
+        assert_eq!(san_of(board::STARTING_FEN, "g1f3")?, "Nf3");
+        assert_eq!(san_of(board::STARTING_FEN, "e2e4")?, "e4");
+        // Knights on b1 and f1 can both reach d2, and rooks on a1 and a5 can both reach a3
+        let fen = "4k3/8/8/R7/8/8/8/RN2KN2 w - - 0 1";
+        assert_eq!(san_of(fen, "b1d2")?, "Nbd2");
+        assert_eq!(san_of(fen, "a1a3")?, "R1a3");
+        assert_eq!(san_of(TEST_FENS[1].0, "e1g1")?, "O-O");
+        assert_eq!(san_of(TEST_FENS[1].0, "e1c1")?, "O-O-O");
+        assert_eq!(san_of(TEST_FENS[1].0, "d5e6")?, "dxe6");
+        assert_eq!(san_of(TEST_FENS[4].0, "d7c8q")?, "dxc8=Q");
+        assert_eq!(san_of("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "a1a8")?, "Ra8#");
+        assert_eq!(san_of("6k1/8/8/8/8/8/8/R5K1 w - - 0 1", "a1a8")?, "Ra8+");
+
+        let mut board = new!(TEST_FENS[1].0)?;
+        for san in ["0-0", "dxe6!?", "de6", "Bxa6"] {
+            assert!(board.move_from_san(san).is_ok(), "{san}");
+        }
+        for san in ["e5", "Nd2", "O-O-O-O", "Kg1", "Bb7xa6", ""] {
+            assert!(board.move_from_san(san).is_err(), "{san}");
+        }
+        // Needless disambiguation and lowercase promotions are read as the same move
+        let mut board = new!();
+        assert_eq!(board.move_from_san("Ngf3")?, board.move_from_uci("g1f3")?);
+        assert_eq!(board.move_from_san("Pe2-e4")?, board.move_from_uci("e2e4")?);
+        let mut board = new!(TEST_FENS[4].0)?;
+        assert_eq!(
+            board.move_from_san("dxc8=q")?,
+            board.move_from_uci("d7c8q")?
+        );
+        assert_eq!(board.move_from_san("d7c8n")?, board.move_from_uci("d7c8n")?);
+        assert!(board.move_from_san("dxc8").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn game_termination() -> Result<(), board::Error> {
+        use board::GameState;
+        use game::{Game, Termination};
+
+        let mut game = Game::new(&new!());
+        let mut board = game.board().clone();
+        for san in ["f3", "e5", "g4"] {
+            let mov = board.move_from_san(san)?;
+            board.make_move(&mov);
+            assert_eq!(game.play(&mov), None);
+        }
+        let mate = board.move_from_san("Qh4#")?;
+        assert_eq!(
+            game.play(&mate),
+            Some((GameState::Victory(Color::Black), Termination::Checkmate))
+        );
+        assert_eq!(game.takeback(), Some(mate));
+        assert_eq!(game.result(), None);
+
+        let mut game = Game::new(&new!());
+        let mut board = game.board().clone();
+        let shuffle = ["Nf3", "Nf6", "Ng1", "Ng8"];
+        for (ply, san) in shuffle.iter().cycle().take(8).enumerate() {
+            let mov = board.move_from_san(san)?;
+            board.make_move(&mov);
+            let expected = (ply == 7).then_some((GameState::Draw, Termination::Repetition));
+            assert_eq!(game.play(&mov), expected);
+        }
+
+        let mut game = Game::new(&new!("8/8/8/4k3/8/8/3n4/4K3 w - - 0 1")?);
+        let capture = game.board().clone().move_from_uci("e1d2")?;
+        assert_eq!(
+            game.play(&capture),
+            Some((GameState::Draw, Termination::InsufficientMaterial))
+        );
+        for (fen, insufficient) in [
+            ("8/8/8/4k3/8/8/8/2B1KB2 w - - 0 1", false),
+            ("8/8/8/4k3/8/8/8/3BKB2 w - - 0 1", true),
+            ("8/8/8/4k3/8/8/8/3NK3 w - - 0 1", true),
+            ("8/8/8/4k3/8/8/4P3/4K3 w - - 0 1", false),
+        ] {
+            assert_eq!(game::is_insufficient_material(&new!(fen)?), insufficient, "{fen}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn portable_game_notation() -> Result<(), board::Error> {
+        let mut game = game::Game::new(&new!());
+        game.set_tag("White", "Alice");
+        game.set_tag("Annotator", "tnewt");
+        let mut board = game.board().clone();
+        for san in ["e4", "e5", "Nf3", "Nc6"] {
+            let mov = board.move_from_san(san)?;
+            board.make_move(&mov);
+            game.play(&mov);
+        }
+        game.set_result(board::GameState::Draw, game::Termination::Agreement);
+        assert_eq!(
+            game.to_pgn(),
+            "[Event \"?\"]\n[Site \"?\"]\n[Date \"????.??.??\"]\n[Round \"?\"]\n\
+             [White \"Alice\"]\n[Black \"?\"]\n[Result \"1/2-1/2\"]\n[Annotator \"tnewt\"]\n\n\
+             1. e4 e5 2. Nf3 Nc6 1/2-1/2\n"
+        );
+
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 b - - 3 40";
+        let mut game = game::Game::new(&new!(fen)?);
+        let mov = game.board().clone().move_from_san("Kd7")?;
+        game.play(&mov);
+        let pgn = game.to_pgn();
+        assert!(pgn.contains(&format!("[SetUp \"1\"]\n[FEN \"{fen}\"]\n")));
+        assert!(pgn.ends_with("\n\n40... Kd7 *\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn board_diagram() {
+        let board = new!();
+        let white = board.diagram(Color::White);
+        let black = board.diagram(Color::Black);
+        assert!(white.starts_with("8  r n b q k b n r"));
+        assert!(white.ends_with("   a b c d e f g h"));
+        assert!(black.starts_with("1  R N B K Q B N R"));
+        assert!(black.ends_with("   h g f e d c b a"));
+    }
+
+    /// Polyglot keys made of arbitrary random numbers, which behave like the real table.
+    fn polyglot_keys() -> polyglot::Keys {
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(781);
+        polyglot::Keys::new((0..polyglot::NUM_RANDOMS).map(|_| rng.gen()).collect()).unwrap()
+    }
+
+    #[test]
+    fn polyglot_keys_and_moves() -> Result<(), board::Error> {
+        use polyglot::{decode_move, encode_move, Keys};
+
+        let keys = polyglot_keys();
+        let listing: Vec<String> = (0..polyglot::NUM_RANDOMS)
+            .map(|index| format!("U64(0x{index:016X}),"))
+            .collect();
+        let parsed = Keys::parse(&listing.join("\n"));
+        assert_eq!(parsed, Keys::new((0..polyglot::NUM_RANDOMS as u64).collect()));
+        assert_eq!(
+            Keys::parse("0x9D39247E33776D41ULL, 0x2AF7398005AAA5C7ULL"),
+            Err(polyglot::Error::InvalidRandoms(2))
+        );
+
+        // En passant only counts when a pawn can capture
+        let key = |fen: &str| -> Result<u64, board::Error> { Ok(keys.key(&new!(fen)?)) };
+        let after_e4 = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
+        assert_eq!(key(after_e4)?, key(&after_e4.replace("e3", "-"))?);
+        let capturable = "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 3";
+        assert_ne!(key(capturable)?, key(&capturable.replace("e3", "-"))?);
+        assert_ne!(key(board::STARTING_FEN)?, key(&board::STARTING_FEN.replace(" w ", " b "))?);
+        assert_ne!(key(board::STARTING_FEN)?, key(&board::STARTING_FEN.replace("KQkq", "Kkq"))?);
+
+        // Castling is stored as the king taking its own rook
+        let mut kiwipete = new!(TEST_FENS[1].0)?;
+        let castle = kiwipete.move_from_uci("e1g1")?;
+        assert_eq!(encode_move(&kiwipete, &castle), 7 | 4 << 6);
+        assert_eq!(decode_move(&kiwipete, 7 | 4 << 6), Some(castle));
+        assert_eq!(decode_move(&kiwipete, 6 | 4 << 6), None);
+        for (fen, _) in TEST_FENS {
+            let mut board = new!(fen)?;
+            for mov in board.gen_legal_moves() {
+                assert_eq!(decode_move(&board, encode_move(&board, &mov)), Some(mov), "{fen} {mov}");
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn polyglot_book() -> Result<(), board::Error> {
+        use polyglot::{encode_move, Book, Entry, Selection};
+        use rand::SeedableRng;
+
+        let keys = polyglot_keys();
+        let mut start = new!();
+        let start_key = keys.key(&start);
+        let mut entries = vec![];
+        for (uci, weight) in [("e2e4", 3), ("d2d4", 1), ("g1f3", 0)] {
+            let mov = start.move_from_uci(uci)?;
+            let entry = Entry { key: start_key, mov: encode_move(&start, &mov), weight, learn: 0 };
+            assert_eq!(Entry::from_bytes(entry.to_bytes()), entry);
+            entries.push(entry);
+        }
+        // A pawn can't move three squares, and another position's move comes first
+        entries.push(Entry { key: start_key, mov: 36 | 12 << 6, weight: 100, learn: 0 });
+        entries.insert(0, Entry { key: start_key - 1, mov: 0, weight: 1, learn: 0 });
+        let bytes: Vec<u8> = entries.iter().flat_map(Entry::to_bytes).collect();
+        assert_eq!(
+            Book::from_bytes(&bytes[1..], keys.clone()).map(|_| ()),
+            Err(polyglot::Error::InvalidBookSize(bytes.len() - 1))
+        );
+        let book = Book::from_bytes(&bytes, keys).unwrap();
+
+        let moves: Vec<(String, u16)> = book
+            .moves(&start)
+            .iter()
+            .map(|(mov, weight)| (mov.to_string(), *weight))
+            .collect();
+        let expected = [("e2e4", 3), ("d2d4", 1), ("g1f3", 0)];
+        assert_eq!(moves, expected.map(|(uci, weight)| (uci.to_string(), weight)));
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(43);
+        let best = book.choose(&start, Selection::Best, &mut rng).map(|mov| mov.to_string());
+        assert_eq!(best.as_deref(), Some("e2e4"));
+        let picks: Vec<String> = (0..200)
+            .filter_map(|_| book.choose(&start, Selection::Weighted, &mut rng))
+            .map(|mov| mov.to_string())
+            .collect();
+        let e4_picks = picks.iter().filter(|&uci| uci == "e2e4").count();
+        assert!(picks.iter().all(|uci| uci == "e2e4" || uci == "d2d4"));
+        assert!(picks.len() == 200 && e4_picks > 100 && e4_picks < 200, "{e4_picks}");
+        assert_eq!(book.choose(&new!(TEST_FENS[1].0)?, Selection::Best, &mut rng), None);
+
+        // The search plays the book's move without searching
+        let mut smp = smp::LazySmp::new(1, 1);
+        smp.set_book(Some((std::sync::Arc::new(book), Selection::Best)));
+        let stop = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let result = smp.search(&start, &search::SearchLimits::depth(4), &stop, |_| ());
+        assert_eq!(result.best_move.map(|mov| mov.to_string()).as_deref(), Some("e2e4"));
+        assert_eq!((result.depth, result.nodes), (0, 0));
+        let result = smp.search(&new!(TEST_FENS[2].0)?, &search::SearchLimits::depth(4), &stop, |_| ());
+        assert_eq!(result.depth, 4);
+        Ok(())
+    }
+
+    #[test]
+    fn portable_game_notation_reading() -> Result<(), board::Error> {
+        use game::{PgnGame, PgnReader};
+
+        let pgn = "[Event \"Casual \\\"game\\\"\"]\n[Result \"1-0\"]\n\n\
+                   1. e4 {a comment\nover two lines} e5 2.Nf3 (2. Nc3 Nf6 (2... Nc6)) 2... Nc6 $1\n\
+                   3. Bb5!? ; the Ruy Lopez\n\
+                   a6 1-0\n\
+                   %escaped line\n\
+                   1. d4 d5 *\n\
+                   [Event \"Unfinished\"]\n[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 3 40\"]\n\n\
+                   40... Kd7 41. e4\n\
+                   [Event \"Illegal\"]\n\n1. e5 0-1\n";
+        let games: Vec<PgnGame> = PgnReader::new(pgn.as_bytes()).collect::<Result<_, _>>().unwrap();
+        assert_eq!(games.len(), 4);
+        assert_eq!(games[0].tag("Event"), Some("Casual \"game\""));
+        assert_eq!(games[0].moves, ["e4", "e5", "Nf3", "Nc6", "Bb5!?", "a6"]);
+        assert_eq!(games[0].result, board::GameState::Victory(color::Color::White));
+        assert_eq!((games[1].tags.len(), games[1].moves.len()), (0, 2));
+        assert_eq!(games[1].result, board::GameState::Playing);
+        assert_eq!(games[2].moves, ["Kd7", "e4"]);
+        // Without a result in the movetext, the game's result is the tag's
+        assert_eq!(games[2].result, board::GameState::Playing);
+        assert_eq!(games[2].start()?.state().fullmove_count(), 40);
+        assert_eq!(games[3].result, board::GameState::Victory(color::Color::Black));
+        assert_eq!(games[3].to_game().map(|_| ()), Err(board::Error::IllegalMove("e5".to_string())));
+
+        // Games written as PGN read back the same
+        let game = games[0].to_game()?;
+        assert_eq!(game.san_moves(), ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6"]);
+        let read = PgnReader::new(game.to_pgn().as_bytes()).next().unwrap().unwrap();
+        assert_eq!(read.to_game()?.san_moves(), game.san_moves());
+        let game = games[2].to_game()?;
+        let read = PgnReader::new(game.to_pgn().as_bytes()).next().unwrap().unwrap();
+        assert_eq!(read.start()?.to_fen(), "4k3/8/8/8/8/8/4P3/4K3 b - - 3 40");
+        assert_eq!(read.moves, ["Kd7", "e4"]);
+        Ok(())
+    }
+
+    #[test]
+    fn polyglot_book_builder() -> Result<(), board::Error> {
+        use game::PgnReader;
+        use polyglot::{BookBuilder, BuildOptions, ResultWeights};
+
+        let pgn = "1. e4 e5 2. Nf3 1-0\n1. e4 c5 0-1\n1. e4 e5 1/2-1/2\n1. d4 d5 0-1\n1. c4 *\n";
+        let build = |options: BuildOptions| {
+            let mut builder = BookBuilder::new(polyglot_keys(), options);
+            for game in PgnReader::new(pgn.as_bytes()) {
+                builder.add_game(&game.unwrap()).unwrap();
+            }
+            builder.build()
+        };
+        let moves = |book: &polyglot::Book, moves: &str| -> Result<Vec<(String, u16)>, board::Error> {
+            let mut board = new!();
+            for uci in moves.split_whitespace() {
+                let mov = board.move_from_uci(uci)?;
+                board.make_move(&mov);
+            }
+            Ok(book.moves(&board).iter().map(|(mov, weight)| (mov.to_string(), *weight)).collect())
+        };
+        let weighted = |moves: &[(&str, u16)]| -> Vec<(String, u16)> {
+            moves.iter().map(|&(uci, weight)| (uci.to_string(), weight)).collect()
+        };
+
+        // White scored 2 + 0 + 1 half points with e4, and lost with d4 and c4 never finished
+        let book = build(BuildOptions::default());
+        assert_eq!(moves(&book, "")?, weighted(&[("e2e4", 3)]));
+        assert_eq!(moves(&book, "e2e4")?, weighted(&[("c7c5", 2), ("e7e5", 1)]));
+        assert_eq!(moves(&book, "d2d4")?, weighted(&[("d7d5", 2)]));
+        assert_eq!(moves(&book, "e2e4 e7e5")?, weighted(&[("g1f3", 2)]));
+        let reread = polyglot::Book::from_bytes(&book.to_bytes(), polyglot_keys()).unwrap();
+        assert_eq!(reread.entries(), book.entries());
+
+        let book = build(BuildOptions { max_plies: 1, min_count: 2, ..BuildOptions::default() });
+        assert_eq!(moves(&book, "")?, weighted(&[("e2e4", 3)]));
+        assert_eq!(book.entries().len(), 1);
+
+        // Weights that don't fit in an entry are scaled down
+        let weights = ResultWeights { win: 40_000, draw: 30_000, loss: 1 };
+        let book = build(BuildOptions { weights, ..BuildOptions::default() });
+        assert_eq!(moves(&book, "")?, weighted(&[("e2e4", u16::MAX), ("d2d4", 1)]));
+        assert_eq!(moves(&book, "e2e4")?, weighted(&[("c7c5", 40_000), ("e7e5", 30_001)]));
+        Ok(())
+    }
+
+    /// Whether [`goal`] is reached by looking at every line, with the first side to
+    /// move if [`first`,] and [`left`] moves of the first side left.
+    fn reaches_goal(board: &Board, goal: solver::Goal, first: bool, left: u32) -> bool {
+        use solver::Goal;
+
+        let moves = board.clone().gen_legal_moves();
+        let mated = moves.is_empty() && board.in_check();
+        let left_after = if first { left } else { left - 1 };
+        let mut next = moves.iter().map(|mov| {
+            let mut child = board.clone();
+            child.make_move(mov);
+            reaches_goal(&child, goal, !first, left_after)
+        });
+        match (goal, first) {
+            (Goal::Mate, true) => left > 0 && next.any(|reached| reached),
+            (Goal::Mate, false) => mated || (!moves.is_empty() && left > 1 && next.all(|reached| reached)),
+            (_, true) => mated || (left > 0 && next.any(|reached| reached)),
+            (Goal::SelfMate, false) => !moves.is_empty() && next.all(|reached| reached),
+            (Goal::HelpMate, false) => next.any(|reached| reached),
+        }
+    }
+
+    #[test]
+    fn mate_solver() -> Result<(), board::Error> {
+        use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+        use solver::{Goal, Line, Solver, Stipulation};
+
+        for (text, goal, moves) in [("#2", Goal::Mate, 2), ("h#3", Goal::HelpMate, 3), ("s#1", Goal::SelfMate, 1)] {
+            let stipulation: Stipulation = text.parse().unwrap();
+            assert_eq!(stipulation, Stipulation { goal, moves });
+            assert_eq!(stipulation.to_string(), text);
+        }
+        for text in ["#0", "x#2", "2", "h#", "#-1"] {
+            assert!(text.parse::<Stipulation>().is_err(), "{text}");
+        }
+        let solve = |fen: &str, stipulation: &str| -> Result<solver::Solution, board::Error> {
+            Ok(Solver::new(stipulation.parse().unwrap()).solve(&new!(fen)?))
+        };
+        let line = |uci: &str, continuations: Vec<Line>| -> Result<Line, board::Error> {
+            let index = |square: &str| -> Result<usize, board::Error> {
+                Ok(coordinate::Coordinate::from(square)?.expect("A square").into_index())
+            };
+            let mov = mov::Move::new(index(&uci[..2])?, index(&uci[2..])?);
+            Ok(Line { mov, continuations })
+        };
+
+        let solution = solve("k7/8/1K6/8/8/8/7Q/8 w - - 0 1", "#1")?;
+        assert_eq!(solution.keys, [line("h2h8", vec![])?]);
+        assert!(solution.cooks().is_empty() && solution.duals().is_empty());
+        assert!(solve("k7/8/1K6/8/8/8/7Q/8 w - - 0 1", "s#1")?.keys.is_empty());
+        // Black walks into the corner for the rook to mate
+        let solution = solve("k7/8/1K6/8/8/8/8/7R b - - 0 1", "h#1")?;
+        assert_eq!(solution.keys, [line("a8b8", vec![line("h1h8", vec![])?])?]);
+
+        // White forces black's only move, which mates
+        let solution = solve("R7/8/8/8/8/5p2/3N1kPP/7K w - - 0 1", "s#2")?;
+        assert_eq!(solution.keys, [line("a8e8", vec![line("f3g2", vec![])?])?]);
+
+        let uci = |moves: &[mov::Move]| -> Vec<String> { moves.iter().map(|mov| mov.to_string()).collect() };
+        let solution = solve("1R6/k7/8/8/6R1/p7/K7/8 w - - 2 5", "#2")?;
+        let keys: Vec<mov::Move> = solution.keys.iter().map(|key| key.mov).collect();
+        assert_eq!(uci(&keys), ["b8b3", "b8b1", "g4b4"]);
+        assert_eq!(solution.cooks().len(), 2);
+        let duals = solution.duals();
+        assert_eq!(duals.len(), 1);
+        assert_eq!(uci(&duals[0].line), ["g4b4", "a7a6"]);
+        assert_eq!(uci(&duals[0].moves), ["b8a8", "b4a4"]);
+
+        // The solver finds the same keys as looking at every line
+        let mut rng = StdRng::seed_from_u64(47);
+        for fen in ["6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1", "8/8/8/3k4/8/8/2Q5/3K4 w - - 0 1", "7k/8/5K2/8/8/8/8/6qR w - - 0 1"] {
+            let mut board = new!(fen)?;
+            for _ in 0..4 {
+                for stipulation in ["#1", "#2", "h#1", "h#2", "s#1", "s#2"] {
+                    let stipulation: Stipulation = stipulation.parse().unwrap();
+                    let mut keys: Vec<mov::Move> = board
+                        .clone()
+                        .gen_legal_moves()
+                        .into_iter()
+                        .filter(|mov| {
+                            let mut child = board.clone();
+                            child.make_move(mov);
+                            reaches_goal(&child, stipulation.goal, false, stipulation.moves)
+                        })
+                        .collect();
+                    let mut solution: Vec<mov::Move> =
+                        Solver::new(stipulation).solve(&board).keys.iter().map(|key| key.mov).collect();
+                    keys.sort();
+                    solution.sort();
+                    assert_eq!(solution, keys, "{stipulation} {}", board.to_fen());
+                }
+                let moves = board.gen_legal_moves();
+                let Some(mov) = moves.choose(&mut rng) else {
+                    break;
+                };
+                board.make_move(mov);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn packed_positions() -> Result<(), packed::Error> {
+        use packed::{PackedPosition, PackedReader, PackedWriter, PACKED_SIZE};
+        use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+        let mut boards = vec![];
+        for (fen, _) in TEST_FENS {
+            boards.push(Board::from_fen(fen).unwrap());
+        }
+        for fen in [
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+            "1r2k3/8/8/8/8/8/8/R3K2R b Kb - 70000 1",
+            "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 16777215",
+        ] {
+            boards.push(Board::from_fen(fen).unwrap());
+        }
+        let mut rng = StdRng::seed_from_u64(49);
+        let mut board = new!();
+        for _ in 0..300 {
+            let moves = board.gen_legal_moves();
+            let Some(mov) = moves.choose(&mut rng) else {
+                board = new!();
+                continue;
+            };
+            board.make_move(mov);
+            boards.push(board.clone());
+        }
+
+        let mut writer = PackedWriter::new(vec![]);
+        for board in &boards {
+            if board.state().halfmove_clock() > u16::MAX.into() {
+                assert!(matches!(
+                    writer.write(board),
+                    Err(packed::Error::Unpackable(_))
+                ));
+                continue;
+            }
+            let packed = PackedPosition::pack(board)?;
+            let unpacked = packed.unpack()?;
+            assert_eq!(unpacked.to_fen(), board.to_fen());
+            assert_eq!(
+                unpacked.state().castling_state(),
+                board.state().castling_state()
+            );
+            writer.write(board)?;
+        }
+        let written = writer.written() as usize;
+        assert_eq!(written, boards.len() - 1);
+        let bytes = writer.finish()?;
+        assert_eq!(bytes.len(), written * PACKED_SIZE);
+
+        let read: Vec<Board> = PackedReader::new(bytes.as_slice()).collect::<Result<_, _>>()?;
+        let fens = |boards: &[Board]| boards.iter().map(Board::to_fen).collect::<Vec<_>>();
+        let packable: Vec<Board> = boards
+            .into_iter()
+            .filter(|board| board.state().halfmove_clock() <= u16::MAX.into())
+            .collect();
+        assert_eq!(fens(&read), fens(&packable));
+
+        let mut truncated = PackedReader::new(&bytes[..PACKED_SIZE + 5]);
+        assert!(truncated.next().is_some_and(|board| board.is_ok()));
+        assert_eq!(
+            truncated.next().unwrap().err(),
+            Some(packed::Error::Truncated(5))
+        );
+        assert!(truncated.next().is_none());
+
+        let mut invalid = PackedPosition::pack(&new!())?.to_bytes();
+        invalid[8] = 0xFC;
+        assert!(PackedPosition::from_bytes(invalid).unpack().is_err());
+        let full = "qqqqkqqq/qqqqqqqq/8/8/8/8/QQQQQQQQ/QQQQKQQQ w - - 0 1";
+        assert!(PackedPosition::pack(&Board::from_fen(full).unwrap()).is_ok());
+        let overfull = "qqqqkqqq/qqqqqqqq/8/8/8/Q7/QQQQQQQQ/QQQQKQQQ w - - 0 1";
+        assert!(matches!(
+            PackedPosition::pack(&Board::from_fen(overfull).unwrap()),
+            Err(packed::Error::Unpackable(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn self_play_training_data() -> Result<(), packed::Error> {
+        use datagen::{Format, Options, Sample, SampleWriter, SAMPLE_SIZE};
+        use search::SearchLimits;
+
+        let options = Options {
+            games: 4,
+            threads: 2,
+            limits: SearchLimits::depth(2),
+            max_plies: 40,
+            hash_mb: 1,
+            seed: 50,
+            ..Options::default()
+        };
+        let mut writer = SampleWriter::new(vec![], Format::Binary);
+        assert_eq!(datagen::generate(&options, &mut writer)?, 4);
+        let written = writer.written() as usize;
+        let bytes = writer.finish()?;
+        assert!(written > 0);
+        assert_eq!(bytes.len(), written * SAMPLE_SIZE);
+
+        let mut lines = vec![];
+        for chunk in bytes.chunks_exact(SAMPLE_SIZE) {
+            let sample = Sample::from_bytes(chunk.try_into().unwrap())?;
+            assert_eq!(sample.to_bytes(), chunk);
+            let board = sample.position.unpack()?;
+            assert!(!board.in_check(), "{}", board.to_fen());
+            assert!(!search::is_mate_score(sample.score.into()));
+            assert_ne!(sample.result, board::GameState::Playing);
+            lines.push(sample.to_text()?);
+        }
+
+        // The same seed plays the same games, whichever format they are written in
+        let mut writer = SampleWriter::new(vec![], Format::Text);
+        datagen::generate(&options, &mut writer)?;
+        let text = String::from_utf8(writer.finish()?).unwrap();
+        let mut text_lines: Vec<String> = text.lines().map(str::to_string).collect();
+        for line in &text_lines {
+            let [fen, score, result] = line.split(" | ").collect::<Vec<_>>()[..] else {
+                panic!("{line}");
+            };
+            assert!(
+                Board::from_fen(fen).is_ok() && score.parse::<i16>().is_ok(),
+                "{line}"
+            );
+            assert!(["1.0", "0.5", "0.0"].contains(&result), "{line}");
+        }
+        lines.sort();
+        text_lines.sort();
+        assert_eq!(lines, text_lines);
+
+        let mut invalid = [0; SAMPLE_SIZE];
+        invalid[SAMPLE_SIZE - 1] = 3;
+        assert!(Sample::from_bytes(invalid).is_err());
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_encodings() -> Result<(), board::Error> {
+        use coordinate::Coordinate;
+        use mov::Move;
+
+        const FEN: &str = "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2";
+        let board = Board::from_fen(FEN)?;
+        let json = serde_json::to_string(&board).unwrap();
+        assert_eq!(json, format!("\"{FEN}\""));
+        assert_eq!(serde_json::from_str::<Board>(&json).unwrap().to_fen(), FEN);
+        assert_eq!(
+            serde_json::to_string(&board.state).unwrap(),
+            "\"w KQkq e6 0 2\""
+        );
+        let empty = Board::from_fen("8/8/8/4k3/8/8/8/4K3 b - - 12 40")?;
+        assert_eq!(
+            serde_json::to_string(&empty.state).unwrap(),
+            "\"b - - 12 40\""
+        );
+        let state: board::State = serde_json::from_str("\"w KQkq e3 0 1\"").unwrap();
+        assert_eq!(state.turn(), Color::White);
+        assert_eq!(state.ep_index(), Some(44));
+        assert_eq!(serde_json::to_string(&state).unwrap(), "\"w KQkq e3 0 1\"");
+        for text in ["b Kq - 3 12", "w - - 0 1", "b kq c6 0 2"] {
+            let json = format!("\"{text}\"");
+            let state: board::State = serde_json::from_str(&json).unwrap();
+            assert_eq!(serde_json::to_string(&state).unwrap(), json);
+        }
+        for text in [
+            "w HAha - 0 1",
+            "w KQkq e3 0",
+            "x KQkq - 0 1",
+            "w KQkq - 0 1 1",
+        ] {
+            let json = format!("\"{text}\"");
+            assert!(serde_json::from_str::<board::State>(&json).is_err(), "{text}");
+        }
+
+        let promotion = Move::from_uci("e7e8q")?;
+        assert_eq!(promotion, Move::new(12, 4).set_promotion_kind(Kind::Queen));
+        assert_eq!(serde_json::to_string(&promotion).unwrap(), "\"e7e8q\"");
+        assert_eq!(
+            serde_json::from_str::<Move>("\"g1f3\"").unwrap(),
+            Move::new(62, 45)
+        );
+
+        let knight = Piece {
+            kind: Kind::Knight,
+            color: Color::White,
+        };
+        assert_eq!(serde_json::to_string(&knight).unwrap(), "\"N\"");
+        let pawn: Piece = serde_json::from_str("\"p\"").unwrap();
+        assert_eq!(
+            pawn,
+            Piece {
+                kind: Kind::Pawn,
+                color: Color::Black
+            }
+        );
+
+        let e4: Coordinate = serde_json::from_str("\"e4\"").unwrap();
+        assert_eq!(e4.into_index(), 36);
+        assert_eq!(serde_json::to_string(&e4).unwrap(), "\"e4\"");
+        assert_eq!(serde_json::to_string(&Color::White).unwrap(), "\"w\"");
+        assert_eq!(
+            serde_json::from_str::<Color>("\"b\"").unwrap(),
+            Color::Black
+        );
+
+        assert!(serde_json::from_str::<Board>("\"8/8/8 w - - 0 1\"").is_err());
+        for uci in ["e2", "e2e9", "e7e8k", "e2e4qq", "\u{e9}2e4"] {
+            assert!(Move::from_uci(uci).is_err(), "{uci}");
+        }
+        assert!(serde_json::from_str::<Piece>("\"x\"").is_err());
+        assert!(serde_json::from_str::<Coordinate>("\"-\"").is_err());
+        assert!(serde_json::from_str::<Color>("\"white\"").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn endgame_tablebases() -> Result<(), board::Error> {
+        use search::{SearchLimits, Searcher};
+        use std::sync::{atomic::AtomicBool, Arc};
+        use tablebase::{Material, Probe, Table, Tablebases, Wdl};
+
+        let mut tablebases = Tablebases::new();
+        let kqk: Material = "KQK".parse().unwrap();
+        let generated = tablebases.generate(&kqk).unwrap();
+        assert_eq!(generated, ["KK".parse().unwrap(), kqk.clone()]);
+        assert!(tablebases.generate(&"KKQ".parse().unwrap()).unwrap().is_empty());
+        assert_eq!(tablebases.get(&kqk).unwrap().longest_mate(), 19);
+
+        let probe = |fen: &str| -> Result<Option<Probe>, board::Error> { Ok(tablebases.probe(&new!(fen)?)) };
+        let win = Some(Probe { wdl: Wdl::Win, dtm: 1 });
+        assert_eq!(probe("k7/8/1K6/8/8/8/7Q/8 w - - 0 1")?, win);
+        assert_eq!(probe("8/7q/8/8/8/1k6/8/K7 b - - 0 1")?, win);
+        assert_eq!(probe("k6Q/8/1K6/8/8/8/8/8 b - - 0 1")?, Some(Probe { wdl: Wdl::Loss, dtm: 0 }));
+        assert_eq!(probe("8/8/8/3k4/8/8/8/3K4 w - - 0 1")?, Some(Probe { wdl: Wdl::Draw, dtm: 0 }));
+        // The queen is lost at once
+        assert_eq!(probe("7K/8/8/8/8/8/1k6/1Q6 b - - 0 1")?, Some(Probe { wdl: Wdl::Draw, dtm: 0 }));
+        assert_eq!(probe("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")?, None);
+        assert_eq!(probe("4k3/8/8/8/8/8/8/R3K3 w - - 0 1")?, None);
+
+        // The best move of a win leads to a loss a ply sooner
+        let mut board = new!();
+        let (white, black) = (Color::White, Color::Black);
+        for (king, queen, enemy) in (0..64).map(|i| (27 + i % 3, i, (i * 37 + 5) % 64)) {
+            if king == queen || queen == enemy || enemy == king {
+                continue;
+            }
+            let mut squares = [None; 64];
+            squares[king] = Some(Piece { kind: Kind::King, color: white });
+            squares[queen] = Some(Piece { kind: Kind::Queen, color: white });
+            squares[enemy] = Some(Piece { kind: Kind::King, color: black });
+            board.set_position(squares, white)?;
+            let Some((mov, Probe { wdl: Wdl::Win, dtm })) = tablebases.best_move(&board) else {
+                continue;
+            };
+            board.make_move(&mov);
+            assert_eq!(tablebases.probe(&board), Some(Probe { wdl: Wdl::Loss, dtm: dtm - 1 }));
+        }
+
+        let table = tablebases.get(&kqk).unwrap();
+        let bytes = table.to_bytes();
+        // Less than a byte for each of the 2 turns, 10 white king and 64 * 64 other squares
+        assert!(bytes.len() < 2 * 10 * 64 * 64 * 3 / 4);
+        let reread = Table::from_bytes(&bytes).unwrap();
+        assert_eq!(reread.to_bytes(), bytes);
+        assert!(Table::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+        // The search finds the mate a table knows beyond its horizon
+        let fen = "8/8/8/3k4/8/8/8/Q3K3 w - - 0 1";
+        let dtm = probe(fen)?.unwrap().dtm;
+        assert!(dtm > 4);
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut searcher = Searcher::new(&new!(fen)?);
+        searcher.set_tablebases(Some(Arc::new(tablebases)));
+        let result = searcher.search_limited(&SearchLimits::depth(2), &stop, |_| ());
+        assert_eq!(search::mate_in(result.score), Some(dtm.div_ceil(2) as i32));
+        Ok(())
+    }
+
+    #[test]
+    #[ignore = "larger tables, run with `cargo test --release -- --ignored`"]
+    fn endgame_tablebases_longest_mates() {
+        let mut tablebases = tablebase::Tablebases::new();
+        for (ending, plies) in [("KRK", 31), ("KPK", 55), ("KBNK", 65), ("KQKR", 69)] {
+            let material = ending.parse().unwrap();
+            tablebases.generate(&material).unwrap();
+            assert_eq!(tablebases.get(&material).unwrap().longest_mate(), plies, "{ending}");
+        }
+    }
+
+    #[test]
+    fn retro_moves() -> Result<(), board::Error> {
+        use mov::Move;
+        use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+        use retro::RetroMove;
+
+        let fens = |board: &Board| -> Vec<String> {
+            let mut fens: Vec<String> =
+                board.predecessors().iter().map(|(_, before)| before.to_fen()).collect();
+            fens.sort();
+            fens
+        };
+
+        // Only the double step leads to an en passant square
+        let board = new!("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1")?;
+        assert_eq!(fens(&board), ["rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"]);
+        // Black's knights may have gone back, but nothing they captured would fit
+        assert_eq!(new!().predecessors().len(), 4);
+
+        let board = new!("4k3/8/3P4/8/8/8/8/4K3 b - - 0 9")?;
+        let mov = Move::new(28, 19);
+        let ep = RetroMove { mov, uncaptured: Some(Kind::Pawn), en_passant: true };
+        assert!(board.gen_retro_moves().contains(&ep));
+        assert_eq!(board.retract(&ep).unwrap().to_fen(), "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 9");
+        let capture = RetroMove { mov, uncaptured: Some(Kind::Knight), en_passant: false };
+        assert_eq!(board.retract(&capture).unwrap().to_fen(), "4k3/8/3n4/4P3/8/8/8/4K3 w - - 0 9");
+        // A pawn is never captured on the last rank, nor a king anywhere
+        assert!(board.retract(&RetroMove { uncaptured: Some(Kind::King), ..capture }).is_none());
+
+        let board = new!("4k2Q/8/8/8/8/8/8/4K3 b - - 3 20")?;
+        let retros = board.gen_retro_moves();
+        let unpromotion = Move::new(15, 7).set_promotion_kind(Kind::Queen);
+        assert!(retros.contains(&RetroMove { mov: unpromotion, uncaptured: None, en_passant: false }));
+        let unpromotion = Move::new(14, 7).set_promotion_kind(Kind::Queen);
+        assert!(retros.contains(&RetroMove { mov: unpromotion, uncaptured: Some(Kind::Rook), en_passant: false }));
+        assert!(!retros.iter().any(|retro| retro.uncaptured == Some(Kind::Pawn) && retro.mov.target_index == 7));
+        let quiet = RetroMove { mov: Move::new(63, 7), uncaptured: None, en_passant: false };
+        assert_eq!(board.retract(&quiet).unwrap().to_fen(), "4k3/8/8/8/8/8/8/4K2Q w - - 2 20");
+        // From e5 the queen would have given check with white to move
+        let illegal = RetroMove { mov: Move::new(28, 7), ..quiet };
+        assert!(board.retract(&illegal).is_none());
+
+        // Each move is found again by taking it back, and each move taken back leads to the position
+        let mut rng = StdRng::seed_from_u64(46);
+        for fen in TEST_FENS.map(|(fen, _)| fen) {
+            let mut board = new!(fen)?;
+            for _ in 0..12 {
+                let moves = board.gen_legal_moves();
+                let Some(&mov) = moves.choose(&mut rng) else {
+                    break;
+                };
+                let castles = board.squares[mov.target_index].is_some_and(|piece| piece.color == board.state().turn())
+                    || board.squares[mov.start_index].is_some_and(|piece| piece.kind == Kind::King)
+                        && mov.start_index.abs_diff(mov.target_index) == 2;
+                let mut after = board.clone();
+                after.make_move(&mov);
+                let predecessors = after.predecessors();
+                assert_eq!(
+                    predecessors.iter().any(|(retro, before)| retro.mov == mov && before.squares == board.squares),
+                    !castles,
+                    "{} {mov}",
+                    board.to_fen()
+                );
+                for (retro, before) in predecessors {
+                    let mut before = before;
+                    before.make_move(&retro.mov);
+                    assert_eq!(before.squares, after.squares);
+                }
+                board = after;
+            }
+        }
+        Ok(())
+    }
+
     #[test]
     fn clone_play_random_games() -> Result<(), board::Error> {
         let mut board = new!();
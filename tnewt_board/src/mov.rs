@@ -31,6 +31,29 @@ impl Move {
         }
     }
 
+    /// Reads the move written as [`uci`] in coordinate notation, e.g. "e2e4" or "e7e8q",
+    /// without checking that it is legal in any position.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if [`uci`] isn't two squares and an optional promotion.
+    pub fn from_uci(uci: &str) -> Result<Self, board::Error> {
+        let invalid = || board::Error::IllegalMove(uci.to_string());
+        let index = |square: Option<&str>| -> Result<usize, board::Error> {
+            let coordinate = Coordinate::from(square.ok_or_else(invalid)?)?;
+            Ok(coordinate.ok_or_else(invalid)?.into_index())
+        };
+        let mov = Move::new(index(uci.get(0..2))?, index(uci.get(2..4))?);
+        match uci.get(4..) {
+            Some("") => Ok(mov),
+            Some(promotion @ ("q" | "r" | "b" | "n")) => {
+                let kind = Kind::from(promotion.chars().next().unwrap_or_default())?;
+                Ok(mov.set_promotion_kind(kind))
+            }
+            _ => Err(invalid()),
+        }
+    }
+
     #[must_use]
     pub fn set_promotion_kind(mut self, promotion: Kind) -> Self {
         self.promotion_kind = Some(promotion);
@@ -0,0 +1,290 @@
+use std::cmp::Reverse;
+
+use crate::board::Board;
+use crate::color::Color;
+use crate::eval;
+use crate::magic_numbers::{
+    get_pawn_threat_offsets, get_threat_pieces, DIRECTION_OFFSETS, KNIGHT_THREAT_INDICES,
+    SQUARES_TO_EDGE,
+};
+use crate::mov::Move;
+use crate::piece::{Kind, Piece};
+use crate::search::MAX_PLY;
+
+// Moves are searched in order of these scores, from highest to lowest,
+// so that every category of move comes before the next.
+const TT_MOVE_SCORE: i32 = 1_000_000;
+const CAPTURE_SCORE: i32 = 100_000;
+const PROMOTION_SCORE: i32 = 90_000;
+const KILLER_SCORES: [i32; 2] = [80_000, 79_000];
+const COUNTERMOVE_SCORE: i32 = 70_000;
+/// Captures that lose material are searched after every quiet move.
+const LOSING_CAPTURE_SCORE: i32 = -50_000;
+/// History scores are kept within this bound, below the score of any other category.
+const HISTORY_MAX: i32 = 16_384;
+
+/// Returns whether [`mov`] captures a piece, including en passant.
+/// Castling is encoded as the king capturing its own rook in Chess960, and is not a capture.
+#[must_use]
+pub fn is_capture(board: &Board, mov: &Move) -> bool {
+    let Some(moving_piece) = board.squares[mov.start_index] else {
+        return false;
+    };
+    match board.squares[mov.target_index] {
+        Some(piece) => piece.color != moving_piece.color,
+        None => moving_piece.kind == Kind::Pawn && Some(mov.target_index) == board.state.ep_index(),
+    }
+}
+
+/// Returns the piece captured by [`mov`,] which must be a capture.
+#[must_use]
+pub fn captured_piece(board: &Board, mov: &Move) -> Piece {
+    board.squares[mov.target_index].unwrap_or(Piece {
+        kind: Kind::Pawn,
+        color: board.state.turn().opposite(),
+    })
+}
+
+/// Scores a capture so that the most valuable victims are taken first,
+/// by the least valuable attackers.
+///
+/// See: [MVV-LVA](https://www.chessprogramming.org/MVV-LVA)
+#[must_use]
+pub fn mvv_lva(board: &Board, mov: &Move) -> i32 {
+    let victim = captured_piece(board, mov).kind;
+    let attacker = board.squares[mov.start_index].map_or(Kind::Pawn, |piece| piece.kind);
+    let attacker_value = match attacker {
+        Kind::King => eval::value(Kind::Queen) + 1,
+        kind => eval::value(kind),
+    };
+    eval::value(victim) * 10 - attacker_value / 10
+}
+
+/// The value of a piece of [`kind`] when exchanging pieces, where the king
+/// outweighs everything else, since it can only capture last.
+fn exchange_value(kind: Kind) -> i32 {
+    match kind {
+        Kind::King => 20_000,
+        kind => eval::value(kind),
+    }
+}
+
+/// Returns the index of the least valuable piece of [`color`] attacking [`target_index`.]
+fn least_valuable_attacker(
+    squares: &[Option<Piece>; 64],
+    target_index: usize,
+    color: Color,
+) -> Option<usize> {
+    let mut attackers = vec![];
+    for &index in KNIGHT_THREAT_INDICES[target_index] {
+        if squares[index]
+            == Some(Piece {
+                kind: Kind::Knight,
+                color,
+            })
+        {
+            attackers.push(index);
+        }
+    }
+    // A pawn of [`color`] attacks from where a pawn of the opposite color would be attacked
+    let pawn_offsets = get_pawn_threat_offsets(color.opposite());
+    for (direction_index, offset) in DIRECTION_OFFSETS.iter().enumerate() {
+        let threat_pieces = get_threat_pieces(direction_index)
+            .expect("DIRECTION_OFFSETS yields valid direction offsets");
+        for n in 0..SQUARES_TO_EDGE[target_index][direction_index] {
+            let index = (target_index as i32 + offset * (n + 1)) as usize;
+            let Some(piece) = squares[index] else {
+                continue;
+            };
+            let is_adjacent_attacker = n == 0
+                && (piece.kind == Kind::King
+                    || (piece.kind == Kind::Pawn && pawn_offsets.contains(offset)));
+            if piece.color == color && (threat_pieces.contains(&piece.kind) || is_adjacent_attacker)
+            {
+                attackers.push(index);
+            }
+            break;
+        }
+    }
+    attackers
+        .into_iter()
+        .min_by_key(|&index| squares[index].map_or(0, |piece| exchange_value(piece.kind)))
+}
+
+/// Returns the material gained by [`mov`] once every profitable recapture on its
+/// target square has been played, by the least valuable attacker each time.
+/// Pins are not taken into account, but pieces behind attackers are.
+///
+/// See: [Static Exchange Evaluation](https://www.chessprogramming.org/Static_Exchange_Evaluation)
+#[must_use]
+pub fn see(board: &Board, mov: &Move) -> i32 {
+    let mut squares = board.squares;
+    let Some(mut on_target) = squares[mov.start_index] else {
+        return 0;
+    };
+    let target_index = mov.target_index;
+    let mut gains = vec![0];
+    if is_capture(board, mov) {
+        gains[0] = eval::value(captured_piece(board, mov).kind);
+        if squares[target_index].is_none() {
+            // En passant removes the pawn behind the target square
+            let pawn_index = match on_target.color {
+                Color::White => target_index + 8,
+                Color::Black => target_index - 8,
+            };
+            squares[pawn_index] = None;
+        }
+    }
+    if let Some(kind) = mov.promotion_kind {
+        gains[0] += eval::value(kind) - eval::value(Kind::Pawn);
+        on_target.kind = kind;
+    }
+    squares[mov.start_index] = None;
+    squares[target_index] = Some(on_target);
+
+    let mut color = on_target.color.opposite();
+    while let Some(index) = least_valuable_attacker(&squares, target_index, color) {
+        let attacker = squares[index].expect("Attackers are found on occupied squares");
+        squares[index] = None;
+        if attacker.kind == Kind::King
+            && least_valuable_attacker(&squares, target_index, color.opposite()).is_some()
+        {
+            break;
+        }
+        let previous_gain = *gains.last().expect("Gains start with the first capture");
+        gains.push(exchange_value(on_target.kind) - previous_gain);
+        squares[target_index] = Some(attacker);
+        on_target = attacker;
+        color = color.opposite();
+    }
+
+    // Either side may stop capturing when continuing would lose material
+    while gains.len() > 1 {
+        let gain = gains.pop().expect("Gains has more than one element");
+        let previous_gain = gains.last_mut().expect("Gains has more than one element");
+        *previous_gain = -(-*previous_gain).max(gain);
+    }
+    gains[0]
+}
+
+/// The tables of quiet moves that caused cutoffs earlier in the search,
+/// which are kept between the searches of a game.
+pub struct OrderingTables {
+    /// Two quiet moves per ply that last caused a cutoff among siblings.
+    ///
+    /// See: [Killer Heuristic](https://www.chessprogramming.org/Killer_Heuristic)
+    killers: Vec<[Option<Move>; 2]>,
+    /// Indexed by color, then the start and target square of the move.
+    ///
+    /// See: [History Heuristic](https://www.chessprogramming.org/History_Heuristic)
+    history: Vec<[[i32; 64]; 64]>,
+    /// Indexed by the start and target square of the opponent's previous move.
+    ///
+    /// See: [Countermove Heuristic](https://www.chessprogramming.org/Countermove_Heuristic)
+    countermoves: Vec<[Option<Move>; 64]>,
+}
+
+impl Default for OrderingTables {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OrderingTables {
+    #[must_use]
+    pub fn new() -> Self {
+        OrderingTables {
+            killers: vec![[None; 2]; MAX_PLY as usize + 1],
+            history: vec![[[0; 64]; 64]; 2],
+            countermoves: vec![[None; 64]; 64],
+        }
+    }
+
+    /// Forgets every move, e.g. when a new game starts.
+    pub fn clear(&mut self) {
+        *self = OrderingTables::new();
+    }
+
+    /// Prepares the tables for a new search of the same game. Killers only
+    /// apply to the positions of the last search, and history scores are halved
+    /// so that recent cutoffs count for more.
+    pub fn new_search(&mut self) {
+        self.killers.fill([None; 2]);
+        for table in &mut self.history {
+            for score in table.iter_mut().flatten() {
+                *score /= 2;
+            }
+        }
+    }
+
+    /// Records that the quiet move [`mov`] caused a cutoff at [`ply`] and [`depth`,]
+    /// in reply to [`previous_move`,] after the quiet moves [`searched`] failed to.
+    pub fn update_cutoff(
+        &mut self,
+        color: Color,
+        mov: Move,
+        previous_move: Option<Move>,
+        searched: &[Move],
+        ply: i32,
+        depth: i32,
+    ) {
+        let killers = &mut self.killers[ply as usize];
+        if killers[0] != Some(mov) {
+            killers[1] = killers[0];
+            killers[0] = Some(mov);
+        }
+        if let Some(previous) = previous_move {
+            self.countermoves[previous.start_index][previous.target_index] = Some(mov);
+        }
+
+        let bonus = (depth * depth).min(HISTORY_MAX);
+        let table = &mut self.history[color as usize];
+        update_history(&mut table[mov.start_index][mov.target_index], bonus);
+        for quiet in searched.iter().filter(|&&quiet| quiet != mov) {
+            update_history(&mut table[quiet.start_index][quiet.target_index], -bonus);
+        }
+    }
+
+    #[must_use]
+    pub fn history(&self, color: Color, mov: &Move) -> i32 {
+        self.history[color as usize][mov.start_index][mov.target_index]
+    }
+
+    /// Scores [`mov`] in [`board`'s] position at [`ply`,] with higher scores to be searched first.
+    #[must_use]
+    pub fn score(&self, board: &Board, mov: &Move, tt_move: Option<Move>, ply: i32) -> i32 {
+        if Some(*mov) == tt_move {
+            return TT_MOVE_SCORE;
+        }
+        if is_capture(board, mov) {
+            return match see(board, mov) {
+                gain if gain >= 0 => CAPTURE_SCORE + mvv_lva(board, mov),
+                _ => LOSING_CAPTURE_SCORE + mvv_lva(board, mov),
+            };
+        }
+        if mov.promotion_kind == Some(Kind::Queen) {
+            return PROMOTION_SCORE;
+        }
+        let killers = self.killers[ply as usize];
+        if let Some(index) = killers.iter().position(|&killer| killer == Some(*mov)) {
+            return KILLER_SCORES[index];
+        }
+        if let Some(previous) = board.state.last_move() {
+            if self.countermoves[previous.start_index][previous.target_index] == Some(*mov) {
+                return COUNTERMOVE_SCORE;
+            }
+        }
+        self.history(board.state.turn(), mov)
+    }
+
+    /// Sorts [`moves`] from most to least promising. See [`score`.]
+    pub fn order(&self, board: &Board, moves: &mut [Move], tt_move: Option<Move>, ply: i32) {
+        moves.sort_by_cached_key(|mov| Reverse(self.score(board, mov, tt_move, ply)));
+    }
+}
+
+/// Moves a history score towards the bound in the direction of [`bonus`,] by less
+/// the closer it already is, so that scores never leave [`HISTORY_MAX`.]
+fn update_history(score: &mut i32, bonus: i32) {
+    *score += bonus - *score * bonus.abs() / HISTORY_MAX;
+}
@@ -0,0 +1,376 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::Path;
+
+use crate::board::Board;
+use crate::castling::{self, Rights};
+use crate::color::Color;
+use crate::piece::{Kind, Piece};
+
+/// The size of a packed position in bytes.
+pub const PACKED_SIZE: usize = 32;
+/// The most pieces a packed position has room for, one 4-bit code each.
+pub const MAX_PIECES: usize = 32;
+const CODES_OFFSET: usize = 8;
+const FLAGS_OFFSET: usize = CODES_OFFSET + MAX_PIECES / 2;
+const ROOK_FILES_OFFSET: usize = FLAGS_OFFSET + 1;
+const EP_OFFSET: usize = ROOK_FILES_OFFSET + 1;
+const HALFMOVE_OFFSET: usize = EP_OFFSET + 1;
+const FULLMOVE_OFFSET: usize = HALFMOVE_OFFSET + 2;
+/// The en passant byte of a position without an en passant square.
+const NO_EP: u8 = 0xFF;
+const MAX_HALFMOVE_CLOCK: u32 = u16::MAX as u32;
+const MAX_FULLMOVE_COUNT: u32 = (1 << 24) - 1;
+/// The kinds in the order of their codes, which are offset by 6 for black pieces.
+const KINDS: [Kind; 6] = [
+    Kind::Pawn,
+    Kind::Knight,
+    Kind::Bishop,
+    Kind::Rook,
+    Kind::Queen,
+    Kind::King,
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    Io(String),
+    /// A position doesn't fit in [`PACKED_SIZE`] bytes, e.g. because it has more
+    /// than [`MAX_PIECES`] pieces.
+    Unpackable(String),
+    InvalidPacking(String),
+    /// A stream ended this many bytes into a packed position.
+    Truncated(usize),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Unpackable(reason) => write!(f, "the position can't be packed: {reason}"),
+            Error::InvalidPacking(reason) => write!(f, "invalid packed position: {reason}"),
+            Error::Truncated(len) => write!(
+                f,
+                "the positions end {len} bytes into a {PACKED_SIZE} byte position"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error.to_string())
+    }
+}
+
+fn piece_code(piece: Piece) -> u8 {
+    let kind = KINDS
+        .iter()
+        .position(|&kind| kind == piece.kind)
+        .expect("Every kind has a code") as u8;
+    match piece.color {
+        Color::White => kind,
+        Color::Black => kind + 6,
+    }
+}
+
+fn rights_bits(rights: Rights) -> u8 {
+    match rights {
+        Rights::Neither => 0,
+        Rights::Kingside => 1,
+        Rights::Queenside => 2,
+        Rights::Both => 3,
+    }
+}
+
+fn rights_from_bits(bits: u8) -> Rights {
+    Rights::from(bits & 1 != 0, bits & 2 != 0)
+}
+
+/// A position packed into [`PACKED_SIZE`] bytes, to store many positions compactly:
+///
+/// - 8 bytes of the squares with a piece, from a8 to h1, as a little-endian bitboard.
+/// - 16 bytes of the 4-bit code of each piece, in the same order, low half first.
+///   Codes 0 to 5 are white's pawn, knight, bishop, rook, queen and king, and 6 to
+///   11 black's.
+/// - A byte with the side to move, each side's castling rights and whether
+///   castling is encoded as in Chess960.
+/// - A byte with the files of the castling rooks.
+/// - The en passant square's index, or `0xFF` without one.
+/// - The halfmove clock in 2 bytes and the fullmove number in 3, little-endian.
+///
+/// Packing and unpacking are lossless: a position unpacks to the same FEN and
+/// castling state it was packed from.
+///
+/// See: [Board Representation](https://www.chessprogramming.org/Board_Representation)
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct PackedPosition {
+    bytes: [u8; PACKED_SIZE],
+}
+
+impl PackedPosition {
+    /// Packs [`board`'s] position, leaving out its history.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the position has more than [`MAX_PIECES`]
+    /// pieces, or a clock too large to pack.
+    pub fn pack(board: &Board) -> Result<Self, Error> {
+        let state = board.state();
+        let pieces = board.squares.iter().flatten().count();
+        if pieces > MAX_PIECES {
+            return Err(Error::Unpackable(format!("it has {pieces} pieces")));
+        }
+        if state.halfmove_clock() > MAX_HALFMOVE_CLOCK {
+            return Err(Error::Unpackable(format!(
+                "its halfmove clock is {}",
+                state.halfmove_clock()
+            )));
+        }
+        if state.fullmove_count() > MAX_FULLMOVE_COUNT {
+            return Err(Error::Unpackable(format!(
+                "its fullmove number is {}",
+                state.fullmove_count()
+            )));
+        }
+
+        let mut bytes = [0; PACKED_SIZE];
+        let mut occupancy = 0u64;
+        let mut codes = 0;
+        for (index, square) in board.squares.iter().enumerate() {
+            let Some(piece) = square else {
+                continue;
+            };
+            occupancy |= 1 << index;
+            bytes[CODES_OFFSET + codes / 2] |= piece_code(*piece) << (4 * (codes % 2));
+            codes += 1;
+        }
+        bytes[..CODES_OFFSET].copy_from_slice(&occupancy.to_le_bytes());
+
+        let castling = state.castling_state();
+        bytes[FLAGS_OFFSET] = u8::from(state.turn() == Color::Black)
+            | rights_bits(castling.white) << 1
+            | rights_bits(castling.black) << 3
+            | u8::from(castling.chess960) << 5;
+        bytes[ROOK_FILES_OFFSET] = (castling.kingside_file | castling.queenside_file << 3) as u8;
+        bytes[EP_OFFSET] = state.ep_index().map_or(NO_EP, |index| index as u8);
+        bytes[HALFMOVE_OFFSET..FULLMOVE_OFFSET]
+            .copy_from_slice(&(state.halfmove_clock() as u16).to_le_bytes());
+        bytes[FULLMOVE_OFFSET..].copy_from_slice(&state.fullmove_count().to_le_bytes()[..3]);
+        Ok(PackedPosition { bytes })
+    }
+
+    /// Unpacks the position into a new board, without a history.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the bytes aren't a packed position
+    /// with both kings.
+    pub fn unpack(&self) -> Result<Board, Error> {
+        let bytes = &self.bytes;
+        let occupancy = u64::from_le_bytes(bytes[..CODES_OFFSET].try_into().expect("8 bytes"));
+        if occupancy.count_ones() as usize > MAX_PIECES {
+            return Err(Error::InvalidPacking(format!(
+                "{} pieces",
+                occupancy.count_ones()
+            )));
+        }
+
+        let mut squares = [None; 64];
+        let mut codes = 0;
+        for (index, square) in squares.iter_mut().enumerate() {
+            if occupancy & 1 << index == 0 {
+                continue;
+            }
+            let code = bytes[CODES_OFFSET + codes / 2] >> (4 * (codes % 2)) & 0xF;
+            codes += 1;
+            let (kind, color) = match code {
+                0..=5 => (code, Color::White),
+                6..=11 => (code - 6, Color::Black),
+                _ => return Err(Error::InvalidPacking(format!("invalid piece code {code}"))),
+            };
+            *square = Some(Piece {
+                kind: KINDS[kind as usize],
+                color,
+            });
+        }
+
+        let flags = bytes[FLAGS_OFFSET];
+        if flags >> 6 != 0 || bytes[ROOK_FILES_OFFSET] >> 6 != 0 {
+            return Err(Error::InvalidPacking("unused bits are set".to_string()));
+        }
+        let turn = match flags & 1 {
+            0 => Color::White,
+            _ => Color::Black,
+        };
+        let castling_state = castling::State {
+            white: rights_from_bits(flags >> 1),
+            black: rights_from_bits(flags >> 3),
+            kingside_file: (bytes[ROOK_FILES_OFFSET] & 7) as usize,
+            queenside_file: (bytes[ROOK_FILES_OFFSET] >> 3) as usize,
+            chess960: flags >> 5 & 1 != 0,
+        };
+        let ep_index = match bytes[EP_OFFSET] {
+            NO_EP => None,
+            index if index < 64 => Some(index as usize),
+            index => {
+                return Err(Error::InvalidPacking(format!(
+                    "invalid en passant square {index}"
+                )))
+            }
+        };
+        let halfmove_clock =
+            u16::from_le_bytes([bytes[HALFMOVE_OFFSET], bytes[HALFMOVE_OFFSET + 1]]);
+        let mut fullmove_count = [0; 4];
+        fullmove_count[..3].copy_from_slice(&bytes[FULLMOVE_OFFSET..]);
+
+        let mut board = Board::new();
+        board
+            .set_fen_fields(
+                squares,
+                turn,
+                castling_state,
+                ep_index,
+                halfmove_clock.into(),
+                u32::from_le_bytes(fullmove_count),
+            )
+            .map_err(|e| Error::InvalidPacking(e.to_string()))?;
+        Ok(board)
+    }
+
+    #[must_use]
+    pub fn from_bytes(bytes: [u8; PACKED_SIZE]) -> Self {
+        PackedPosition { bytes }
+    }
+
+    #[must_use]
+    pub fn to_bytes(&self) -> [u8; PACKED_SIZE] {
+        self.bytes
+    }
+}
+
+/// Reads packed positions one after another from a stream, e.g. a file written
+/// by a [`PackedWriter`,] without reading the whole stream at once.
+///
+/// As an iterator it unpacks each position, and stops after the first error.
+pub struct PackedReader<R: Read> {
+    reader: R,
+    failed: bool,
+}
+
+impl PackedReader<BufReader<File>> {
+    /// # Errors
+    ///
+    /// This function will return an error if the file at [`path`] can't be opened.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(PackedReader::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: Read> PackedReader<R> {
+    pub fn new(reader: R) -> Self {
+        PackedReader {
+            reader,
+            failed: false,
+        }
+    }
+
+    /// Reads the next packed position, or `None` at the end of the stream.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the stream can't be read, or ends
+    /// partway through a position.
+    pub fn read_packed(&mut self) -> Result<Option<PackedPosition>, Error> {
+        let mut bytes = [0; PACKED_SIZE];
+        let mut len = 0;
+        while len < PACKED_SIZE {
+            match self.reader.read(&mut bytes[len..]) {
+                Ok(0) => break,
+                Ok(read) => len += read,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        match len {
+            0 => Ok(None),
+            PACKED_SIZE => Ok(Some(PackedPosition::from_bytes(bytes))),
+            len => Err(Error::Truncated(len)),
+        }
+    }
+}
+
+impl<R: Read> Iterator for PackedReader<R> {
+    type Item = Result<Board, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let board = self
+            .read_packed()
+            .transpose()?
+            .and_then(|packed| packed.unpack());
+        self.failed = board.is_err();
+        Some(board)
+    }
+}
+
+/// Writes packed positions one after another to a stream, e.g. a file to read
+/// back with a [`PackedReader`.]
+pub struct PackedWriter<W: Write> {
+    writer: W,
+    written: u64,
+}
+
+impl PackedWriter<BufWriter<File>> {
+    /// Creates the file at [`path`,] or truncates it if it exists.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file can't be created.
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(PackedWriter::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> PackedWriter<W> {
+    pub fn new(writer: W) -> Self {
+        PackedWriter { writer, written: 0 }
+    }
+
+    /// Packs and writes [`board`'s] position.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the position can't be packed or written.
+    pub fn write(&mut self, board: &Board) -> Result<(), Error> {
+        self.write_packed(&PackedPosition::pack(board)?)
+    }
+
+    /// # Errors
+    ///
+    /// This function will return an error if the stream can't be written.
+    pub fn write_packed(&mut self, packed: &PackedPosition) -> Result<(), Error> {
+        self.writer.write_all(&packed.bytes)?;
+        self.written += 1;
+        Ok(())
+    }
+
+    /// The number of positions written so far.
+    #[must_use]
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    /// Flushes the positions written and returns the stream.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the stream can't be flushed.
+    pub fn finish(mut self) -> Result<W, Error> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
@@ -0,0 +1,513 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use rand::Rng;
+
+use crate::board::{self, Board, GameState};
+use crate::castling::Rights;
+use crate::color::Color;
+use crate::game::PgnGame;
+use crate::mov::Move;
+use crate::piece::{Kind, Piece};
+
+/// One number for each piece on each square, then one for each castling right,
+/// then one for each en passant file, and finally one for white to move.
+pub const NUM_RANDOMS: usize = 12 * 64 + 4 + 8 + 1;
+const CASTLING_OFFSET: usize = 12 * 64;
+const EP_OFFSET: usize = CASTLING_OFFSET + 4;
+const TURN_OFFSET: usize = EP_OFFSET + 8;
+/// The size of an entry of a book file in bytes.
+pub const ENTRY_SIZE: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    Io(String),
+    /// The random numbers of the keys aren't exactly [`NUM_RANDOMS`.]
+    InvalidRandoms(usize),
+    /// A book's size in bytes isn't a multiple of [`ENTRY_SIZE`.]
+    InvalidBookSize(usize),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::InvalidRandoms(count) => {
+                write!(
+                    f,
+                    "expected {NUM_RANDOMS} Polyglot random numbers, found {count}"
+                )
+            }
+            Error::InvalidBookSize(size) => {
+                write!(
+                    f,
+                    "a book of {size} bytes is not made of {ENTRY_SIZE} byte entries"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error.to_string())
+    }
+}
+
+/// The `Random64` table of the Polyglot book format, which Polyglot keys are made
+/// of the same way as, but independently from, [`crate::zobrist`] hashes.
+///
+/// The table is data published with the format rather than generated, so it is
+/// read from a listing of its numbers, e.g. the specification's.
+///
+/// See: [PolyGlot](https://www.chessprogramming.org/PolyGlot)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keys {
+    randoms: Vec<u64>,
+}
+
+impl Keys {
+    /// # Errors
+    ///
+    /// This function will return an error if there aren't exactly [`NUM_RANDOMS`] [`randoms`.]
+    pub fn new(randoms: Vec<u64>) -> Result<Self, Error> {
+        match randoms.len() {
+            NUM_RANDOMS => Ok(Keys { randoms }),
+            count => Err(Error::InvalidRandoms(count)),
+        }
+    }
+
+    /// Reads the table from the hexadecimal numbers of [`text`] in order, written
+    /// as `0x9D39247E33776D41`, optionally followed by a `ULL` suffix or wrapped in
+    /// `U64(..)` as in the specification. Everything else is ignored.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if there aren't exactly [`NUM_RANDOMS`] numbers.
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let randoms = text
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter_map(|token| {
+                token
+                    .strip_prefix("0x")
+                    .or_else(|| token.strip_prefix("0X"))
+            })
+            .filter_map(|hex| {
+                u64::from_str_radix(hex.trim_end_matches(['U', 'L', 'u', 'l']), 16).ok()
+            })
+            .collect();
+        Keys::new(randoms)
+    }
+
+    /// Reads the table from the file at [`path`,] see [`Keys::parse`.]
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file can't be read or doesn't list the table.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Keys::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// The Polyglot key of [`board`'s] position. The en passant file only counts if
+    /// a pawn of the side to move stands next to the pawn that just moved, and
+    /// castling rights count as they are, even if castling is currently illegal.
+    #[must_use]
+    pub fn key(&self, board: &Board) -> u64 {
+        let mut key = 0;
+        for (index, square) in board.squares.iter().enumerate() {
+            if let Some(piece) = square {
+                key ^= self.randoms[piece_offset(*piece) + square_offset(index)];
+            }
+        }
+
+        let castling = board.state().castling_state();
+        for (color, offset) in [(Color::White, 0), (Color::Black, 2)] {
+            let rights = castling.rights(&color);
+            if matches!(rights, Rights::Kingside | Rights::Both) {
+                key ^= self.randoms[CASTLING_OFFSET + offset];
+            }
+            if matches!(rights, Rights::Queenside | Rights::Both) {
+                key ^= self.randoms[CASTLING_OFFSET + offset + 1];
+            }
+        }
+
+        let turn = board.state().turn();
+        if let Some(ep_index) = board.state().ep_index() {
+            // The pawn that just moved stands one rank closer to the side to move than the ep square
+            let pawn_index = match turn {
+                Color::White => ep_index + 8,
+                Color::Black => ep_index - 8,
+            };
+            let file = ep_index % 8;
+            let capturer = Some(Piece {
+                kind: Kind::Pawn,
+                color: turn,
+            });
+            let can_capture = (file > 0 && board.squares[pawn_index - 1] == capturer)
+                || (file < 7 && board.squares[pawn_index + 1] == capturer);
+            if can_capture {
+                key ^= self.randoms[EP_OFFSET + file];
+            }
+        }
+
+        if turn == Color::White {
+            key ^= self.randoms[TURN_OFFSET];
+        }
+        key
+    }
+}
+
+/// Pieces are ordered black pawn, white pawn, black knight, white knight and so on
+/// up to the kings.
+fn piece_offset(piece: Piece) -> usize {
+    let kind = match piece.kind {
+        Kind::Pawn => 0,
+        Kind::Knight => 1,
+        Kind::Bishop => 2,
+        Kind::Rook => 3,
+        Kind::Queen => 4,
+        Kind::King => 5,
+    };
+    64 * (2 * kind + usize::from(piece.color == Color::White))
+}
+
+/// Polyglot numbers squares from a1 up to h8, while our indices go from a8 down to h1.
+fn square_offset(index: usize) -> usize {
+    (7 - index / 8) * 8 + index % 8
+}
+
+/// A move stored in a book, for the position with the Polyglot [`key`.]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    pub key: u64,
+    /// The move, encoded as described by [`decode_move`.]
+    pub mov: u16,
+    /// How good the move is relative to the other moves of the position.
+    pub weight: u16,
+    pub learn: u32,
+}
+
+impl Entry {
+    /// Reads an entry written as big-endian integers.
+    #[must_use]
+    pub fn from_bytes(bytes: [u8; ENTRY_SIZE]) -> Self {
+        let [k0, k1, k2, k3, k4, k5, k6, k7, m0, m1, w0, w1, l0, l1, l2, l3] = bytes;
+        Entry {
+            key: u64::from_be_bytes([k0, k1, k2, k3, k4, k5, k6, k7]),
+            mov: u16::from_be_bytes([m0, m1]),
+            weight: u16::from_be_bytes([w0, w1]),
+            learn: u32::from_be_bytes([l0, l1, l2, l3]),
+        }
+    }
+
+    #[must_use]
+    pub fn to_bytes(&self) -> [u8; ENTRY_SIZE] {
+        let mut bytes = [0; ENTRY_SIZE];
+        bytes[..8].copy_from_slice(&self.key.to_be_bytes());
+        bytes[8..10].copy_from_slice(&self.mov.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.weight.to_be_bytes());
+        bytes[12..].copy_from_slice(&self.learn.to_be_bytes());
+        bytes
+    }
+}
+
+/// Returns the legal move of [`board`'s] position encoded as [`raw`,] which holds
+/// the target file and rank, the start file and rank, and the promotion kind, three
+/// bits each from the lowest. Castling is encoded as the king capturing its own rook.
+#[must_use]
+pub fn decode_move(board: &Board, raw: u16) -> Option<Move> {
+    let field = |shift: u16| usize::from((raw >> shift) & 0b111);
+    let target_index = (7 - field(3)) * 8 + field(0);
+    let start_index = (7 - field(9)) * 8 + field(6);
+    let promotion_kind = match field(12) {
+        0 => None,
+        1 => Some(Kind::Knight),
+        2 => Some(Kind::Bishop),
+        3 => Some(Kind::Rook),
+        4 => Some(Kind::Queen),
+        _ => return None,
+    };
+    let color = board.state().turn();
+    let castling = board.state().castling_state();
+    board.clone().gen_legal_moves().into_iter().find(|mov| {
+        mov.start_index == start_index
+            && mov.promotion_kind == promotion_kind
+            && match castling.get_squares(mov, &color) {
+                Some(squares)
+                    if board.squares[start_index].map(|piece| piece.kind) == Some(Kind::King) =>
+                {
+                    squares.rook_start_index == target_index
+                }
+                _ => mov.target_index == target_index,
+            }
+    })
+}
+
+/// Encodes the legal move [`mov`] of [`board`'s] position as a book stores it, see [`decode_move`.]
+#[must_use]
+pub fn encode_move(board: &Board, mov: &Move) -> u16 {
+    let is_king = board.squares[mov.start_index].map(|piece| piece.kind) == Some(Kind::King);
+    let castling = match is_king {
+        true => board
+            .state()
+            .castling_state()
+            .get_squares(mov, &board.state().turn()),
+        false => None,
+    };
+    let target_index = castling.map_or(mov.target_index, |squares| squares.rook_start_index);
+    let promotion = match mov.promotion_kind {
+        None | Some(Kind::Pawn | Kind::King) => 0,
+        Some(Kind::Knight) => 1,
+        Some(Kind::Bishop) => 2,
+        Some(Kind::Rook) => 3,
+        Some(Kind::Queen) => 4,
+    };
+    let square = |index: usize| ((7 - index / 8) * 8 + index % 8) as u16;
+    square(target_index) | square(mov.start_index) << 6 | promotion << 12
+}
+
+/// How a move is picked from those a book has for a position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Selection {
+    /// The move with the highest weight.
+    Best,
+    /// A random move, with chances proportional to the weights.
+    #[default]
+    Weighted,
+}
+
+/// An opening book in the Polyglot format, whose entries are sorted by key so that
+/// the moves of a position are found with a binary search.
+#[derive(Debug, Clone)]
+pub struct Book {
+    keys: Keys,
+    entries: Vec<Entry>,
+}
+
+impl Book {
+    /// Reads the book file at [`path`,] whose keys are made of [`keys`.]
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file can't be read or isn't a book.
+    pub fn open(path: impl AsRef<Path>, keys: Keys) -> Result<Self, Error> {
+        Book::from_bytes(&std::fs::read(path)?, keys)
+    }
+
+    /// # Errors
+    ///
+    /// This function will return an error if [`bytes`] isn't a whole number of entries.
+    pub fn from_bytes(bytes: &[u8], keys: Keys) -> Result<Self, Error> {
+        if !bytes.len().is_multiple_of(ENTRY_SIZE) {
+            return Err(Error::InvalidBookSize(bytes.len()));
+        }
+        let mut entries: Vec<Entry> = bytes
+            .chunks_exact(ENTRY_SIZE)
+            .map(|chunk| Entry::from_bytes(chunk.try_into().expect("Chunks are entries")))
+            .collect();
+        // Books are sorted already, but a stable sort keeps the order of each position's moves
+        entries.sort_by_key(|entry| entry.key);
+        Ok(Book { keys, entries })
+    }
+
+    /// Writes the book's entries as a book file stores them.
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.entries.iter().flat_map(Entry::to_bytes).collect()
+    }
+
+    /// Writes the book to a book file at [`path`.]
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file can't be written.
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        Ok(std::fs::write(path, self.to_bytes())?)
+    }
+
+    #[must_use]
+    pub fn keys(&self) -> &Keys {
+        &self.keys
+    }
+
+    #[must_use]
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// The book's moves for [`board`'s] position with their weights, skipping
+    /// entries that aren't legal moves.
+    #[must_use]
+    pub fn moves(&self, board: &Board) -> Vec<(Move, u16)> {
+        let key = self.keys.key(board);
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        self.entries[start..]
+            .iter()
+            .take_while(|entry| entry.key == key)
+            .filter_map(|entry| Some((decode_move(board, entry.mov)?, entry.weight)))
+            .collect()
+    }
+
+    /// Picks one of the book's moves for [`board`'s] position by [`selection`,]
+    /// or returns `None` if the book has no move with a weight for it.
+    pub fn choose(&self, board: &Board, selection: Selection, rng: &mut impl Rng) -> Option<Move> {
+        let moves: Vec<(Move, u16)> = self
+            .moves(board)
+            .into_iter()
+            .filter(|&(_, weight)| weight > 0)
+            .collect();
+        match selection {
+            Selection::Best => moves
+                .iter()
+                .rev()
+                .max_by_key(|(_, weight)| weight)
+                .map(|&(mov, _)| mov),
+            Selection::Weighted => {
+                let total: u32 = moves.iter().map(|&(_, weight)| u32::from(weight)).sum();
+                if total == 0 {
+                    return None;
+                }
+                let mut pick = rng.gen_range(0..total);
+                moves.into_iter().find_map(|(mov, weight)| {
+                    match pick.checked_sub(u32::from(weight)) {
+                        Some(rest) => {
+                            pick = rest;
+                            None
+                        }
+                        None => Some(mov),
+                    }
+                })
+            }
+        }
+    }
+}
+
+/// What each game adds to the weights of the moves played in it, by its result
+/// for the side that played them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResultWeights {
+    pub win: u32,
+    pub draw: u32,
+    pub loss: u32,
+}
+
+impl Default for ResultWeights {
+    /// Polyglot's own weights, which count the half points scored.
+    fn default() -> Self {
+        ResultWeights {
+            win: 2,
+            draw: 1,
+            loss: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuildOptions {
+    /// Moves are only taken from the first plies of each game.
+    pub max_plies: usize,
+    /// Moves played in fewer games than this are left out of the book.
+    pub min_count: u32,
+    pub weights: ResultWeights,
+}
+
+impl Default for BuildOptions {
+    fn default() -> Self {
+        BuildOptions {
+            max_plies: 40,
+            min_count: 1,
+            weights: ResultWeights::default(),
+        }
+    }
+}
+
+/// How often a move was played in a position, and the weight its games add up to.
+#[derive(Debug, Clone, Copy, Default)]
+struct MoveStats {
+    count: u32,
+    weight: u64,
+}
+
+/// Builds a [`Book`] from the opening moves of a collection of games.
+#[derive(Debug, Clone)]
+pub struct BookBuilder {
+    keys: Keys,
+    options: BuildOptions,
+    /// The statistics of each move, by the key of its position and the move as stored.
+    moves: HashMap<(u64, u16), MoveStats>,
+}
+
+impl BookBuilder {
+    #[must_use]
+    pub fn new(keys: Keys, options: BuildOptions) -> Self {
+        BookBuilder {
+            keys,
+            options,
+            moves: HashMap::new(),
+        }
+    }
+
+    /// Adds the moves of [`game`] up to the ply limit, weighted by its result.
+    /// Games without a result only count towards how often their moves were played.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error, and add none of the game, if its
+    /// start position or one of the moves added is invalid.
+    pub fn add_game(&mut self, game: &PgnGame) -> Result<(), board::Error> {
+        let mut board = game.start()?;
+        let mut played = vec![];
+        for san in game.moves.iter().take(self.options.max_plies) {
+            let mov = board.move_from_san(san)?;
+            played.push((
+                self.keys.key(&board),
+                encode_move(&board, &mov),
+                board.state().turn(),
+            ));
+            board.make_move(&mov);
+        }
+        let weights = self.options.weights;
+        for (key, mov, color) in played {
+            let stats = self.moves.entry((key, mov)).or_default();
+            stats.count += 1;
+            stats.weight += u64::from(match game.result {
+                GameState::Victory(winner) if winner == color => weights.win,
+                GameState::Victory(_) => weights.loss,
+                GameState::Draw => weights.draw,
+                GameState::Playing => 0,
+            });
+        }
+        Ok(())
+    }
+
+    /// The book of the moves played often enough and with a weight. Weights are
+    /// scaled down for positions whose heaviest move doesn't fit in an entry, and
+    /// each position's moves are stored heaviest first.
+    #[must_use]
+    pub fn build(&self) -> Book {
+        let mut positions: HashMap<u64, Vec<(u16, u64)>> = HashMap::new();
+        for (&(key, mov), stats) in &self.moves {
+            if stats.count >= self.options.min_count && stats.weight > 0 {
+                positions.entry(key).or_default().push((mov, stats.weight));
+            }
+        }
+        let mut entries = vec![];
+        for (key, mut moves) in positions {
+            moves.sort_by_key(|&(mov, weight)| (std::cmp::Reverse(weight), mov));
+            let max = moves[0].1.max(u64::from(u16::MAX));
+            entries.extend(moves.into_iter().map(|(mov, weight)| Entry {
+                key,
+                mov,
+                weight: (weight * u64::from(u16::MAX) / max).max(1) as u16,
+                learn: 0,
+            }));
+        }
+        entries.sort_by_key(|entry| entry.key);
+        Book {
+            keys: self.keys.clone(),
+            entries,
+        }
+    }
+}
@@ -0,0 +1,327 @@
+use crate::board::Board;
+use crate::color::Color;
+use crate::magic_numbers::{DIRECTION_OFFSETS, KNIGHT_THREAT_INDICES, SQUARES_TO_EDGE};
+use crate::mov::Move;
+use crate::piece::{Kind, Piece};
+
+/// The kinds of piece a pawn may promote to, which are also the kinds a move may
+/// capture on any square.
+const PIECES: [Kind; 4] = [Kind::Queen, Kind::Rook, Kind::Bishop, Kind::Knight];
+
+/// A move that may have led to a position, which is taken back to find the
+/// position before it.
+///
+/// See: [Retrograde Analysis](https://www.chessprogramming.org/Retrograde_Analysis)
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct RetroMove {
+    /// The move as it was played, with the kind it promoted to if taking it back
+    /// unpromotes a pawn.
+    pub mov: Move,
+    /// The kind of the piece the move captured, which taking it back puts back.
+    pub uncaptured: Option<Kind>,
+    /// Whether the move captured en passant, so the pawn it captured is put back
+    /// behind its target square rather than on it.
+    pub en_passant: bool,
+}
+
+impl RetroMove {
+    fn new(mov: Move, uncaptured: Option<Kind>) -> Self {
+        RetroMove {
+            mov,
+            uncaptured,
+            en_passant: false,
+        }
+    }
+}
+
+/// The offset from a pawn of [`color`] to the square behind it.
+fn pawn_back(color: Color) -> i32 {
+    match color {
+        Color::White => 8,
+        Color::Black => -8,
+    }
+}
+
+/// The kinds of piece a move to [`target`] may have captured.
+fn uncaptures(target: usize) -> impl Iterator<Item = Kind> {
+    let pawn = (8..56).contains(&target).then_some(Kind::Pawn);
+    PIECES.into_iter().chain(pawn)
+}
+
+/// Pushes the squares the piece on [`square`] could have come from with a move
+/// that captured nothing and wasn't a promotion, passing over empty squares only.
+pub(crate) fn unmove_origins(
+    piece: Piece,
+    square: usize,
+    occupied: &[bool; 64],
+    origins: &mut Vec<usize>,
+) {
+    let empty = |origin: &usize| !occupied[*origin];
+    let (directions, branch_length) = match piece.kind {
+        Kind::Pawn => {
+            let back = pawn_back(piece.color);
+            let behind = square as i32 + back;
+            // Pawns never stand on the first rank, so can't step back onto it
+            if !(8..56).contains(&behind) || occupied[behind as usize] {
+                return;
+            }
+            origins.push(behind as usize);
+            let double_step_row = match piece.color {
+                Color::White => 4,
+                Color::Black => 3,
+            };
+            let start = (behind + back) as usize;
+            if square / 8 == double_step_row && empty(&start) {
+                origins.push(start);
+            }
+            return;
+        }
+        Kind::Knight => {
+            origins.extend(KNIGHT_THREAT_INDICES[square].iter().copied().filter(empty));
+            return;
+        }
+        Kind::Bishop => (4..8, 8),
+        Kind::Rook => (0..4, 8),
+        Kind::King => (0..8, 1),
+        Kind::Queen => (0..8, 8),
+    };
+    for direction_index in directions {
+        let offset = DIRECTION_OFFSETS[direction_index];
+        for n in 0..branch_length.min(SQUARES_TO_EDGE[square][direction_index]) {
+            let origin = (square as i32 + offset * (n + 1)) as usize;
+            if occupied[origin] {
+                break;
+            }
+            origins.push(origin);
+        }
+    }
+}
+
+/// Whether [`color`] may have the pieces on [`squares`] in a game: no more than
+/// 16 pieces, and no more promoted pieces than pawns missing.
+fn has_possible_material(squares: &[Option<Piece>; 64], color: Color) -> bool {
+    let count = |kind: Kind| {
+        squares
+            .iter()
+            .filter(|&&square| square == Some(Piece { kind, color }))
+            .count()
+    };
+    let promoted: usize = [
+        (Kind::Queen, 1),
+        (Kind::Rook, 2),
+        (Kind::Bishop, 2),
+        (Kind::Knight, 2),
+    ]
+    .iter()
+    .map(|&(kind, initial)| count(kind).saturating_sub(initial))
+    .sum();
+    count(Kind::Pawn) + promoted <= 8
+}
+
+impl Board {
+    /// Generates the moves that may have led to the current position from a legal one.
+    ///
+    /// See [`Board::predecessors`] for the positions they were played in.
+    #[must_use]
+    pub fn gen_retro_moves(&self) -> Vec<RetroMove> {
+        self.predecessors()
+            .into_iter()
+            .map(|(retro, _)| retro)
+            .collect()
+    }
+
+    /// Returns each legal position the current one may have been reached from, with
+    /// the move that reached it: every move of the side that isn't to move, which may
+    /// have captured any piece but a king that the other side may have had, promoted
+    /// or captured en passant.
+    ///
+    /// Positions before are given the current castling rights, so castling is never
+    /// taken back, and an en passant square only if the move captured en passant.
+    /// A position without an en passant square may have been reached by a pawn's
+    /// double step, as FEN often leaves the square out when no capture is possible.
+    #[must_use]
+    pub fn predecessors(&self) -> Vec<(RetroMove, Board)> {
+        self.gen_pseudo_retro_moves()
+            .into_iter()
+            .filter_map(|retro| Some((retro, self.retract(&retro)?)))
+            .collect()
+    }
+
+    /// Returns the position before [`retro`] was played, or `None` if that position
+    /// isn't legal or [`retro`] doesn't lead from it to the current one.
+    #[must_use]
+    pub fn retract(&self, retro: &RetroMove) -> Option<Board> {
+        let turn = self.state.turn();
+        let mover = turn.opposite();
+        let (start, target) = retro.mov.indices();
+        let moved = self.squares[target]?;
+        if moved.color != mover || self.squares[start].is_some() {
+            return None;
+        }
+
+        let mut squares = self.squares;
+        squares[start] = match retro.mov.promotion_kind {
+            Some(_) => Some(Piece {
+                kind: Kind::Pawn,
+                color: mover,
+            }),
+            None => Some(moved),
+        };
+        squares[target] = None;
+        let mut ep_index = None;
+        if let Some(kind) = retro.uncaptured {
+            let captured_index = match retro.en_passant {
+                true => {
+                    ep_index = Some(target);
+                    (start / 8) * 8 + target % 8
+                }
+                false => target,
+            };
+            if kind == Kind::King
+                || (kind == Kind::Pawn && !(8..56).contains(&captured_index))
+                || squares[captured_index].is_some()
+            {
+                return None;
+            }
+            squares[captured_index] = Some(Piece { kind, color: turn });
+        }
+        let resets_clock = moved.kind == Kind::Pawn
+            || retro.mov.promotion_kind.is_some()
+            || retro.uncaptured.is_some();
+        let halfmove_clock = match resets_clock {
+            true => 0,
+            false => self.state.halfmove_clock().saturating_sub(1),
+        };
+
+        if !has_possible_material(&squares, mover) || !has_possible_material(&squares, turn) {
+            return None;
+        }
+
+        let mut before = self.clone();
+        before
+            .set_predecessor(squares, ep_index, halfmove_clock)
+            .ok()?;
+        // The side to move now can't have been left in check
+        if before.is_attacked(&[before.king_index(turn).ok()?], turn) {
+            return None;
+        }
+        let mut after = before.clone();
+        if !after.gen_legal_moves().contains(&retro.mov) {
+            return None;
+        }
+        after.make_move(&retro.mov);
+        let ep_matches =
+            self.state.ep_index().is_none() || after.state.ep_index() == self.state.ep_index();
+        let matches = after.squares == self.squares
+            && after.state.castling_state() == self.state.castling_state()
+            && ep_matches;
+        matches.then_some(before)
+    }
+
+    /// Generates the moves that may have led to the current position, whether or
+    /// not the position before is legal.
+    fn gen_pseudo_retro_moves(&self) -> Vec<RetroMove> {
+        let mover = self.state.turn().opposite();
+        let occupied = self.squares.map(|square| square.is_some());
+        let mut retros = vec![];
+        let mut origins = vec![];
+        for (target, square) in self.squares.iter().enumerate() {
+            let Some(piece) = *square else {
+                continue;
+            };
+            if piece.color != mover {
+                continue;
+            }
+            origins.clear();
+            unmove_origins(piece, target, &occupied, &mut origins);
+            for &start in &origins {
+                let mov = Move::new(start, target);
+                retros.push(RetroMove::new(mov, None));
+                // Pawns only capture diagonally
+                if piece.kind != Kind::Pawn {
+                    retros.extend(uncaptures(target).map(|kind| RetroMove::new(mov, Some(kind))));
+                }
+            }
+            if piece.kind == Kind::Pawn {
+                self.gen_pawn_capture_retro_moves(&mut retros, target, mover);
+            }
+            if PIECES.contains(&piece.kind) {
+                self.gen_unpromotions(&mut retros, target, piece.kind, mover);
+            }
+        }
+        retros
+    }
+
+    fn gen_pawn_capture_retro_moves(
+        &self,
+        retros: &mut Vec<RetroMove>,
+        target: usize,
+        color: Color,
+    ) {
+        let back = pawn_back(color);
+        let behind = (target as i32 + back) as usize;
+        // Pawns never stand on the first rank, so can't step back onto it
+        if !(8..56).contains(&behind) {
+            return;
+        }
+        let ep_row = match color {
+            Color::White => 2,
+            Color::Black => 5,
+        };
+        for file_offset in [-1, 1] {
+            let file = (target % 8) as i32 + file_offset;
+            if !(0..8).contains(&file) {
+                continue;
+            }
+            let start = (behind as i32 + file_offset) as usize;
+            if self.squares[start].is_some() {
+                continue;
+            }
+            let mov = Move::new(start, target);
+            retros.extend(uncaptures(target).map(|kind| RetroMove::new(mov, Some(kind))));
+            // The pawn captured en passant stood behind the target square, having
+            // stepped through it from the square in front
+            let in_front = (target as i32 - back) as usize;
+            if target / 8 == ep_row
+                && self.squares[behind].is_none()
+                && self.squares[in_front].is_none()
+            {
+                retros.push(RetroMove {
+                    mov,
+                    uncaptured: Some(Kind::Pawn),
+                    en_passant: true,
+                });
+            }
+        }
+    }
+
+    /// Generates the promotions to [`kind`] on [`target`,] if it is on the last rank.
+    fn gen_unpromotions(
+        &self,
+        retros: &mut Vec<RetroMove>,
+        target: usize,
+        kind: Kind,
+        color: Color,
+    ) {
+        let last_row = match color {
+            Color::White => 0,
+            Color::Black => 7,
+        };
+        if target / 8 != last_row {
+            return;
+        }
+        let behind = (target as i32 + pawn_back(color)) as usize;
+        if self.squares[behind].is_none() {
+            let mov = Move::new(behind, target).set_promotion_kind(kind);
+            retros.push(RetroMove::new(mov, None));
+        }
+        for file_offset in [-1, 1] {
+            let file = (target % 8) as i32 + file_offset;
+            let start = (behind as i32 + file_offset) as usize;
+            if (0..8).contains(&file) && self.squares[start].is_none() {
+                let mov = Move::new(start, target).set_promotion_kind(kind);
+                retros.extend(PIECES.map(|captured| RetroMove::new(mov, Some(captured))));
+            }
+        }
+    }
+}
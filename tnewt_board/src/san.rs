@@ -0,0 +1,200 @@
+use crate::board::{Board, Error};
+use crate::coordinate::Coordinate;
+use crate::mov::Move;
+use crate::ordering;
+use crate::piece::Kind;
+
+/// Writes the legal move [`mov`] of [`board`'s] position in Standard Algebraic
+/// Notation, e.g. "Nf3", "exd5", "O-O" or "e8=Q#".
+///
+/// See: [Algebraic Chess Notation](https://www.chessprogramming.org/Algebraic_Chess_Notation)
+#[must_use]
+pub fn to_san(board: &Board, mov: &Move) -> String {
+    let mut san = san_without_check(board, mov);
+    let mut after = board.clone();
+    after.make_move(mov);
+    if after.in_check() {
+        san.push(match after.gen_legal_moves().is_empty() {
+            true => '#',
+            false => '+',
+        });
+    }
+    san
+}
+
+/// [`to_san`] without the suffix for check or checkmate, which needs the move to be made.
+fn san_without_check(board: &Board, mov: &Move) -> String {
+    let piece = board.squares[mov.start_index].expect("Legal moves start on occupied squares");
+    let color = board.state.turn();
+    let mut san = String::new();
+
+    let castling = match piece.kind {
+        Kind::King => board.state.castling_state().get_squares(mov, &color),
+        _ => None,
+    };
+    if let Some(castling) = castling {
+        san.push_str(match castling.king_target_index % 8 {
+            6 => "O-O",
+            _ => "O-O-O",
+        });
+    } else {
+        let target = Coordinate::from_index(mov.target_index).to_string();
+        let is_capture = ordering::is_capture(board, mov);
+        if piece.kind == Kind::Pawn {
+            if is_capture {
+                san.push(file_char(mov.start_index));
+            }
+        } else {
+            san.push(piece.kind.to_char().to_ascii_uppercase());
+            san.push_str(&disambiguation(board, mov, piece.kind));
+        }
+        if is_capture {
+            san.push('x');
+        }
+        san.push_str(&target);
+        if let Some(kind) = mov.promotion_kind {
+            san.push('=');
+            san.push(kind.to_char().to_ascii_uppercase());
+        }
+    }
+    san
+}
+
+fn file_char(index: usize) -> char {
+    (b'a' + (index % 8) as u8) as char
+}
+
+fn rank_char(index: usize) -> char {
+    (b'8' - (index / 8) as u8) as char
+}
+
+/// The start file, rank or square that tells [`mov`] apart from the other legal
+/// moves of a piece of the same [`kind`] to the same square, if there are any.
+fn disambiguation(board: &Board, mov: &Move, kind: Kind) -> String {
+    let others: Vec<usize> = board
+        .clone()
+        .gen_legal_moves()
+        .into_iter()
+        .filter(|other| {
+            other.target_index == mov.target_index
+                && other.start_index != mov.start_index
+                && board.squares[other.start_index].map(|piece| piece.kind) == Some(kind)
+        })
+        .map(|other| other.start_index)
+        .collect();
+    let shares_file = |index: &usize| index % 8 == mov.start_index % 8;
+    let shares_rank = |index: &usize| index / 8 == mov.start_index / 8;
+    match others.as_slice() {
+        [] => String::new(),
+        others if !others.iter().any(shares_file) => file_char(mov.start_index).to_string(),
+        others if !others.iter().any(shares_rank) => rank_char(mov.start_index).to_string(),
+        _ => Coordinate::from_index(mov.start_index).to_string(),
+    }
+}
+
+/// The parts of a non-castling move in SAN that tell which legal move it is.
+struct Parsed {
+    kind: Kind,
+    start_file: Option<usize>,
+    start_rank: Option<usize>,
+    target_index: usize,
+    promotion_kind: Option<Kind>,
+}
+
+/// Reads the piece, the optional start file and rank, the target square and the
+/// promotion of [`san`], skipping capture marks, dashes and the `=` of promotions.
+fn parse(san: &str) -> Option<Parsed> {
+    let mut chars: Vec<char> = san
+        .chars()
+        .filter(|c| !matches!(c, 'x' | ':' | '=' | '-'))
+        .collect();
+    // Piece letters are uppercase, so that e.g. "b" is a pawn on the b-file
+    let kind = match chars.first() {
+        Some(c) if c.is_ascii_uppercase() => {
+            Kind::from(chars.remove(0).to_ascii_lowercase()).ok()?
+        }
+        _ => Kind::Pawn,
+    };
+    // Anything after the target square's rank is the promotion, in either case
+    let promotion_kind = match chars.last() {
+        Some(c) if c.is_ascii_alphabetic() => {
+            Some(Kind::from(chars.pop()?.to_ascii_lowercase()).ok()?)
+        }
+        _ => None,
+    };
+    let split = chars.len().checked_sub(2)?;
+    let target: String = chars[split..].iter().collect();
+    let target_index = Coordinate::from(&target).ok()??.into_index();
+    let (mut start_file, mut start_rank) = (None, None);
+    for c in &chars[..split] {
+        match c {
+            'a'..='h' if start_file.is_none() => start_file = Some(*c as usize - 'a' as usize),
+            '1'..='8' if start_rank.is_none() => start_rank = Some('8' as usize - *c as usize),
+            _ => return None,
+        }
+    }
+    Some(Parsed {
+        kind,
+        start_file,
+        start_rank,
+        target_index,
+        promotion_kind,
+    })
+}
+
+impl Board {
+    /// Returns the legal move written as [`san`] in Standard Algebraic Notation,
+    /// ignoring check and annotation suffixes, capture marks and the `=` of promotions.
+    /// Promotions may be lowercase, and a start file or rank may be given even if the
+    /// move isn't ambiguous without it.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if no legal move, or more than one, is
+    /// written as [`san`.]
+    pub fn move_from_san(&mut self, san: &str) -> Result<Move, Error> {
+        let stripped = san.trim_end_matches(['+', '#', '!', '?']).replace('0', "O");
+        let castling_file = match stripped.as_str() {
+            "O-O" => Some(6),
+            "O-O-O" => Some(2),
+            _ => None,
+        };
+        let parsed = match castling_file {
+            Some(_) => None,
+            None => Some(parse(&stripped).ok_or_else(|| Error::IllegalMove(san.to_string()))?),
+        };
+        let color = self.state.turn();
+        let matches: Vec<Move> = self
+            .gen_legal_moves()
+            .into_iter()
+            .filter(|mov| {
+                let kind = self.squares[mov.start_index].map(|piece| piece.kind);
+                let castling = match kind {
+                    Some(Kind::King) => self.state.castling_state().get_squares(mov, &color),
+                    _ => None,
+                };
+                match (&parsed, castling) {
+                    (None, castling) => castling.is_some_and(|castling| {
+                        Some(castling.king_target_index % 8) == castling_file
+                    }),
+                    (Some(_), Some(_)) => false,
+                    (Some(parsed), None) => {
+                        kind == Some(parsed.kind)
+                            && mov.target_index == parsed.target_index
+                            && mov.promotion_kind == parsed.promotion_kind
+                            && parsed
+                                .start_file
+                                .is_none_or(|file| mov.start_index % 8 == file)
+                            && parsed
+                                .start_rank
+                                .is_none_or(|rank| mov.start_index / 8 == rank)
+                    }
+                }
+            })
+            .collect();
+        match matches.as_slice() {
+            [mov] => Ok(*mov),
+            _ => Err(Error::IllegalMove(san.to_string())),
+        }
+    }
+}
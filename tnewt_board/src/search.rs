@@ -0,0 +1,820 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::board::{Algorithm, Board, GameState};
+use crate::color::Color;
+use crate::eval;
+use crate::mov::Move;
+use crate::ordering::{self, OrderingTables};
+use crate::piece::Kind;
+use crate::tablebase::Tablebases;
+use crate::tt::{Bound, TranspositionTable};
+
+/// The score of a position in which the player to move has been checkmated.
+/// Mates further from the root score closer to zero, so that faster mates are preferred.
+pub const MATE_SCORE: i32 = 30_000;
+/// The deepest any search may reach from the root.
+pub const MAX_PLY: i32 = 256;
+/// The score of a drawn position.
+pub const DRAW_SCORE: i32 = 0;
+/// A bound on all scores, used to open the search window.
+pub const INFINITY: i32 = MATE_SCORE + 1;
+
+/// How many nodes are searched between checks of the clock and the stop signal.
+const CHECK_INTERVAL: u64 = 1024;
+/// How many moves are assumed to be left in the game when the time control does not say.
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+/// Reverse futility pruning is only applied this close to the horizon.
+const REVERSE_FUTILITY_MAX_DEPTH: i32 = 6;
+/// How far the static evaluation must be above beta per ply of depth left
+/// for reverse futility pruning.
+const REVERSE_FUTILITY_MARGIN: i32 = 80;
+/// How far below alpha the static evaluation must be for quiet moves to be pruned,
+/// at 1, 2 and 3 plies from the horizon.
+const FUTILITY_MARGINS: [i32; 3] = [150, 300, 500];
+const NULL_MOVE_MIN_DEPTH: i32 = 3;
+/// How many plies shallower the search after a null move is, besides the move itself.
+const NULL_MOVE_REDUCTION: i32 = 2;
+const LMR_MIN_DEPTH: i32 = 3;
+/// How many moves are searched at full depth before moves start being reduced.
+const LMR_MIN_MOVE_INDEX: usize = 3;
+/// Aspiration windows are only used once the score of the previous iteration is stable.
+const ASPIRATION_MIN_DEPTH: i32 = 5;
+/// The initial distance of the aspiration window's bounds from the previous score.
+const ASPIRATION_WINDOW: i32 = 25;
+/// How far above alpha a capture must be able to raise the evaluation
+/// to be searched in quiescence, accounting for positional gains.
+const DELTA_MARGIN: i32 = 200;
+/// Time kept in reserve on the clock, so that the engine never flags.
+const SAFETY_MARGIN: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    /// The best move found, or `None` if the root position has no legal moves.
+    pub best_move: Option<Move>,
+    /// The score of [`best_move`] in centipawns, from the perspective of the player to move.
+    pub score: i32,
+    /// The principal variation, i.e. the line of best play starting with [`best_move`.]
+    pub pv: Vec<Move>,
+    /// The number of nodes visited.
+    pub nodes: u64,
+    /// The depth of the deepest completed iteration.
+    pub depth: i32,
+    /// The rank of this line among the best lines of the iteration, from 1.
+    /// See [`Searcher::set_multi_pv`.]
+    pub multipv: usize,
+}
+
+/// The constraints a search runs under. Every limit that is set is respected,
+/// and a search with no limits set runs until it is stopped or reaches [`MAX_PLY`.]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchLimits {
+    /// The maximum depth in plies.
+    pub depth: Option<i32>,
+    /// The maximum number of nodes.
+    pub nodes: Option<u64>,
+    /// A fixed amount of time to spend on the move.
+    pub movetime: Option<Duration>,
+    pub wtime: Option<Duration>,
+    pub btime: Option<Duration>,
+    pub winc: Option<Duration>,
+    pub binc: Option<Duration>,
+    /// The number of moves until the next time control.
+    pub movestogo: Option<u32>,
+    /// Search until stopped, ignoring all other limits.
+    pub infinite: bool,
+    /// The root moves to choose from, or every legal move if empty.
+    pub searchmoves: Vec<Move>,
+}
+
+impl SearchLimits {
+    #[must_use]
+    pub fn depth(depth: i32) -> Self {
+        SearchLimits {
+            depth: Some(depth),
+            ..Default::default()
+        }
+    }
+
+    /// Returns how long [`color`] should search for, as a soft limit after which
+    /// no new iteration is started, and a hard limit at which the search is stopped.
+    #[must_use]
+    pub fn time_budget(&self, color: Color) -> Option<(Duration, Duration)> {
+        if self.infinite {
+            return None;
+        }
+        if let Some(movetime) = self.movetime {
+            return Some((movetime, movetime));
+        }
+        let (time, inc) = match color {
+            Color::White => (self.wtime?, self.winc.unwrap_or_default()),
+            Color::Black => (self.btime?, self.binc.unwrap_or_default()),
+        };
+        let available = time.saturating_sub(SAFETY_MARGIN);
+        let moves_to_go = self.movestogo.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+        let hard = (time / moves_to_go + inc * 3 / 4).min(available);
+        Some((hard / 2, hard))
+    }
+}
+
+/// Mate scores are stored in the transposition table relative to the position
+/// they were found in rather than to the root, since a position can be reached at any ply.
+fn score_to_tt(score: i32, ply: i32) -> i32 {
+    match score {
+        score if is_mate_score(score) && score > 0 => score + ply,
+        score if is_mate_score(score) => score - ply,
+        score => score,
+    }
+}
+
+fn score_from_tt(score: i32, ply: i32) -> i32 {
+    match score {
+        score if is_mate_score(score) && score > 0 => score - ply,
+        score if is_mate_score(score) => score + ply,
+        score => score,
+    }
+}
+
+/// Toggles for the selective parts of the search, which trade exactness for depth,
+/// so that each can be measured on its own. All are enabled by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchConfig {
+    pub null_move_pruning: bool,
+    pub late_move_reductions: bool,
+    pub reverse_futility_pruning: bool,
+    pub futility_pruning: bool,
+    pub mate_distance_pruning: bool,
+    pub check_extensions: bool,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            null_move_pruning: true,
+            late_move_reductions: true,
+            reverse_futility_pruning: true,
+            futility_pruning: true,
+            mate_distance_pruning: true,
+            check_extensions: true,
+        }
+    }
+}
+
+impl SearchConfig {
+    /// A configuration with every selective feature disabled, which searches
+    /// every move at full depth.
+    #[must_use]
+    pub fn full_width() -> Self {
+        SearchConfig {
+            null_move_pruning: false,
+            late_move_reductions: false,
+            reverse_futility_pruning: false,
+            futility_pruning: false,
+            mate_distance_pruning: false,
+            check_extensions: false,
+        }
+    }
+}
+
+/// How many plies to reduce the [`move_index`]th move by at [`depth`,] growing
+/// with both, and leaving at least one ply to search.
+fn late_move_reduction(depth: i32, move_index: usize, is_pv: bool) -> i32 {
+    let reduction = 0.75 + f64::from(depth).ln() * (move_index as f64).ln() / 2.25;
+    let reduction = reduction as i32 - i32::from(is_pv);
+    reduction.clamp(1, (depth - 2).max(1))
+}
+
+/// Returns whether [`color`] has any pieces besides pawns and the king.
+fn has_non_pawn_material(board: &Board, color: Color) -> bool {
+    board
+        .squares
+        .iter()
+        .flatten()
+        .any(|piece| piece.color == color && !matches!(piece.kind, Kind::Pawn | Kind::King))
+}
+
+/// Returns whether [`score`] represents a forced checkmate for either player.
+#[must_use]
+pub fn is_mate_score(score: i32) -> bool {
+    score.abs() >= MATE_SCORE - MAX_PLY
+}
+
+/// Returns the number of moves until checkmate if [`score`] is a mate score.
+/// The number is negative if the player to move is being mated.
+#[must_use]
+pub fn mate_in(score: i32) -> Option<i32> {
+    if !is_mate_score(score) {
+        return None;
+    }
+    let plies = MATE_SCORE - score.abs();
+    let moves = (plies + 1) / 2;
+    Some(if score > 0 { moves } else { -moves })
+}
+
+/// The number of rows of the PV table, and the length of each.
+const PV_TABLE_SIZE: usize = MAX_PLY as usize + 1;
+
+/// Holds the principal variation from every ply of the current line, where the
+/// row of each ply is its best move, followed by the row of the ply after it.
+///
+/// See: [Triangular PV-Table](https://www.chessprogramming.org/Triangular_PV-Table)
+struct PvTable {
+    moves: Vec<Move>,
+    lengths: Vec<usize>,
+}
+
+impl PvTable {
+    fn new() -> Self {
+        PvTable {
+            moves: vec![Move::new(0, 0); PV_TABLE_SIZE * PV_TABLE_SIZE],
+            lengths: vec![0; PV_TABLE_SIZE],
+        }
+    }
+
+    fn clear(&mut self, ply: usize) {
+        self.lengths[ply] = 0;
+    }
+
+    /// Makes [`mov`] followed by the line of the next ply the line of [`ply`.]
+    fn update(&mut self, ply: usize, mov: Move) {
+        let child_length = self.lengths.get(ply + 1).copied().unwrap_or(0);
+        let (rows, child_rows) = self.moves.split_at_mut((ply + 1) * PV_TABLE_SIZE);
+        let row = &mut rows[ply * PV_TABLE_SIZE..];
+        row[0] = mov;
+        row[1..=child_length].copy_from_slice(&child_rows[..child_length]);
+        self.lengths[ply] = child_length + 1;
+    }
+
+    fn line(&self, ply: usize) -> &[Move] {
+        let start = ply * PV_TABLE_SIZE;
+        &self.moves[start..start + self.lengths[ply]]
+    }
+}
+
+/// Holds the state of a search on its own copy of a position, which is searched
+/// with the [`Unmove`] algorithm so that no boards need to be cloned.
+pub struct Searcher {
+    board: Board,
+    tt: Arc<TranspositionTable>,
+    ordering: OrderingTables,
+    pv_table: PvTable,
+    config: SearchConfig,
+    nodes: u64,
+    stop: Arc<AtomicBool>,
+    node_limit: Option<u64>,
+    deadline: Option<Instant>,
+    /// Set once the current iteration has been abandoned, after which all
+    /// scores returned are meaningless.
+    stopped: bool,
+    /// The best move of the previous iteration, searched first at the root.
+    root_move: Option<Move>,
+    /// How many plies deeper than the main thread a helper thread searches
+    /// each iteration. See [`smp`.]
+    depth_skew: i32,
+    /// Helper threads leave ageing the shared transposition table to the main thread.
+    is_helper: bool,
+    multi_pv: usize,
+    /// The root moves already found to begin better lines in the current iteration.
+    excluded_root_moves: Vec<Move>,
+    /// The only root moves searched, or every legal move if empty.
+    searchmoves: Vec<Move>,
+    /// The endgame tables whose positions are scored without searching them.
+    tablebases: Option<Arc<Tablebases>>,
+}
+
+impl Searcher {
+    /// Creates a searcher with its own transposition table of the default size.
+    #[must_use]
+    pub fn new(board: &Board) -> Self {
+        Searcher::with_table(board, Arc::new(TranspositionTable::default()))
+    }
+
+    /// Creates a searcher that shares [`tt`] with other searches.
+    #[must_use]
+    pub fn with_table(board: &Board, tt: Arc<TranspositionTable>) -> Self {
+        let mut board = board.clone();
+        board.set_algorithm(Algorithm::Unmove);
+        Searcher {
+            board,
+            tt,
+            ordering: OrderingTables::new(),
+            pv_table: PvTable::new(),
+            config: SearchConfig::default(),
+            nodes: 0,
+            stop: Arc::new(AtomicBool::new(false)),
+            node_limit: None,
+            deadline: None,
+            stopped: false,
+            root_move: None,
+            depth_skew: 0,
+            is_helper: false,
+            multi_pv: 1,
+            excluded_root_moves: vec![],
+            searchmoves: vec![],
+            tablebases: None,
+        }
+    }
+
+    /// Creates a searcher for a helper thread of a parallel search. See [`smp`.]
+    pub(crate) fn helper(board: &Board, tt: Arc<TranspositionTable>, depth_skew: i32) -> Self {
+        Searcher {
+            depth_skew,
+            is_helper: true,
+            ..Searcher::with_table(board, tt)
+        }
+    }
+
+    pub fn set_config(&mut self, config: SearchConfig) {
+        self.config = config;
+    }
+
+    /// Sets how many of the best lines are searched and reported each iteration,
+    /// each of them starting with a different move.
+    pub fn set_multi_pv(&mut self, multi_pv: usize) {
+        self.multi_pv = multi_pv.max(1);
+    }
+
+    pub fn set_tablebases(&mut self, tablebases: Option<Arc<Tablebases>>) {
+        self.tablebases = tablebases;
+    }
+
+    /// Searches the position to a fixed [`depth`] in plies.
+    pub fn search(&mut self, depth: i32) -> SearchResult {
+        let stop = Arc::new(AtomicBool::new(false));
+        self.search_limited(&SearchLimits::depth(depth), &stop, |_| ())
+    }
+
+    /// Searches the position one ply deeper at a time until [`limits`] are reached
+    /// or [`stop`] is set, calling [`on_iteration`] with the result of each completed
+    /// iteration.
+    ///
+    /// The first iteration is always completed, so that a move is returned
+    /// whenever one exists. After that, a stopped iteration is discarded, and the
+    /// result of the last completed iteration is returned.
+    ///
+    /// See: [Iterative Deepening](https://www.chessprogramming.org/Iterative_Deepening)
+    pub fn search_limited(
+        &mut self,
+        limits: &SearchLimits,
+        stop: &Arc<AtomicBool>,
+        mut on_iteration: impl FnMut(&SearchResult),
+    ) -> SearchResult {
+        let start = Instant::now();
+        let budget = limits.time_budget(self.board.state().turn());
+        let max_depth = match limits.infinite {
+            true => MAX_PLY,
+            false => limits.depth.unwrap_or(MAX_PLY).clamp(1, MAX_PLY),
+        };
+        self.stop = Arc::clone(stop);
+        self.node_limit = limits.nodes.filter(|_| !limits.infinite);
+        self.deadline = None;
+        self.stopped = false;
+        self.nodes = 0;
+        self.root_move = None;
+        self.searchmoves.clone_from(&limits.searchmoves);
+        if !self.is_helper {
+            self.tt.new_search();
+        }
+        self.ordering.new_search();
+
+        let mut root_moves = self.board.gen_legal_moves();
+        root_moves.retain(|mov| self.is_root_move(mov));
+        let num_lines = self.multi_pv.min(root_moves.len()).max(1);
+        let mut lines: Vec<SearchResult> = vec![];
+        for depth in (1 + self.depth_skew).min(max_depth)..=max_depth {
+            // Each line is the best line that doesn't start with the first move of a line before it
+            let mut iteration_lines = vec![];
+            for multipv in 1..=num_lines {
+                let previous_score = lines.get(multipv - 1).map_or(0, |line| line.score);
+                let score = self.aspiration_search(depth, previous_score);
+                if self.stopped {
+                    break;
+                }
+                let pv = self.legal_pv(self.pv_table.line(0));
+                self.excluded_root_moves.extend(pv.first());
+                iteration_lines.push(SearchResult {
+                    best_move: pv.first().copied(),
+                    score,
+                    pv,
+                    nodes: self.nodes,
+                    depth,
+                    multipv,
+                });
+            }
+            self.excluded_root_moves.clear();
+            if self.stopped {
+                break;
+            }
+            lines = iteration_lines;
+            for line in &lines {
+                on_iteration(line);
+            }
+            let result = &lines[0];
+            self.root_move = result.best_move;
+
+            // Only the first iteration has to run to completion
+            self.deadline = budget.map(|(_, hard)| start + hard);
+            let out_of_time = budget.is_some_and(|(soft, _)| start.elapsed() >= soft);
+            let out_of_nodes = self.node_limit.is_some_and(|limit| self.nodes >= limit);
+            if result.best_move.is_none()
+                || out_of_time
+                || out_of_nodes
+                || stop.load(Ordering::Relaxed)
+            {
+                break;
+            }
+        }
+        let mut result = lines.into_iter().next().unwrap_or(SearchResult {
+            best_move: None,
+            score: 0,
+            pv: vec![],
+            nodes: 0,
+            depth: 0,
+            multipv: 1,
+        });
+        result.nodes = self.nodes;
+        result
+    }
+
+    /// Searches the root at [`depth`] with a narrow window around the score of the
+    /// previous iteration, which is widened every time the score falls outside of it.
+    ///
+    /// See: [Aspiration Windows](https://www.chessprogramming.org/Aspiration_Windows)
+    fn aspiration_search(&mut self, depth: i32, previous_score: i32) -> i32 {
+        let mut delta = ASPIRATION_WINDOW;
+        let (mut alpha, mut beta) =
+            match depth >= ASPIRATION_MIN_DEPTH && !is_mate_score(previous_score) {
+                true => (previous_score - delta, previous_score + delta),
+                false => (-INFINITY, INFINITY),
+            };
+        loop {
+            let score = self.negamax(depth, 0, alpha, beta);
+            if self.stopped {
+                return 0;
+            }
+            if score <= alpha {
+                alpha = (alpha - delta).max(-INFINITY);
+            } else if score >= beta {
+                beta = (beta + delta).min(INFINITY);
+            } else {
+                return score;
+            }
+            delta *= 2;
+        }
+    }
+
+    /// Returns the longest prefix of [`pv`] that can be played from the root.
+    /// Every move is expected to be legal, but a hash collision in the
+    /// transposition table could in principle slip an illegal move in.
+    fn legal_pv(&self, pv: &[Move]) -> Vec<Move> {
+        let mut board = self.board.clone();
+        let mut legal_pv = vec![];
+        for mov in pv {
+            if !board.gen_legal_moves().contains(mov) {
+                break;
+            }
+            board.make_move(mov);
+            legal_pv.push(*mov);
+        }
+        legal_pv
+    }
+
+    /// Returns whether [`mov`] is one of the root moves to choose from.
+    fn is_root_move(&self, mov: &Move) -> bool {
+        self.searchmoves.is_empty() || self.searchmoves.contains(mov)
+    }
+
+    /// Returns whether the search has run out of time or nodes, or was stopped.
+    /// Only checks the clock and the stop signal every [`CHECK_INTERVAL`] nodes.
+    fn should_stop(&mut self) -> bool {
+        if self.node_limit.is_some_and(|limit| self.nodes >= limit) {
+            self.stopped = true;
+        } else if self.nodes.is_multiple_of(CHECK_INTERVAL) {
+            self.stopped = self.stop.load(Ordering::Relaxed)
+                || self
+                    .deadline
+                    .is_some_and(|deadline| Instant::now() >= deadline);
+        }
+        self.stopped
+    }
+
+    /// Returns the score of the current position from the perspective of the player
+    /// to move, and stores the best line found in the PV table.
+    ///
+    /// See: [Negamax](https://www.chessprogramming.org/Negamax)
+    fn negamax(&mut self, mut depth: i32, ply: i32, mut alpha: i32, mut beta: i32) -> i32 {
+        self.pv_table.clear(ply as usize);
+        let in_check = self.board.in_check();
+        if in_check && self.config.check_extensions {
+            depth += 1;
+        }
+        if depth <= 0 {
+            return self.quiescence(ply, alpha, beta);
+        }
+        self.nodes += 1;
+
+        // The first iteration has no move to fall back on, so it is never stopped
+        if self.root_move.is_some() && self.should_stop() {
+            return 0;
+        }
+        // A repetition is scored as a draw the first time, since if it was good for
+        // either player it could be repeated again
+        if ply > 0
+            && (self.board.state().game_state() == GameState::Draw || self.board.is_repetition())
+        {
+            return DRAW_SCORE;
+        }
+        if ply >= MAX_PLY {
+            return self.board.evaluate();
+        }
+        // The root is searched, so that a move is found, but the positions after it
+        // are scored exactly by their table
+        if ply > 0 {
+            let tablebases = self.tablebases.as_ref();
+            if let Some(probe) = tablebases.and_then(|tablebases| tablebases.probe(&self.board)) {
+                return probe.score(ply);
+            }
+        }
+
+        // No line can be better than mating on the next move, or worse than being mated now
+        if self.config.mate_distance_pruning && ply > 0 {
+            alpha = alpha.max(-MATE_SCORE + ply);
+            beta = beta.min(MATE_SCORE - ply - 1);
+            if alpha >= beta {
+                return alpha;
+            }
+        }
+        let original_alpha = alpha;
+        // Only nodes searched with an open window can end up on the principal variation
+        let is_pv = beta - alpha > 1;
+
+        let hash = self.board.hash();
+        let tt_entry = self.tt.probe(hash);
+        // Cutting off at PV nodes would cut the principal variation short
+        if let Some(entry) = tt_entry.filter(|entry| !is_pv && ply > 0 && entry.depth >= depth) {
+            let score = score_from_tt(entry.score, ply);
+            match entry.bound {
+                Bound::Exact => return score,
+                Bound::Lower if score >= beta => return score,
+                Bound::Upper if score <= alpha => return score,
+                _ => (),
+            }
+        }
+
+        let static_eval = match in_check {
+            true => -INFINITY,
+            false => self.board.evaluate(),
+        };
+        let can_prune = !is_pv && !in_check && ply > 0;
+
+        // Reverse futility pruning: the position is so far above beta that
+        // a shallow search is unlikely to bring it back down
+        if can_prune
+            && self.config.reverse_futility_pruning
+            && depth <= REVERSE_FUTILITY_MAX_DEPTH
+            && !is_mate_score(beta)
+            && static_eval - REVERSE_FUTILITY_MARGIN * depth >= beta
+        {
+            return static_eval;
+        }
+
+        // Null move pruning: if passing the turn still fails high, so would any move.
+        // Positions with only pawns left are prone to zugzwang, where passing would
+        // be the best move if it were legal, and consecutive null moves are pointless.
+        if can_prune
+            && self.config.null_move_pruning
+            && depth >= NULL_MOVE_MIN_DEPTH
+            && static_eval >= beta
+            && self.board.state().last_move().is_some()
+            && has_non_pawn_material(&self.board, self.board.state().turn())
+        {
+            let reduction = NULL_MOVE_REDUCTION + depth / 6;
+            self.board.make_null_move();
+            self.tt.prefetch(self.board.hash());
+            let score = -self.negamax(depth - 1 - reduction, ply + 1, -beta, -beta + 1);
+            self.board.unmake_null_move();
+            if self.stopped {
+                return 0;
+            }
+            if score >= beta {
+                // A mate found after passing is not proven with the moves actually available
+                return match is_mate_score(score) {
+                    true => beta,
+                    false => score,
+                };
+            }
+        }
+
+        // Futility pruning: quiet moves can't raise a position this far below alpha
+        let futile = can_prune
+            && self.config.futility_pruning
+            && depth <= FUTILITY_MARGINS.len() as i32
+            && !is_mate_score(alpha)
+            && static_eval + FUTILITY_MARGINS[depth as usize - 1] <= alpha;
+
+        let mut moves = self.board.gen_legal_moves();
+        if moves.is_empty() {
+            return match in_check {
+                true => -MATE_SCORE + ply,
+                false => DRAW_SCORE,
+            };
+        }
+        let tt_move = match ply {
+            0 => self.root_move,
+            _ => tt_entry.and_then(|entry| entry.best_move),
+        };
+        if ply == 0 {
+            moves.retain(|mov| self.is_root_move(mov) && !self.excluded_root_moves.contains(mov));
+        }
+        self.ordering.order(&self.board, &mut moves, tt_move, ply);
+
+        let mut best_score = -INFINITY;
+        let mut best_move = None;
+        let mut searched_quiets = vec![];
+        for (move_index, mov) in moves.into_iter().enumerate() {
+            let is_quiet = !ordering::is_capture(&self.board, &mov) && mov.promotion_kind.is_none();
+            self.board.make_move(&mov);
+            // The child's bucket loads while the move is checked and maybe pruned
+            self.tt.prefetch(self.board.hash());
+            let gives_check = self.board.in_check();
+
+            if futile && is_quiet && move_index > 0 && !gives_check {
+                self.board.unmake_move();
+                continue;
+            }
+
+            // Late move reductions: moves ordered late are unlikely to be best,
+            // so they are first searched shallower with a null window, and only
+            // searched fully if they turn out to raise alpha.
+            //
+            // See: [Late Move Reductions](https://www.chessprogramming.org/Late_Move_Reductions)
+            let reduction = match self.config.late_move_reductions
+                && move_index >= LMR_MIN_MOVE_INDEX
+                && depth >= LMR_MIN_DEPTH
+                && is_quiet
+                && !in_check
+                && !gives_check
+            {
+                true => late_move_reduction(depth, move_index, is_pv),
+                false => 0,
+            };
+
+            // Principal variation search: once a first move has been searched, the
+            // rest are only searched with a null window to prove that they are worse,
+            // and searched again with the full window if they turn out not to be.
+            //
+            // See: [Principal Variation Search](https://www.chessprogramming.org/Principal_Variation_Search)
+            let score = if move_index == 0 {
+                -self.negamax(depth - 1, ply + 1, -beta, -alpha)
+            } else {
+                let mut score = alpha + 1;
+                if reduction > 0 {
+                    score = -self.negamax(depth - 1 - reduction, ply + 1, -alpha - 1, -alpha);
+                }
+                if score > alpha {
+                    score = -self.negamax(depth - 1, ply + 1, -alpha - 1, -alpha);
+                }
+                if score > alpha && score < beta {
+                    score = -self.negamax(depth - 1, ply + 1, -beta, -alpha);
+                }
+                score
+            };
+            self.board.unmake_move();
+            if self.stopped {
+                return 0;
+            }
+
+            if score > best_score {
+                best_score = score;
+                best_move = Some(mov);
+            }
+            if score > alpha {
+                alpha = score;
+                self.pv_table.update(ply as usize, mov);
+            }
+            if alpha >= beta {
+                if is_quiet {
+                    let state = self.board.state();
+                    self.ordering.update_cutoff(
+                        state.turn(),
+                        mov,
+                        state.last_move(),
+                        &searched_quiets,
+                        ply,
+                        depth,
+                    );
+                }
+                break;
+            }
+            if is_quiet {
+                searched_quiets.push(mov);
+            }
+        }
+
+        let bound = match best_score {
+            score if score >= beta => Bound::Lower,
+            score if score > original_alpha => Bound::Exact,
+            _ => Bound::Upper,
+        };
+        // A root searched without some of its moves is not stored, as its score may not be its own
+        if ply > 0 || (self.excluded_root_moves.is_empty() && self.searchmoves.is_empty()) {
+            self.tt
+                .store(hash, best_move, depth, bound, score_to_tt(best_score, ply));
+        }
+        best_score
+    }
+
+    /// Searches captures and promotions until the position is quiet, so that the
+    /// evaluation is never taken in the middle of an exchange. The player to move
+    /// may "stand pat" on the static evaluation instead of capturing, unless in check,
+    /// in which case every evasion is searched.
+    ///
+    /// See: [Quiescence Search](https://www.chessprogramming.org/Quiescence_Search)
+    fn quiescence(&mut self, ply: i32, mut alpha: i32, beta: i32) -> i32 {
+        self.nodes += 1;
+
+        if self.root_move.is_some() && self.should_stop() {
+            return 0;
+        }
+        if self.board.state().game_state() == GameState::Draw || self.board.is_repetition() {
+            return DRAW_SCORE;
+        }
+        if ply >= MAX_PLY {
+            return self.board.evaluate();
+        }
+
+        let in_check = self.board.in_check();
+        let stand_pat = self.board.evaluate();
+        let mut moves = match in_check {
+            true => self.board.gen_legal_moves(),
+            false => self.board.gen_legal_captures(),
+        };
+        let mut best_score = match in_check {
+            true if moves.is_empty() => return -MATE_SCORE + ply,
+            true => -INFINITY,
+            false => stand_pat,
+        };
+        if best_score >= beta {
+            return best_score;
+        }
+        alpha = alpha.max(best_score);
+        self.ordering.order(&self.board, &mut moves, None, ply);
+
+        for mov in moves {
+            if !in_check {
+                // Delta pruning: skip captures that can't raise alpha even if the
+                // captured piece were won for free
+                let mut gain = match ordering::is_capture(&self.board, &mov) {
+                    true => eval::value(ordering::captured_piece(&self.board, &mov).kind),
+                    false => 0,
+                };
+                if let Some(kind) = mov.promotion_kind {
+                    gain += eval::value(kind) - eval::value(Kind::Pawn);
+                }
+                if stand_pat + gain + DELTA_MARGIN <= alpha {
+                    continue;
+                }
+                if ordering::see(&self.board, &mov) < 0 {
+                    continue;
+                }
+            }
+
+            self.board.make_move(&mov);
+            let score = -self.quiescence(ply + 1, -beta, -alpha);
+            self.board.unmake_move();
+            if self.stopped {
+                return 0;
+            }
+
+            if score > best_score {
+                best_score = score;
+            }
+            if score > alpha {
+                alpha = score;
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+        best_score
+    }
+}
+
+/// Finds the best move in [`board`'s] position by searching [`depth`] plies deep.
+///
+/// See: [Alpha-Beta](https://www.chessprogramming.org/Alpha-Beta)
+#[must_use]
+pub fn search(board: &Board, depth: i32) -> SearchResult {
+    Searcher::new(board).search(depth)
+}
+
+/// Finds the best move in [`board`'s] position within [`limits`,] or until [`stop`] is set.
+#[must_use]
+pub fn search_limited(
+    board: &Board,
+    limits: &SearchLimits,
+    stop: &Arc<AtomicBool>,
+) -> SearchResult {
+    Searcher::new(board).search_limited(limits, stop, |_| ())
+}
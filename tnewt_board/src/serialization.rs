@@ -0,0 +1,140 @@
+//! Serde encodings behind the `serde` feature, which are stable and readable: a
+//! position is its FEN, a move its UCI string, a piece its FEN char, a square its
+//! name and a color `w` or `b`.
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::board::{self, Board, State, STARTING_FEN};
+use crate::color::Color;
+use crate::coordinate::Coordinate;
+use crate::mov::Move;
+use crate::piece::{Kind, Piece};
+
+/// Deserializes a string and parses it with [`parse`.]
+fn deserialize_with<'de, D, T>(
+    deserializer: D,
+    parse: impl FnOnce(&str) -> Result<T, board::Error>,
+) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let text = String::deserialize(deserializer)?;
+    parse(&text).map_err(D::Error::custom)
+}
+
+impl Serialize for Board {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_fen())
+    }
+}
+
+impl<'de> Deserialize<'de> for Board {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_with(deserializer, Board::from_fen)
+    }
+}
+
+/// A state is the fields of its position's FEN after the pieces, e.g. `w KQkq e3 0 1`.
+impl Serialize for State {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let castling_rights = match self.castling_state().to_str() {
+            rights if rights.is_empty() => "-".to_string(),
+            rights => rights,
+        };
+        let ep_square = match self.ep_index() {
+            Some(index) => Coordinate::from_index(index).to_string(),
+            None => "-".to_string(),
+        };
+        serializer.serialize_str(&format!(
+            "{} {castling_rights} {ep_square} {} {}",
+            color_str(self.turn()),
+            self.halfmove_clock(),
+            self.fullmove_count()
+        ))
+    }
+}
+
+/// A state is parsed as the state of the starting position, whose kings and rooks
+/// the castling rights refer to. Chess960 rights, which name the files of rooks
+/// that may not be on their standard squares, are an error: deserialize the
+/// [`Board`] instead.
+impl<'de> Deserialize<'de> for State {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_with(deserializer, |text| {
+            let castling_rights = text.split(' ').nth(1).unwrap_or_default();
+            if let Some(c) = castling_rights.chars().find(|c| !"KQkq-".contains(*c)) {
+                return Err(board::Error::InvalidCastlingChar(c));
+            }
+            let pieces = STARTING_FEN
+                .split(' ')
+                .next()
+                .expect("A FEN starts with its pieces");
+            Ok(Board::from_fen(&format!("{pieces} {text}"))?.state())
+        })
+    }
+}
+
+impl Serialize for Move {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Move {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_with(deserializer, Move::from_uci)
+    }
+}
+
+impl Serialize for Piece {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_char(Piece::square_to_char(&Some(*self)))
+    }
+}
+
+impl<'de> Deserialize<'de> for Piece {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let char = char::deserialize(deserializer)?;
+        let color = match char.is_ascii_uppercase() {
+            true => Color::White,
+            false => Color::Black,
+        };
+        let kind = Kind::from(char.to_ascii_lowercase())
+            .map_err(|_| D::Error::custom(board::Error::InvalidPieceChar(char)))?;
+        Ok(Piece { kind, color })
+    }
+}
+
+impl Serialize for Coordinate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Coordinate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_with(deserializer, |text| {
+            Coordinate::from(text)?.ok_or_else(|| board::Error::InvalidCoordinate(text.to_string()))
+        })
+    }
+}
+
+fn color_str(color: Color) -> &'static str {
+    match color {
+        Color::White => "w",
+        Color::Black => "b",
+    }
+}
+
+impl Serialize for Color {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(color_str(*self))
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_with(deserializer, Color::from)
+    }
+}
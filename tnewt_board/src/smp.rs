@@ -0,0 +1,171 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use crate::board::Board;
+use crate::polyglot::{Book, Selection};
+use crate::search::{SearchConfig, SearchLimits, SearchResult, Searcher};
+use crate::tablebase::Tablebases;
+use crate::tt::{self, TranspositionTable};
+
+/// Helpers recurse as deep as the main thread, which may run on a larger stack.
+const HELPER_STACK_SIZE: usize = 16 * 1024 * 1024;
+
+/// A multi-threaded search, where every thread runs its own iterative deepening
+/// on its own copy of the position, and the threads only cooperate through a
+/// shared, lock-free transposition table. Half of the helper threads search one
+/// ply deeper than the main thread, so that the threads diverge and fill the
+/// table with different parts of the tree.
+///
+/// See: [Lazy SMP](https://www.chessprogramming.org/Lazy_SMP)
+pub struct LazySmp {
+    tt: Arc<TranspositionTable>,
+    threads: usize,
+    config: SearchConfig,
+    multi_pv: usize,
+    /// The book consulted before searching, and how its moves are picked.
+    book: Option<(Arc<Book>, Selection)>,
+    tablebases: Option<Arc<Tablebases>>,
+}
+
+impl Default for LazySmp {
+    fn default() -> Self {
+        Self::new(1, tt::DEFAULT_SIZE_MB)
+    }
+}
+
+impl LazySmp {
+    /// Creates a search running on [`threads`] threads in total, sharing a
+    /// transposition table of [`hash_mb`] MB.
+    #[must_use]
+    pub fn new(threads: usize, hash_mb: usize) -> Self {
+        LazySmp {
+            tt: Arc::new(TranspositionTable::new(hash_mb)),
+            threads: threads.max(1),
+            config: SearchConfig::default(),
+            multi_pv: 1,
+            book: None,
+            tablebases: None,
+        }
+    }
+
+    #[must_use]
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Sets the total number of threads, including the main thread, used by the
+    /// next search.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    /// Replaces the transposition table with an empty one of [`hash_mb`] MB.
+    pub fn set_hash(&mut self, hash_mb: usize) {
+        self.tt = Arc::new(TranspositionTable::new(hash_mb));
+    }
+
+    #[must_use]
+    pub fn tt(&self) -> &TranspositionTable {
+        &self.tt
+    }
+
+    pub fn set_config(&mut self, config: SearchConfig) {
+        self.config = config;
+    }
+
+    /// Sets how many of the best lines the main thread searches. Helpers only search the best line.
+    pub fn set_multi_pv(&mut self, multi_pv: usize) {
+        self.multi_pv = multi_pv.max(1);
+    }
+
+    /// Sets the opening book whose moves are played without searching, if any.
+    pub fn set_book(&mut self, book: Option<(Arc<Book>, Selection)>) {
+        self.book = book;
+    }
+
+    /// Sets the endgame tables every thread scores the positions it reaches with, if any.
+    pub fn set_tablebases(&mut self, tablebases: Option<Arc<Tablebases>>) {
+        self.tablebases = tablebases;
+    }
+
+    /// Forgets everything learned from previous searches, e.g. when a new game starts.
+    pub fn clear(&self) {
+        self.tt.clear();
+    }
+
+    /// Searches [`board`'s] position within [`limits`,] or until [`stop`] is set.
+    /// Only the main thread respects the limits and reports its completed
+    /// iterations to [`on_iteration`,] and the helpers are stopped once it finishes.
+    ///
+    /// Returns the deepest completed result of any thread, preferring the main
+    /// thread's, with the nodes of every thread added up. If the book has a move
+    /// for the position, it is returned at depth 0 without searching, unless the
+    /// search is infinite, as an analysis is after more than a move.
+    pub fn search(
+        &self,
+        board: &Board,
+        limits: &SearchLimits,
+        stop: &Arc<AtomicBool>,
+        on_iteration: impl FnMut(&SearchResult),
+    ) -> SearchResult {
+        if let Some((book, selection)) = self.book.as_ref().filter(|_| !limits.infinite) {
+            let book_move = book.choose(board, *selection, &mut rand::thread_rng());
+            if let Some(mov) = book_move
+                .filter(|mov| limits.searchmoves.is_empty() || limits.searchmoves.contains(mov))
+            {
+                return SearchResult {
+                    best_move: Some(mov),
+                    score: 0,
+                    pv: vec![mov],
+                    nodes: 0,
+                    depth: 0,
+                    multipv: 1,
+                };
+            }
+        }
+        let helpers_stop = Arc::new(AtomicBool::new(false));
+        let helper_limits = SearchLimits {
+            infinite: true,
+            searchmoves: limits.searchmoves.clone(),
+            ..SearchLimits::default()
+        };
+
+        thread::scope(|scope| {
+            let helpers: Vec<_> = (1..self.threads)
+                .map(|index| {
+                    let mut searcher =
+                        Searcher::helper(board, Arc::clone(&self.tt), (index % 2) as i32);
+                    searcher.set_config(self.config);
+                    searcher.set_tablebases(self.tablebases.clone());
+                    let (helper_limits, helpers_stop) = (&helper_limits, &helpers_stop);
+                    thread::Builder::new()
+                        .stack_size(HELPER_STACK_SIZE)
+                        .spawn_scoped(scope, move || {
+                            searcher.search_limited(helper_limits, helpers_stop, |_| ())
+                        })
+                        .expect("Failed to spawn helper thread")
+                })
+                .collect();
+
+            let mut searcher = Searcher::with_table(board, Arc::clone(&self.tt));
+            searcher.set_config(self.config);
+            searcher.set_multi_pv(self.multi_pv);
+            searcher.set_tablebases(self.tablebases.clone());
+            let mut result = searcher.search_limited(limits, stop, on_iteration);
+            helpers_stop.store(true, Ordering::Relaxed);
+
+            for helper in helpers {
+                let helper_result = helper.join().expect("Helper thread panicked");
+                result.nodes += helper_result.nodes;
+                if helper_result.best_move.is_some() && helper_result.depth > result.depth {
+                    result = SearchResult {
+                        nodes: result.nodes,
+                        ..helper_result
+                    };
+                }
+            }
+            result
+        })
+    }
+}
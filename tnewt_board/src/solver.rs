@@ -0,0 +1,367 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::board::Board;
+use crate::mov::Move;
+
+/// Proof and disproof numbers this large are infinite: the node is disproved or proved.
+const INFINITY: u32 = u32::MAX / 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    InvalidStipulation(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::InvalidStipulation(stipulation) => write!(
+                f,
+                "invalid stipulation `{stipulation}`, expected e.g. `#2`, `h#3` or `s#2`"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// What the side to move in a problem's position aims for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Goal {
+    /// The side to move mates against any defense.
+    Mate,
+    /// The side to move helps the other side mate it, both moving to that end.
+    HelpMate,
+    /// The side to move forces the other side to mate it, which the other side resists.
+    SelfMate,
+}
+
+/// A problem's goal and the most moves of the side to move it is reached in, e.g.
+/// `#2`, `h#3` or `s#2`.
+///
+/// See: [Chess Problem](https://www.chessprogramming.org/Chess_Problem)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stipulation {
+    pub goal: Goal,
+    pub moves: u32,
+}
+
+impl std::fmt::Display for Stipulation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let prefix = match self.goal {
+            Goal::Mate => "",
+            Goal::HelpMate => "h",
+            Goal::SelfMate => "s",
+        };
+        write!(f, "{prefix}#{}", self.moves)
+    }
+}
+
+impl FromStr for Stipulation {
+    type Err = Error;
+
+    fn from_str(stipulation: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidStipulation(stipulation.to_string());
+        let (goal, moves) = match stipulation.split_once('#').ok_or_else(invalid)? {
+            ("", moves) => (Goal::Mate, moves),
+            ("h", moves) => (Goal::HelpMate, moves),
+            ("s", moves) => (Goal::SelfMate, moves),
+            _ => return Err(invalid()),
+        };
+        match moves.parse() {
+            Ok(moves) if moves > 0 => Ok(Stipulation { goal, moves }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// A move of a solution, with every move that follows it: each defense after a
+/// move of the side that reaches the goal, and each way to go on after a defense.
+/// In a helpmate both sides' moves are followed by every way to go on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub mov: Move,
+    pub continuations: Vec<Line>,
+}
+
+/// A position reached in a solution where the side choosing its move has more
+/// than one way to go on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dual {
+    /// The moves from the problem's position to the one with the dual.
+    pub line: Vec<Move>,
+    pub moves: Vec<Move>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Solution {
+    pub stipulation: Stipulation,
+    /// Every first move that reaches the goal, the problem's key if there is one.
+    pub keys: Vec<Line>,
+}
+
+impl Solution {
+    /// The keys besides the first, which cook a problem meant to have one solution.
+    #[must_use]
+    pub fn cooks(&self) -> &[Line] {
+        self.keys.get(1..).unwrap_or_default()
+    }
+
+    /// Every position after the key where a side choosing its move has more than
+    /// one way to reach the goal in the fewest moves left, which the defending
+    /// side of a mate or selfmate never does.
+    #[must_use]
+    pub fn duals(&self) -> Vec<Dual> {
+        let mut duals = vec![];
+        for key in &self.keys {
+            self.find_duals(key, &mut vec![], &mut duals);
+        }
+        duals
+    }
+
+    fn find_duals(&self, line: &Line, path: &mut Vec<Move>, duals: &mut Vec<Dual>) {
+        path.push(line.mov);
+        // The side to move after an odd number of moves defends in a mate or selfmate
+        let chooses = self.stipulation.goal == Goal::HelpMate || path.len().is_multiple_of(2);
+        if chooses && line.continuations.len() > 1 {
+            duals.push(Dual {
+                line: path.clone(),
+                moves: line.continuations.iter().map(|line| line.mov).collect(),
+            });
+        }
+        for continuation in &line.continuations {
+            self.find_duals(continuation, path, duals);
+        }
+        path.pop();
+    }
+}
+
+/// Which side moves at a node of a problem's tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    /// The side to move in the problem's position.
+    First,
+    Second,
+}
+
+/// What a node's position decides, before its moves are searched.
+enum Expansion {
+    Proved,
+    Disproved,
+    Moves(Vec<Move>),
+}
+
+/// Solves chess problems by depth-first proof-number search, which searches the
+/// moves closest to proving or disproving the goal first, and remembers the proof
+/// and disproof numbers of the positions searched between solves.
+///
+/// See: [Proof-Number Search](https://www.chessprogramming.org/Proof-Number_Search)
+pub struct Solver {
+    stipulation: Stipulation,
+    /// The proof and disproof numbers of each position by its hash and the moves
+    /// left to the first side, which also tell the side to move.
+    table: HashMap<(u64, u32), (u32, u32)>,
+    nodes: u64,
+}
+
+impl Solver {
+    #[must_use]
+    pub fn new(stipulation: Stipulation) -> Self {
+        Solver {
+            stipulation,
+            table: HashMap::new(),
+            nodes: 0,
+        }
+    }
+
+    /// The number of nodes searched so far.
+    #[must_use]
+    pub fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    /// Finds every key of the problem in [`board`'s] position, with every line
+    /// that follows it.
+    pub fn solve(&mut self, board: &Board) -> Solution {
+        let mut board = board.clone();
+        let moves = self.stipulation.moves;
+        Solution {
+            stipulation: self.stipulation,
+            keys: self.lines(&mut board, Side::First, moves, true),
+        }
+    }
+
+    /// Whether the goal is reached from [`board`'s] position, with [`side`] to move
+    /// and [`left`] moves left to the first side.
+    fn prove(&mut self, board: &mut Board, side: Side, left: u32) -> bool {
+        let (proof, _) = self.mid(board, side, left, INFINITY, INFINITY);
+        proof == 0
+    }
+
+    fn expand(&self, board: &mut Board, side: Side, left: u32) -> Expansion {
+        let moves = board.gen_legal_moves();
+        let decided = |proved: bool| match proved {
+            true => Expansion::Proved,
+            false => Expansion::Disproved,
+        };
+        let mated = || moves.is_empty() && board.in_check();
+        match (self.stipulation.goal, side) {
+            (Goal::Mate, Side::First) if left == 0 || moves.is_empty() => decided(false),
+            (Goal::Mate, Side::Second) if moves.is_empty() => decided(mated()),
+            // The last move didn't mate
+            (Goal::Mate, Side::Second) if left == 1 => decided(false),
+            (Goal::HelpMate | Goal::SelfMate, Side::First) if moves.is_empty() => decided(mated()),
+            (Goal::HelpMate | Goal::SelfMate, Side::First) if left == 0 => decided(false),
+            (Goal::HelpMate | Goal::SelfMate, Side::Second) if moves.is_empty() => decided(false),
+            _ => Expansion::Moves(moves),
+        }
+    }
+
+    /// Whether [`side`] picks one move to reach the goal, rather than each of its
+    /// moves having to.
+    fn is_or_node(&self, side: Side) -> bool {
+        self.stipulation.goal == Goal::HelpMate || side == Side::First
+    }
+
+    /// The side to move and the moves left after a move of [`side`.]
+    fn child(side: Side, left: u32) -> (Side, u32) {
+        match side {
+            Side::First => (Side::Second, left),
+            Side::Second => (Side::First, left - 1),
+        }
+    }
+
+    /// Searches the position until its proof number reaches [`proof_threshold`] or
+    /// its disproof number reaches [`disproof_threshold`,] and returns both.
+    ///
+    /// See: [Nagai, Df-pn Algorithm](https://www.chessprogramming.org/Proof-Number_Search#Df-pn)
+    fn mid(
+        &mut self,
+        board: &mut Board,
+        side: Side,
+        left: u32,
+        proof_threshold: u32,
+        disproof_threshold: u32,
+    ) -> (u32, u32) {
+        self.nodes += 1;
+        let key = (board.hash(), left);
+        let moves = match self.expand(board, side, left) {
+            Expansion::Proved => return self.store(key, (0, INFINITY)),
+            Expansion::Disproved => return self.store(key, (INFINITY, 0)),
+            Expansion::Moves(moves) => moves,
+        };
+        let (child_side, child_left) = Solver::child(side, left);
+        let mut children: Vec<Board> = moves
+            .iter()
+            .map(|mov| {
+                let mut child = board.clone();
+                child.make_move(mov);
+                child
+            })
+            .collect();
+        let child_keys: Vec<(u64, u32)> = children
+            .iter()
+            .map(|child| (child.hash(), child_left))
+            .collect();
+        let is_or_node = self.is_or_node(side);
+
+        loop {
+            // An OR node is as close to proved as its closest child and as far from
+            // disproved as all its children together, and an AND node the other way round
+            let numbers: Vec<(u32, u32)> = child_keys
+                .iter()
+                .map(|key| self.table.get(key).copied().unwrap_or((1, 1)))
+                .map(|(proof, disproof)| match is_or_node {
+                    true => (proof, disproof),
+                    false => (disproof, proof),
+                })
+                .collect();
+            let closest = numbers
+                .iter()
+                .map(|&(near, _)| near)
+                .min()
+                .unwrap_or(INFINITY);
+            let total = numbers
+                .iter()
+                .fold(0, |total: u32, &(_, far)| total.saturating_add(far))
+                .min(INFINITY);
+            let (proof, disproof) = match is_or_node {
+                true => (closest, total),
+                false => (total, closest),
+            };
+            if proof >= proof_threshold || disproof >= disproof_threshold {
+                return self.store(key, (proof, disproof));
+            }
+
+            let (best, &(best_near, best_far)) = numbers
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, &(near, _))| near)
+                .expect("A node with moves has children");
+            let second_near = numbers
+                .iter()
+                .enumerate()
+                .filter(|&(index, _)| index != best)
+                .map(|(_, &(near, _))| near)
+                .min()
+                .unwrap_or(INFINITY);
+            let (near_threshold, far_threshold) = match is_or_node {
+                true => (proof_threshold, disproof_threshold),
+                false => (disproof_threshold, proof_threshold),
+            };
+            let child_near = near_threshold.min(second_near.saturating_add(1));
+            let child_far = (far_threshold - total.min(far_threshold)).saturating_add(best_far);
+            let (child_proof, child_disproof) = match is_or_node {
+                true => (child_near, child_far),
+                false => (child_far, child_near),
+            };
+            debug_assert!(best_near < child_near);
+            self.mid(
+                &mut children[best],
+                child_side,
+                child_left,
+                child_proof,
+                child_disproof,
+            );
+        }
+    }
+
+    fn store(&mut self, key: (u64, u32), numbers: (u32, u32)) -> (u32, u32) {
+        self.table.insert(key, numbers);
+        numbers
+    }
+
+    /// Every move of [`side`] that reaches the goal, or that the other side can't
+    /// stop reaching it, with the lines that follow. After the key, the first side
+    /// of a mate or selfmate only goes on in the fewest moves it can.
+    fn lines(&mut self, board: &mut Board, side: Side, left: u32, root: bool) -> Vec<Line> {
+        let Expansion::Moves(moves) = self.expand(board, side, left) else {
+            return vec![];
+        };
+        let is_or_node = self.is_or_node(side);
+        let fewest_first = !root && side == Side::First && self.stipulation.goal != Goal::HelpMate;
+        let lefts = match fewest_first {
+            true => 1..=left,
+            false => left..=left,
+        };
+        for left in lefts {
+            let (child_side, child_left) = Solver::child(side, left);
+            let mut lines = vec![];
+            for mov in &moves {
+                let mut child = board.clone();
+                child.make_move(mov);
+                if is_or_node && !self.prove(&mut child, child_side, child_left) {
+                    continue;
+                }
+                lines.push(Line {
+                    mov: *mov,
+                    continuations: self.lines(&mut child, child_side, child_left, false),
+                });
+            }
+            if !lines.is_empty() {
+                return lines;
+            }
+        }
+        vec![]
+    }
+}
@@ -0,0 +1,919 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::board::Board;
+use crate::color::Color;
+use crate::mov::Move;
+use crate::ordering;
+use crate::piece::{Kind, Piece};
+use crate::retro::unmove_origins;
+use crate::search::{DRAW_SCORE, MATE_SCORE};
+
+/// The most pieces, kings included, of the positions tables are generated for.
+pub const MAX_PIECES: usize = 4;
+/// The endings whose tables are generated unless others are asked for.
+pub const ENDINGS: [&str; 5] = ["KQK", "KRK", "KPK", "KBNK", "KQKR"];
+/// The extension of table files, which are named after their material, e.g. `KQKR.tnb`.
+pub const EXTENSION: &str = "tnb";
+const MAGIC: &[u8; 4] = b"TNTB";
+const VERSION: u8 = 1;
+
+// While a table is generated, each position holds one byte: 0 while its value is
+// unknown, the plies to mate plus one once it is known to be won or lost, or
+// `INVALID` if it can't be reached in a game. Won positions are an odd number of
+// plies from mate, and lost ones an even number. Tables store unknown positions
+// as draws, and their files any value for invalid ones, as they are never probed.
+const UNKNOWN: u8 = 0;
+const INVALID: u8 = u8::MAX;
+const MAX_PLIES: usize = INVALID as usize - 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    Io(String),
+    InvalidMaterial(String),
+    /// Tables can't be generated for the material, e.g. because it has too many pieces.
+    UnsupportedMaterial(String),
+    /// The table a capture or promotion leads to hasn't been generated.
+    MissingTable(String),
+    InvalidFile(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::InvalidMaterial(material) => write!(f, "invalid material `{material}`"),
+            Error::UnsupportedMaterial(material) => {
+                write!(f, "tables can't be generated for `{material}`")
+            }
+            Error::MissingTable(material) => write!(f, "the table of `{material}` is missing"),
+            Error::InvalidFile(reason) => write!(f, "invalid table file: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error.to_string())
+    }
+}
+
+/// The pieces on the board besides the kings, written like `KQKR` for a white
+/// king and queen against a black king and rook.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Material {
+    /// Each side's pieces, from the most valuable.
+    white: Vec<Kind>,
+    black: Vec<Kind>,
+}
+
+impl Material {
+    /// The material of [`board`'s] position, or `None` if it has more than [`MAX_PIECES`.]
+    #[must_use]
+    pub fn of(board: &Board) -> Option<Material> {
+        let mut material = Material {
+            white: vec![],
+            black: vec![],
+        };
+        let mut count = 0;
+        for piece in board.squares.iter().flatten() {
+            count += 1;
+            if count > MAX_PIECES {
+                return None;
+            }
+            if piece.kind != Kind::King {
+                material.side_mut(piece.color).push(piece.kind);
+            }
+        }
+        material.white.sort();
+        material.black.sort();
+        Some(material)
+    }
+
+    fn side(&self, color: Color) -> &[Kind] {
+        match color {
+            Color::White => &self.white,
+            Color::Black => &self.black,
+        }
+    }
+
+    fn side_mut(&mut self, color: Color) -> &mut Vec<Kind> {
+        match color {
+            Color::White => &mut self.white,
+            Color::Black => &mut self.black,
+        }
+    }
+
+    /// The pieces besides the kings, white's first.
+    fn pieces(&self) -> impl Iterator<Item = Piece> + '_ {
+        [Color::White, Color::Black]
+            .into_iter()
+            .flat_map(move |color| {
+                self.side(color)
+                    .iter()
+                    .map(move |&kind| Piece { kind, color })
+            })
+    }
+
+    fn len(&self) -> usize {
+        2 + self.white.len() + self.black.len()
+    }
+
+    fn has_pawns(&self) -> bool {
+        self.pieces().any(|piece| piece.kind == Kind::Pawn)
+    }
+
+    /// The same material with the colors swapped.
+    #[must_use]
+    pub fn mirrored(&self) -> Material {
+        Material {
+            white: self.black.clone(),
+            black: self.white.clone(),
+        }
+    }
+
+    /// Tables are only generated with the stronger side as white, and the
+    /// material with the colors swapped is found in the same table.
+    #[must_use]
+    pub fn canonical(&self) -> Material {
+        let strength = |kinds: &[Kind]| {
+            let value: i32 = kinds.iter().map(|&kind| crate::eval::value(kind)).sum();
+            (value, kinds.len())
+        };
+        match strength(&self.white) < strength(&self.black) {
+            true => self.mirrored(),
+            false => self.clone(),
+        }
+    }
+
+    /// The canonical materials one capture, promotion, or capture with a promotion leads to.
+    fn successors(&self) -> Vec<Material> {
+        let mut successors = vec![];
+        let mut add = |material: Material| {
+            let mut material = material;
+            material.white.sort();
+            material.black.sort();
+            let material = material.canonical();
+            if !successors.contains(&material) {
+                successors.push(material);
+            }
+        };
+        for color in [Color::White, Color::Black] {
+            for index in 0..self.side(color).len() {
+                let mut captured = self.clone();
+                captured.side_mut(color).remove(index);
+                add(captured.clone());
+                // The capturing pawn may promote too
+                for (pawn_index, _) in captured
+                    .side(color.opposite())
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &kind)| kind == Kind::Pawn)
+                {
+                    for kind in [Kind::Queen, Kind::Rook, Kind::Bishop, Kind::Knight] {
+                        let mut promoted = captured.clone();
+                        promoted.side_mut(color.opposite())[pawn_index] = kind;
+                        add(promoted);
+                    }
+                }
+            }
+            for (pawn_index, _) in self
+                .side(color)
+                .iter()
+                .enumerate()
+                .filter(|(_, &kind)| kind == Kind::Pawn)
+            {
+                for kind in [Kind::Queen, Kind::Rook, Kind::Bishop, Kind::Knight] {
+                    let mut promoted = self.clone();
+                    promoted.side_mut(color)[pawn_index] = kind;
+                    add(promoted);
+                }
+            }
+        }
+        successors
+    }
+}
+
+impl std::fmt::Display for Material {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for kinds in [&self.white, &self.black] {
+            f.write_str("K")?;
+            for kind in kinds {
+                write!(f, "{}", kind.to_char().to_ascii_uppercase())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Material {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidMaterial(text.to_string());
+        let (white, black) = text
+            .strip_prefix('K')
+            .and_then(|rest| rest.split_once('K'))
+            .ok_or_else(invalid)?;
+        let kinds = |side: &str| -> Result<Vec<Kind>, Error> {
+            let mut kinds = side
+                .chars()
+                .map(|c| match Kind::from(c.to_ascii_lowercase()) {
+                    Ok(Kind::King) | Err(_) => Err(invalid()),
+                    Ok(kind) if c.is_ascii_uppercase() => Ok(kind),
+                    Ok(_) => Err(invalid()),
+                })
+                .collect::<Result<Vec<Kind>, Error>>()?;
+            kinds.sort();
+            Ok(kinds)
+        };
+        Ok(Material {
+            white: kinds(white)?,
+            black: kinds(black)?,
+        })
+    }
+}
+
+/// Whether the side to move wins, draws or loses with best play.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wdl {
+    Win,
+    Draw,
+    Loss,
+}
+
+/// What a table knows about a position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Probe {
+    pub wdl: Wdl,
+    /// The plies until mate with best play, or 0 for a draw. The fifty-move rule is ignored.
+    pub dtm: u32,
+}
+
+impl Probe {
+    fn from_value(value: u8) -> Self {
+        match value {
+            UNKNOWN | INVALID => Probe {
+                wdl: Wdl::Draw,
+                dtm: 0,
+            },
+            value => {
+                let dtm = u32::from(value) - 1;
+                let wdl = match dtm % 2 {
+                    1 => Wdl::Win,
+                    _ => Wdl::Loss,
+                };
+                Probe { wdl, dtm }
+            }
+        }
+    }
+
+    /// The score a search finds for the position [`ply`] plies from its root.
+    #[must_use]
+    pub fn score(&self, ply: i32) -> i32 {
+        match self.wdl {
+            Wdl::Win => MATE_SCORE - ply - self.dtm as i32,
+            Wdl::Draw => DRAW_SCORE,
+            Wdl::Loss => -MATE_SCORE + ply + self.dtm as i32,
+        }
+    }
+}
+
+/// The pieces of [`material`] in the order of their squares in a position's
+/// number, kings first.
+fn slot_pieces(material: &Material) -> Vec<Piece> {
+    let kings = [Color::White, Color::Black].map(|color| Piece {
+        kind: Kind::King,
+        color,
+    });
+    kings.into_iter().chain(material.pieces()).collect()
+}
+
+// Every position of a material is numbered by its side to move and the square
+// of each piece, six bits each, the side to move being the highest digit.
+fn decode(mut index: usize, len: usize) -> (Color, [usize; MAX_PIECES]) {
+    let mut squares = [0; MAX_PIECES];
+    for square in squares[..len].iter_mut().rev() {
+        *square = index & 63;
+        index >>= 6;
+    }
+    let turn = match index {
+        0 => Color::White,
+        _ => Color::Black,
+    };
+    (turn, squares)
+}
+
+/// Maps [`square`] by one of the eight symmetries of the board: [`symmetry`]'s
+/// bits mirror the files, mirror the ranks and swap the ranks with the files.
+fn transform(square: usize, symmetry: usize) -> usize {
+    let (mut rank, mut file) = (square / 8, square % 8);
+    if symmetry & 4 != 0 {
+        std::mem::swap(&mut rank, &mut file);
+    }
+    if symmetry & 1 != 0 {
+        file = 7 - file;
+    }
+    if symmetry & 2 != 0 {
+        rank = 7 - rank;
+    }
+    rank * 8 + file
+}
+
+/// A distance-to-mate table of every position of a material, with the stronger
+/// side as white. As positions mirrored or rotated have the same value, only
+/// those with the white king in the a1-d1-d4 triangle are stored, or with it on
+/// the queenside if pawns tell the sides of the board apart.
+///
+/// See: [Endgame Tablebases](https://www.chessprogramming.org/Endgame_Tablebases)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Table {
+    material: Material,
+    /// The value of each stored position, as written while generating it.
+    values: Vec<u8>,
+    /// The squares the white king is stored on.
+    king_squares: Vec<usize>,
+    /// The symmetries which map any position to a stored one.
+    symmetries: usize,
+}
+
+impl Table {
+    /// The table of [`material`,] without its values yet.
+    fn layout(material: Material) -> Self {
+        let has_pawns = material.has_pawns();
+        let king_squares = (0..64)
+            .filter(|&square| {
+                let (rank, file) = (7 - square / 8, square % 8);
+                file < 4 && (has_pawns || rank <= file)
+            })
+            .collect();
+        Table {
+            symmetries: if has_pawns { 2 } else { 8 },
+            king_squares,
+            values: vec![],
+            material,
+        }
+    }
+
+    fn stored_len(&self) -> usize {
+        (2 * self.king_squares.len()) << (6 * (self.material.len() - 1))
+    }
+
+    #[must_use]
+    pub fn material(&self) -> &Material {
+        &self.material
+    }
+
+    /// The name of the table's file, e.g. `KQKR.tnb`.
+    #[must_use]
+    pub fn file_name(&self) -> String {
+        format!("{}.{EXTENSION}", self.material)
+    }
+
+    /// The number, among all positions of the material, of the stored position [`stored`.]
+    fn full_index(&self, stored: usize) -> usize {
+        let others = 6 * (self.material.len() - 1);
+        let (high, low) = (stored >> others, stored & ((1 << others) - 1));
+        let turn = high / self.king_squares.len();
+        let king = self.king_squares[high % self.king_squares.len()];
+        (turn << 6 | king) << others | low
+    }
+
+    /// The number of the stored position equal to [`turn`] to move with the pieces
+    /// on [`squares`.]
+    fn stored_index(&self, turn: Color, squares: &[usize]) -> usize {
+        let (symmetry, king_slot) = (0..self.symmetries)
+            .find_map(|symmetry| {
+                let king = transform(squares[0], symmetry);
+                let slot = self
+                    .king_squares
+                    .iter()
+                    .position(|&square| square == king)?;
+                Some((symmetry, slot))
+            })
+            .expect("Some symmetry maps the white king to a stored square");
+        let turn = usize::from(turn == Color::Black);
+        squares[1..].iter().fold(
+            turn * self.king_squares.len() + king_slot,
+            |index, &square| index << 6 | transform(square, symmetry),
+        )
+    }
+
+    /// The value of [`board`'s] position, or `None` if it doesn't have the table's
+    /// material, with either color as the stronger side.
+    #[must_use]
+    pub fn probe(&self, board: &Board) -> Option<Probe> {
+        let material = Material::of(board)?;
+        // With the colors swapped, the board is also mirrored so that pawns move the same way
+        let mirrored = match material {
+            material if material == self.material => false,
+            material if material == self.material.mirrored() => true,
+            _ => return None,
+        };
+        let slots = slot_pieces(&self.material);
+        let mut squares = [0; MAX_PIECES];
+        let mut used = [false; 64];
+        for (slot, piece) in slots.iter().enumerate() {
+            squares[slot] = (0..64).find_map(|index| {
+                let board_piece = board.squares[index]?;
+                let (square, color) = match mirrored {
+                    true => (index ^ 56, board_piece.color.opposite()),
+                    false => (index, board_piece.color),
+                };
+                let matches = board_piece.kind == piece.kind && color == piece.color;
+                (matches && !used[square]).then_some(square)
+            })?;
+            used[squares[slot]] = true;
+        }
+        let turn = match mirrored {
+            true => board.state().turn().opposite(),
+            false => board.state().turn(),
+        };
+        let index = self.stored_index(turn, &squares[..slots.len()]);
+        Some(Probe::from_value(self.values[index]))
+    }
+
+    /// The most plies any won position of the table is from mate.
+    #[must_use]
+    pub fn longest_mate(&self) -> u32 {
+        self.values
+            .iter()
+            .map(|&value| Probe::from_value(value))
+            .filter(|probe| probe.wdl == Wdl::Win)
+            .map(|probe| probe.dtm)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Generates the table of [`material`] by retrograde analysis: starting from
+    /// the checkmates, and the positions captures and promotions decide, which are
+    /// found in [`tables`,] each position a side wins in one more ply is found by
+    /// taking back a move from a position lost for the other side, and each position
+    /// lost in one more ply by all its moves having been found to lose. Positions
+    /// never found are drawn.
+    ///
+    /// See: [Retrograde Analysis](https://www.chessprogramming.org/Retrograde_Analysis)
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if [`material`] has too many pieces,
+    /// pawns on both sides or the weaker side as white, or a table its captures
+    /// and promotions lead to isn't in [`tables`.]
+    pub fn generate(material: &Material, tables: &Tablebases) -> Result<Table, Error> {
+        let both_sides_have_pawns = [Color::White, Color::Black]
+            .iter()
+            .all(|&color| material.side(color).contains(&Kind::Pawn));
+        if material.len() > MAX_PIECES || both_sides_have_pawns || *material != material.canonical()
+        {
+            return Err(Error::UnsupportedMaterial(material.to_string()));
+        }
+        if let Some(missing) = material
+            .successors()
+            .into_iter()
+            .find(|successor| tables.get(successor).is_none())
+        {
+            return Err(Error::MissingTable(missing.to_string()));
+        }
+
+        let slots = slot_pieces(material);
+        let size = 2 << (6 * slots.len());
+        let mut values = vec![UNKNOWN; size];
+        let mut remaining = vec![0; size];
+        let mut loss_plies = vec![0; size];
+
+        // The positions of each chunk are set up on their own thread
+        let threads = std::thread::available_parallelism().map_or(1, usize::from);
+        let chunk_size = size.div_ceil(threads);
+        let decided: Vec<(usize, usize)> = std::thread::scope(|scope| {
+            let chunks: Vec<_> = values
+                .chunks_mut(chunk_size)
+                .zip(remaining.chunks_mut(chunk_size))
+                .zip(loss_plies.chunks_mut(chunk_size))
+                .enumerate()
+                .map(|(chunk, ((values, remaining), loss_plies))| {
+                    let slots = &slots;
+                    scope.spawn(move || {
+                        let chunk = Chunk {
+                            start: chunk * chunk_size,
+                            values,
+                            remaining,
+                            loss_plies,
+                        };
+                        chunk.set_up(slots, tables)
+                    })
+                })
+                .collect();
+            chunks
+                .into_iter()
+                .flat_map(|chunk| chunk.join().expect("Table generation thread panicked"))
+                .collect()
+        });
+
+        // The positions decided in each number of plies, which are only final once
+        // all positions decided in fewer plies have been taken back from
+        let mut layers: Vec<Vec<u32>> = vec![];
+        let decide = |layers: &mut Vec<Vec<u32>>, index: usize, plies: usize| {
+            assert!(
+                plies <= MAX_PLIES,
+                "Mates are at most {MAX_PLIES} plies long"
+            );
+            if layers.len() <= plies {
+                layers.resize(plies + 1, vec![]);
+            }
+            layers[plies].push(index as u32);
+        };
+        for (index, plies) in decided {
+            decide(&mut layers, index, plies);
+        }
+
+        let turn_stride = 1 << (6 * slots.len());
+        let mut origins = vec![];
+        let mut plies = 0;
+        while plies < layers.len() {
+            for index in std::mem::take(&mut layers[plies]) {
+                let index = index as usize;
+                // The position was decided in fewer plies after it was added to this layer
+                if usize::from(values[index]) != plies + 1 {
+                    continue;
+                }
+                let (turn, squares) = decode(index, slots.len());
+                let mut occupied = [false; 64];
+                for &square in &squares[..slots.len()] {
+                    occupied[square] = true;
+                }
+                let previous_turn_index = match turn {
+                    Color::White => index + turn_stride,
+                    Color::Black => index - turn_stride,
+                };
+                for (slot, &piece) in slots.iter().enumerate() {
+                    if piece.color == turn {
+                        continue;
+                    }
+                    origins.clear();
+                    unmove_origins(piece, squares[slot], &occupied, &mut origins);
+                    let shift = 6 * (slots.len() - 1 - slot);
+                    for &origin in &origins {
+                        let previous =
+                            previous_turn_index - (squares[slot] << shift) + (origin << shift);
+                        let value = values[previous];
+                        if value == INVALID {
+                            continue;
+                        }
+                        if plies % 2 == 0 {
+                            // Moving into a lost position wins
+                            if value == UNKNOWN || usize::from(value) > plies + 2 {
+                                values[previous] = (plies + 2) as u8;
+                                decide(&mut layers, previous, plies + 1);
+                            }
+                        } else if value == UNKNOWN {
+                            // One more move into a won position, which loses once all moves do
+                            remaining[previous] -= 1;
+                            if remaining[previous] == 0 {
+                                let loss = (plies + 1).max(usize::from(loss_plies[previous]));
+                                values[previous] = (loss + 1) as u8;
+                                decide(&mut layers, previous, loss);
+                            }
+                        }
+                    }
+                }
+            }
+            plies += 1;
+        }
+
+        let mut table = Table::layout(material.clone());
+        table.values = (0..table.stored_len())
+            .map(|stored| values[table.full_index(stored)])
+            .collect();
+        Ok(table)
+    }
+}
+
+/// The positions one thread sets up while a table is generated, starting with
+/// the position numbered [`start`.]
+struct Chunk<'a> {
+    start: usize,
+    values: &'a mut [u8],
+    /// How many of each position's moves aren't known to lose yet.
+    remaining: &'a mut [u8],
+    /// The plies to mate after the position's slowest capture or promotion into a
+    /// position won by the other side, which loses no faster than that.
+    loss_plies: &'a mut [u8],
+}
+
+impl Chunk<'_> {
+    /// Sets up each position on a board to mark it invalid if it can't be reached,
+    /// decide it if it is checkmate, or if a capture or promotion wins or all its
+    /// moves are captures and promotions that lose, and count its other moves.
+    /// Returns the positions decided and the plies they are from mate.
+    fn set_up(self, slots: &[Piece], tables: &Tablebases) -> Vec<(usize, usize)> {
+        let mut decided = vec![];
+        let mut board = Board::new();
+        for (offset, value) in self.values.iter_mut().enumerate() {
+            let index = self.start + offset;
+            let (turn, squares) = decode(index, slots.len());
+            let mut pieces = [None; 64];
+            for (&square, &piece) in squares.iter().zip(slots) {
+                let is_pawn_rank = piece.kind == Kind::Pawn && !(8..56).contains(&square);
+                if pieces[square].is_some() || is_pawn_rank {
+                    *value = INVALID;
+                    break;
+                }
+                pieces[square] = Some(piece);
+            }
+            if *value == INVALID {
+                continue;
+            }
+            // The side that just moved can't be left in check
+            board
+                .set_position(pieces, turn.opposite())
+                .expect("Both kings are placed");
+            if board.in_check() {
+                *value = INVALID;
+                continue;
+            }
+            board
+                .set_position(pieces, turn)
+                .expect("Both kings are placed");
+
+            let moves = board.gen_legal_moves();
+            if moves.is_empty() {
+                match board.in_check() {
+                    true => {
+                        *value = 1;
+                        decided.push((index, 0));
+                    }
+                    // Stalemate is drawn, as if a move could never be refuted
+                    false => self.remaining[offset] = 1,
+                }
+                continue;
+            }
+            let mut win_plies = None;
+            let mut remaining = 0;
+            let mut loss_plies = 0;
+            for mov in &moves {
+                if !ordering::is_capture(&board, mov) && mov.promotion_kind.is_none() {
+                    remaining += 1;
+                    continue;
+                }
+                let mut after = board.clone();
+                after.make_move(mov);
+                let reply = tables
+                    .probe(&after)
+                    .expect("The tables of captures and promotions are generated first");
+                let plies = reply.dtm as usize + 1;
+                match reply.wdl {
+                    Wdl::Loss => win_plies = Some(win_plies.map_or(plies, |win| plies.min(win))),
+                    // A move into a draw is never found to lose
+                    Wdl::Draw => remaining += 1,
+                    Wdl::Win => loss_plies = loss_plies.max(plies),
+                }
+            }
+            self.remaining[offset] = remaining;
+            self.loss_plies[offset] = loss_plies as u8;
+            if let Some(plies) = win_plies {
+                *value = plies as u8 + 1;
+                decided.push((index, plies));
+            } else if remaining == 0 {
+                *value = loss_plies as u8 + 1;
+                decided.push((index, loss_plies));
+            }
+        }
+        decided
+    }
+}
+
+// A table file starts with `MAGIC`, the format version, and the material's name
+// preceded by its length, followed by the values of the stored positions in
+// runs of equal values, each a value and the length of the run as a LEB128 number.
+// Invalid positions take the value of the run they are in.
+impl Table {
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let name = self.material.to_string();
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.push(name.len() as u8);
+        bytes.extend(name.bytes());
+        let mut start = 0;
+        while start < self.values.len() {
+            let values = &self.values[start..];
+            let value = values
+                .iter()
+                .copied()
+                .find(|&value| value != INVALID)
+                .unwrap_or(UNKNOWN);
+            let len = values
+                .iter()
+                .take_while(|&&other| other == value || other == INVALID)
+                .count();
+            start += len;
+            let mut run = len as u64;
+            bytes.push(value);
+            loop {
+                let byte = (run & 0x7f) as u8;
+                run >>= 7;
+                match run {
+                    0 => {
+                        bytes.push(byte);
+                        break;
+                    }
+                    _ => bytes.push(byte | 0x80),
+                }
+            }
+        }
+        bytes
+    }
+
+    /// # Errors
+    ///
+    /// This function will return an error if [`bytes`] isn't a table file of a supported material.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let invalid = |reason: &str| Error::InvalidFile(reason.to_string());
+        let rest = bytes
+            .strip_prefix(MAGIC.as_slice())
+            .ok_or_else(|| invalid("not a table"))?;
+        let [version, name_len, rest @ ..] = rest else {
+            return Err(invalid("truncated header"));
+        };
+        if *version != VERSION {
+            return Err(invalid(&format!("unknown version {version}")));
+        }
+        let (name, mut rest) = rest
+            .split_at_checked(usize::from(*name_len))
+            .ok_or_else(|| invalid("truncated header"))?;
+        let material: Material = std::str::from_utf8(name)
+            .map_err(|_| invalid("invalid material"))?
+            .parse()?;
+        if material.len() > MAX_PIECES || material != material.canonical() {
+            return Err(Error::UnsupportedMaterial(material.to_string()));
+        }
+
+        let mut table = Table::layout(material);
+        let mut values = Vec::with_capacity(table.stored_len());
+        while let [value, tail @ ..] = rest {
+            let (mut run, mut shift) = (0_usize, 0);
+            rest = tail;
+            loop {
+                let [byte, tail @ ..] = rest else {
+                    return Err(invalid("truncated run"));
+                };
+                rest = tail;
+                run |= usize::from(byte & 0x7f) << shift;
+                shift += 7;
+                if byte & 0x80 == 0 {
+                    break;
+                }
+            }
+            if values.len() + run > table.stored_len() {
+                return Err(invalid("too many positions"));
+            }
+            values.resize(values.len() + run, *value);
+        }
+        if values.len() != table.stored_len() {
+            return Err(invalid("too few positions"));
+        }
+        table.values = values;
+        Ok(table)
+    }
+
+    /// Reads the table file at [`path`.]
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file can't be read or isn't a table.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Table::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// Writes the table to a file at [`path`.]
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file can't be written.
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        Ok(std::fs::write(path, self.to_bytes())?)
+    }
+}
+
+/// The tables of a collection of materials, which are probed by the material
+/// on the board.
+#[derive(Debug, Clone, Default)]
+pub struct Tablebases {
+    tables: HashMap<Material, Table>,
+}
+
+impl Tablebases {
+    #[must_use]
+    pub fn new() -> Self {
+        Tablebases::default()
+    }
+
+    /// Reads every table file in the directory at [`path`.]
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the directory or one of the tables can't be read.
+    pub fn read_dir(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let mut tablebases = Tablebases::new();
+        for entry in std::fs::read_dir(path)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == EXTENSION)
+            {
+                tablebases.insert(Table::read(path)?);
+            }
+        }
+        Ok(tablebases)
+    }
+
+    pub fn insert(&mut self, table: Table) {
+        self.tables.insert(table.material.clone(), table);
+    }
+
+    /// The table of [`material`,] with either color as the stronger side.
+    #[must_use]
+    pub fn get(&self, material: &Material) -> Option<&Table> {
+        self.tables.get(&material.canonical())
+    }
+
+    pub fn tables(&self) -> impl Iterator<Item = &Table> {
+        self.tables.values()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+
+    /// Generates the table of [`material`,] after the tables its captures and
+    /// promotions lead to, unless they are in the collection already. Returns
+    /// the materials of the tables generated, in the order they were.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if one of the tables can't be generated.
+    pub fn generate(&mut self, material: &Material) -> Result<Vec<Material>, Error> {
+        let material = material.canonical();
+        if self.get(&material).is_some() {
+            return Ok(vec![]);
+        }
+        if material.len() > MAX_PIECES {
+            return Err(Error::UnsupportedMaterial(material.to_string()));
+        }
+        let mut generated = vec![];
+        for successor in material.successors() {
+            generated.extend(self.generate(&successor)?);
+        }
+        self.insert(Table::generate(&material, self)?);
+        generated.push(material);
+        Ok(generated)
+    }
+
+    /// The value of [`board`'s] position if its table is in the collection.
+    /// Positions where castling is still allowed aren't in any table.
+    #[must_use]
+    pub fn probe(&self, board: &Board) -> Option<Probe> {
+        if self.tables.is_empty() {
+            return None;
+        }
+        let castling = board.state().castling_state();
+        let can_castle = [Color::White, Color::Black]
+            .iter()
+            .any(|color| castling.rights(color) != crate::castling::Rights::Neither);
+        if can_castle {
+            return None;
+        }
+        self.get(&Material::of(board)?)?.probe(board)
+    }
+
+    /// The move that wins fastest, draws or loses slowest in [`board`'s] position,
+    /// with the position's value, if its table and those its captures and
+    /// promotions lead to are in the collection.
+    #[must_use]
+    pub fn best_move(&self, board: &Board) -> Option<(Move, Probe)> {
+        let probe = self.probe(board)?;
+        let mov = board
+            .clone()
+            .gen_legal_moves()
+            .into_iter()
+            .filter_map(|mov| {
+                let mut after = board.clone();
+                after.make_move(&mov);
+                let reply = self.probe(&after)?;
+                let dtm = i64::from(reply.dtm);
+                let rank = match reply.wdl {
+                    Wdl::Loss => (0, dtm),
+                    Wdl::Draw => (1, 0),
+                    Wdl::Win => (2, -dtm),
+                };
+                Some((rank, mov))
+            })
+            .min_by_key(|&(rank, _)| rank)
+            .map(|(_, mov)| mov)?;
+        Some((mov, probe))
+    }
+}
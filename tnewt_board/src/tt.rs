@@ -0,0 +1,271 @@
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+use crate::mov::Move;
+use crate::piece::Kind;
+
+/// The size of a table in MB when none is given.
+pub const DEFAULT_SIZE_MB: usize = 16;
+
+/// How an entry's score relates to the true score of its position.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Bound {
+    /// The score is exact, as the position was searched with an open window.
+    Exact,
+    /// The true score is at least the stored score, as the search failed high.
+    Lower,
+    /// The true score is at most the stored score, as no move raised alpha.
+    Upper,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub best_move: Option<Move>,
+    pub depth: i32,
+    pub bound: Bound,
+    pub score: i32,
+    /// The search the entry was stored in, wrapping around after [`MAX_AGE`.]
+    pub age: u8,
+}
+
+const MAX_AGE: u8 = 63;
+
+// Packed entry layout, from the least significant bit:
+//   15 bits  move (6 start, 6 target, 3 promotion kind)
+//   16 bits  score
+//    8 bits  depth
+//    2 bits  bound
+//    6 bits  age
+//    1 bit   set once the entry is in use
+impl Entry {
+    fn pack(&self) -> u64 {
+        let mov = self.best_move.map_or(0, |mov| {
+            let promotion = match mov.promotion_kind {
+                None => 0,
+                Some(Kind::Queen) => 1,
+                Some(Kind::Rook) => 2,
+                Some(Kind::Bishop) => 3,
+                Some(_) => 4,
+            };
+            (mov.start_index as u64) | (mov.target_index as u64) << 6 | promotion << 12
+        });
+        let bound: u64 = match self.bound {
+            Bound::Exact => 0,
+            Bound::Lower => 1,
+            Bound::Upper => 2,
+        };
+        let score = u64::from(self.score as i16 as u16);
+        let depth = u64::from(self.depth.clamp(-128, 127) as i8 as u8);
+        mov | score << 15
+            | depth << 31
+            | bound << 39
+            | u64::from(self.age & MAX_AGE) << 41
+            | 1 << 47
+    }
+
+    fn unpack(data: u64) -> Option<Self> {
+        if data >> 47 & 1 == 0 {
+            return None;
+        }
+        let mov = (data & 0x7FFF) as usize;
+        let promotion_kind = match mov >> 12 {
+            1 => Some(Kind::Queen),
+            2 => Some(Kind::Rook),
+            3 => Some(Kind::Bishop),
+            4 => Some(Kind::Knight),
+            _ => None,
+        };
+        let best_move = match mov {
+            0 => None,
+            _ => Some(Move {
+                start_index: mov & 63,
+                target_index: mov >> 6 & 63,
+                promotion_kind,
+            }),
+        };
+        Some(Entry {
+            best_move,
+            score: i32::from((data >> 15) as u16 as i16),
+            depth: i32::from((data >> 31) as u8 as i8),
+            bound: match data >> 39 & 3 {
+                0 => Bound::Exact,
+                1 => Bound::Lower,
+                _ => Bound::Upper,
+            },
+            age: (data >> 41) as u8 & MAX_AGE,
+        })
+    }
+}
+
+/// A single slot, stored as the entry's data and the entry's hash xored with its data,
+/// so that an entry torn by concurrent writes fails to match its hash instead
+/// of being read back corrupted.
+///
+/// See: [Lockless Hashing](https://www.chessprogramming.org/Shared_Hash_Table#Lockless)
+#[derive(Debug, Default)]
+struct Slot {
+    check: AtomicU64,
+    data: AtomicU64,
+}
+
+impl Slot {
+    fn load(&self, hash: u64) -> Option<Entry> {
+        let data = self.data.load(Ordering::Relaxed);
+        if self.check.load(Ordering::Relaxed) ^ data != hash {
+            return None;
+        }
+        Entry::unpack(data)
+    }
+
+    fn load_any(&self) -> Option<(u64, Entry)> {
+        let data = self.data.load(Ordering::Relaxed);
+        let hash = self.check.load(Ordering::Relaxed) ^ data;
+        Entry::unpack(data).map(|entry| (hash, entry))
+    }
+
+    fn store(&self, hash: u64, entry: &Entry) {
+        let data = entry.pack();
+        self.check.store(hash ^ data, Ordering::Relaxed);
+        self.data.store(data, Ordering::Relaxed);
+    }
+}
+
+/// Each bucket holds an entry that is only replaced by deeper or newer searches,
+/// and an entry that is always replaced.
+#[derive(Debug, Default)]
+#[repr(align(32))]
+struct Bucket {
+    depth_preferred: Slot,
+    always_replace: Slot,
+}
+
+/// A hash table of previously searched positions, which can be shared between threads.
+///
+/// See: [Transposition Table](https://www.chessprogramming.org/Transposition_Table)
+#[derive(Debug)]
+pub struct TranspositionTable {
+    buckets: Vec<Bucket>,
+    age: AtomicU8,
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        Self::new(DEFAULT_SIZE_MB)
+    }
+}
+
+impl TranspositionTable {
+    /// Creates an empty table taking up [`size_mb`] MB, or a single bucket if that is 0.
+    #[must_use]
+    pub fn new(size_mb: usize) -> Self {
+        let num_buckets = (size_mb * 1024 * 1024 / std::mem::size_of::<Bucket>()).max(1);
+        TranspositionTable {
+            buckets: (0..num_buckets).map(|_| Bucket::default()).collect(),
+            age: AtomicU8::new(0),
+        }
+    }
+
+    /// Resizes the table to [`size_mb`] MB, clearing all entries.
+    pub fn resize(&mut self, size_mb: usize) {
+        *self = TranspositionTable::new(size_mb);
+    }
+
+    /// The size of the table in MB, rounded down.
+    #[must_use]
+    pub fn size_mb(&self) -> usize {
+        self.buckets.len() * std::mem::size_of::<Bucket>() / (1024 * 1024)
+    }
+
+    /// Removes all entries, e.g. when a new game starts.
+    pub fn clear(&self) {
+        for bucket in &self.buckets {
+            for slot in [&bucket.depth_preferred, &bucket.always_replace] {
+                slot.check.store(0, Ordering::Relaxed);
+                slot.data.store(0, Ordering::Relaxed);
+            }
+        }
+        self.age.store(0, Ordering::Relaxed);
+    }
+
+    /// Marks the start of a new search, so that entries from previous searches
+    /// are replaced before those of the current one.
+    pub fn new_search(&self) {
+        let age = self.age.load(Ordering::Relaxed);
+        self.age.store((age + 1) & MAX_AGE, Ordering::Relaxed);
+    }
+
+    fn bucket(&self, hash: u64) -> &Bucket {
+        let index = (u128::from(hash) * self.buckets.len() as u128) >> 64;
+        &self.buckets[index as usize]
+    }
+
+    /// Returns the entry stored for the position with [`hash`,] if any.
+    #[must_use]
+    pub fn probe(&self, hash: u64) -> Option<Entry> {
+        let bucket = self.bucket(hash);
+        bucket
+            .depth_preferred
+            .load(hash)
+            .or_else(|| bucket.always_replace.load(hash))
+    }
+
+    /// Stores an entry for the position with [`hash`.] The depth-preferred slot is
+    /// replaced by entries of the same position, from a newer search, or at
+    /// least as deep, and the entry goes to the always-replace slot otherwise.
+    ///
+    /// The best move of a previous entry of the same position is kept if none is given.
+    pub fn store(&self, hash: u64, best_move: Option<Move>, depth: i32, bound: Bound, score: i32) {
+        let bucket = self.bucket(hash);
+        let age = self.age.load(Ordering::Relaxed);
+        let previous = self.probe(hash);
+        let entry = Entry {
+            best_move: best_move.or(previous.and_then(|entry| entry.best_move)),
+            depth,
+            bound,
+            score,
+            age,
+        };
+        let replace_preferred = match bucket.depth_preferred.load_any() {
+            None => true,
+            Some((stored_hash, stored)) => {
+                stored_hash == hash || stored.age != age || depth >= stored.depth
+            }
+        };
+        if replace_preferred {
+            bucket.depth_preferred.store(hash, &entry);
+        } else {
+            bucket.always_replace.store(hash, &entry);
+        }
+    }
+
+    /// Hints the processor to load the bucket of [`hash`] into the cache,
+    /// so that it is ready once the position is probed.
+    pub fn prefetch(&self, hash: u64) {
+        #[cfg(target_arch = "x86_64")]
+        {
+            use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+            let bucket: *const Bucket = self.bucket(hash);
+            // SAFETY: prefetching is only a hint, and the pointer is to a valid bucket
+            unsafe { _mm_prefetch(bucket.cast::<i8>(), _MM_HINT_T0) };
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        let _ = hash;
+    }
+
+    /// How full the table is in permille, estimated from the first thousand buckets'
+    /// depth-preferred slots holding entries of the current search.
+    #[must_use]
+    pub fn hashfull(&self) -> usize {
+        let age = self.age.load(Ordering::Relaxed);
+        let sample = self.buckets.len().min(1000);
+        let used = self.buckets[..sample]
+            .iter()
+            .filter(|bucket| {
+                bucket
+                    .depth_preferred
+                    .load_any()
+                    .is_some_and(|(_, entry)| entry.age == age)
+            })
+            .count();
+        used * 1000 / sample
+    }
+}
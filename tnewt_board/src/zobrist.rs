@@ -0,0 +1,104 @@
+use crate::board::Board;
+use crate::castling::{self, Rights};
+use crate::color::Color;
+use crate::piece::Piece;
+
+/// Generates pseudo-random keys at compile time, so that hashes are the same
+/// across runs and platforms.
+///
+/// See: [SplitMix64](https://prng.di.unimi.it/splitmix64.c)
+const fn split_mix(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    (state, z ^ (z >> 31))
+}
+
+/// One key for each piece on each square, then one for each castling right,
+/// then one for each en passant file, and finally one for black to move.
+const NUM_KEYS: usize = 12 * 64 + 4 + 8 + 1;
+const CASTLING_OFFSET: usize = 12 * 64;
+const EP_OFFSET: usize = CASTLING_OFFSET + 4;
+const TURN_OFFSET: usize = EP_OFFSET + 8;
+
+const KEYS: [u64; NUM_KEYS] = {
+    let mut keys = [0; NUM_KEYS];
+    let mut state = 0x7E57_0B0A_4D00_0001;
+    let mut i = 0;
+    while i < NUM_KEYS {
+        let (next_state, key) = split_mix(state);
+        state = next_state;
+        keys[i] = key;
+        i += 1;
+    }
+    keys
+};
+
+/// The key of [`piece`] standing on [`index`.]
+#[must_use]
+pub fn piece_key(piece: Piece, index: usize) -> u64 {
+    KEYS[(piece.color as usize * 6 + piece.kind as usize) * 64 + index]
+}
+
+/// The combined key of [`color`'s] castling [`rights`.]
+#[must_use]
+pub fn castling_key(rights: Rights, color: Color) -> u64 {
+    let offset = CASTLING_OFFSET + color as usize * 2;
+    match rights {
+        Rights::Neither => 0,
+        Rights::Kingside => KEYS[offset],
+        Rights::Queenside => KEYS[offset + 1],
+        Rights::Both => KEYS[offset] ^ KEYS[offset + 1],
+    }
+}
+
+/// The key of an en passant square on [`file`,] counting from the a-file.
+#[must_use]
+pub fn ep_key(file: usize) -> u64 {
+    KEYS[EP_OFFSET + file]
+}
+
+/// The key xored in when it is black's turn.
+#[must_use]
+pub fn turn_key() -> u64 {
+    KEYS[TURN_OFFSET]
+}
+
+/// The combined key of the castling rights of both colors and the en passant square.
+#[must_use]
+pub fn state_key(castling_state: castling::State, ep_index: Option<usize>) -> u64 {
+    let ep = ep_index.map_or(0, |index| ep_key(index % 8));
+    castling_key(castling_state.white, Color::White)
+        ^ castling_key(castling_state.black, Color::Black)
+        ^ ep
+}
+
+impl Board {
+    /// Returns the Zobrist hash of the current position, which takes the pieces,
+    /// turn, castling rights and en passant square into account, but not the move counters.
+    ///
+    /// The hash is updated with each move rather than computed on demand.
+    ///
+    /// See: [Zobrist Hashing](https://www.chessprogramming.org/Zobrist_Hashing)
+    #[must_use]
+    pub fn hash(&self) -> u64 {
+        self.state.hash()
+    }
+
+    /// Computes the hash of the current position from scratch.
+    #[must_use]
+    pub fn zobrist_hash(&self) -> u64 {
+        let mut hash = 0;
+        for (index, square) in self.squares.iter().enumerate() {
+            if let Some(piece) = square {
+                hash ^= piece_key(*piece, index);
+            }
+        }
+        hash ^= state_key(self.state.castling_state(), self.state.ep_index());
+        if self.state.turn() == Color::Black {
+            hash ^= turn_key();
+        }
+        hash
+    }
+}